@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_minecraft_proxy::utils::{decode_modified_utf8, encode_modified_utf8};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(value) = decode_modified_utf8(data) {
+        assert_eq!(decode_modified_utf8(&encode_modified_utf8(&value)).unwrap(), value);
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_minecraft_proxy::nbt;
+
+// Whatever reads must write back to something that reads the same.
+fuzz_target!(|data: &[u8]| {
+    if let Ok((name, tag, compression)) = nbt::read_file(data) {
+        let _ = tag.to_string();
+        let written = nbt::write_file(&name, &tag, compression);
+        let (name_again, tag_again, compression_again) = nbt::read_file(&written).unwrap();
+        assert_eq!(compression, compression_again);
+        let (mut first, mut second) = (Vec::new(), Vec::new());
+        nbt::write_named(&mut first, &name, &tag);
+        nbt::write_named(&mut second, &name_again, &tag_again);
+        assert_eq!(first, second);
+    }
+});
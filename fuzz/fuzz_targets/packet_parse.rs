@@ -0,0 +1,66 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_minecraft_proxy::packets::{client, server, Packet};
+use rust_minecraft_proxy::utils;
+
+fn parse<P: Packet>(data: &[u8]) {
+    if let Ok(packet) = P::parse(data) {
+        packet.make_string();
+    }
+}
+
+/// Every `Packet` implementation. The seed corpus starts each input with an index into
+/// this list, so append new parsers at the end.
+const PARSERS: &[fn(&[u8])] = &[
+    parse::<client::HandshakePacket>,
+    parse::<client::LoginStart>,
+    parse::<client::SetPlayerPositionPacket>,
+    parse::<client::SetPlayerRotationPacket>,
+    parse::<client::KeepAlivePacket>,
+    parse::<client::SetPlayerPosAndRotPacket>,
+    parse::<server::SetCompressionPacket>,
+    parse::<server::LoginSuccessPacket>,
+    parse::<server::KeepAlivePacket>,
+    parse::<server::PlayerInfoPacket>,
+    parse::<server::LoginPlayPacket>,
+    parse::<server::ChunkDataPacket>,
+    parse::<server::UpdateLightPacket>,
+    parse::<server::RespawnPacket>,
+    parse::<server::UnloadChunkPacket>,
+    parse::<server::BlockUpdatePacket>,
+    parse::<server::UpdateSectionBlocksPacket>,
+    parse::<server::BlockEntityDataPacket>,
+    parse::<server::SpawnEntityPacket>,
+    parse::<server::SpawnExperienceOrbPacket>,
+    parse::<server::SpawnPlayerPacket>,
+    parse::<server::UpdateEntityPositionPacket>,
+    parse::<server::UpdateEntityPositionAndRotationPacket>,
+    parse::<server::UpdateEntityRotationPacket>,
+    parse::<server::RemoveEntitiesPacket>,
+    parse::<server::SetHeadRotationPacket>,
+    parse::<server::SetEntityMetadataPacket>,
+    parse::<server::SetEntityVelocityPacket>,
+    parse::<server::SetEquipmentPacket>,
+    parse::<server::TeleportEntityPacket>,
+    parse::<server::SynchronizePlayerPositionPacket>,
+    parse::<client::ConfirmTeleportationPacket>,
+    parse::<server::PlayerAbilitiesPacket>,
+    parse::<client::SetPlayerOnGroundPacket>,
+    parse::<client::ChatMessagePacket>,
+    parse::<client::ChatCommandPacket>,
+    parse::<server::PlayerChatPacket>,
+    parse::<server::SystemChatPacket>,
+    parse::<server::StatusResponsePacket>,
+    parse::<server::LoginDisconnectPacket>,
+    parse::<server::DisconnectPacket>,
+    parse::<server::TitleTextPacket>,
+    parse::<server::CommandsPacket>,
+];
+
+// The first byte picks the parser, the second strict mode; the rest is the packet data after the id.
+fuzz_target!(|data: &[u8]| {
+    let [selector, mode, packet @ ..] = data else { return };
+    utils::set_strict(mode & 1 != 0);
+    PARSERS[*selector as usize % PARSERS.len()](packet);
+});
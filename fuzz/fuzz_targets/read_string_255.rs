@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_minecraft_proxy::utils::{self, read_string_255};
+use std::io::Cursor;
+
+// The first byte picks strict mode; the rest is the string.
+fuzz_target!(|data: &[u8]| {
+    let Some((&mode, data)) = data.split_first() else { return };
+    utils::set_strict(mode & 1 != 0);
+    let _ = read_string_255(&mut Cursor::new(data));
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_minecraft_proxy::utils::{read_var_int, write_var_int};
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(value) = read_var_int(&mut Cursor::new(data)) {
+        let mut encoded = Vec::new();
+        write_var_int(&mut encoded, value);
+        assert_eq!(read_var_int(&mut Cursor::new(&encoded[..])).unwrap(), value);
+    }
+});
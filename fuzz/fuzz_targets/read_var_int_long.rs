@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_minecraft_proxy::utils::read_var_int_long;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((_, rest)) = read_var_int_long(data) {
+        assert!(rest.len() < data.len());
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_minecraft_proxy::text::Component;
+
+// Every component renders, and writes back to JSON that parses to the same component.
+fuzz_target!(|data: &[u8]| {
+    let Ok(json) = std::str::from_utf8(data) else { return };
+    if let Ok(component) = Component::from_json(json) {
+        let _ = (component.to_plain(), component.to_ansi(), component.to_html(), component.to_legacy());
+        assert_eq!(Component::from_json(&component.to_json()).unwrap(), component);
+    }
+    let _ = Component::from_legacy(json).to_json();
+});
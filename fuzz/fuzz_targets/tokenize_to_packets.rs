@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_minecraft_proxy::utils::{self, decode_frame, tokenize_to_packets};
+
+// The first byte picks strict mode (bit 0) and compression (bit 1); the rest is the stream.
+fuzz_target!(|data: &[u8]| {
+    let Some((&mode, stream)) = data.split_first() else { return };
+    utils::set_strict(mode & 1 != 0);
+    let compression = (mode & 2 != 0).then_some(256);
+    if let Ok((frames, consumed)) = tokenize_to_packets(stream) {
+        assert!(consumed <= stream.len());
+        for frame in frames {
+            let _ = decode_frame(frame, compression);
+        }
+    }
+});
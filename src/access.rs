@@ -0,0 +1,267 @@
+use crate::config;
+use md5::{Digest, Md5};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+use uuid::{Builder, Uuid};
+
+/// Where the access lists live and what rejected players are told.
+///
+/// The UUID a client sends in Login Start is not checked, as a client can claim any.
+/// Everything is decided before the proxy connects to the server, when the only UUID it
+/// can trust is the offline-mode one derived from the player's name, so UUID entries only
+/// count when `offline_uuids` says that is the UUID the servers use.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessConfig {
+    /// When set, only players matching an entry may log in.
+    pub allowlist: Option<String>,
+    /// Players matching an entry may not log in, even if allowlisted.
+    pub denylist: Option<String>,
+    pub not_allowed_reason: String,
+    pub denied_reason: String,
+    /// The servers run in offline mode, so UUID entries are matched against the offline
+    /// UUID of the player's name. Otherwise UUID entries are ignored.
+    pub offline_uuids: bool,
+}
+
+impl Default for AccessConfig {
+    fn default() -> Self {
+        AccessConfig {
+            allowlist: None,
+            denylist: None,
+            not_allowed_reason: "You are not on the allowlist of this server.".to_string(),
+            denied_reason: "You are banned from this server.".to_string(),
+            offline_uuids: false,
+        }
+    }
+}
+
+/// An IP address with a prefix length, e.g. `10.0.0.0/8`; a bare address matches itself.
+#[derive(Clone, Copy, Debug)]
+struct Network {
+    addr: IpAddr,
+    prefix: u32,
+}
+
+impl Network {
+    fn parse(text: &str) -> Option<Network> {
+        let (addr, prefix) = match text.split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, Some(prefix.parse::<u32>().ok()?)),
+            None => (text.parse::<IpAddr>().ok()?, None),
+        };
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(bits);
+        (prefix <= bits).then_some(Network { addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        let (network, ip, bits) = match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => (u32::from(network) as u128, u32::from(ip) as u128, 32),
+            (IpAddr::V6(network), IpAddr::V6(ip)) => (u128::from(network), u128::from(ip), 128),
+            _ => return false,
+        };
+        let shift = bits - self.prefix;
+        shift == bits || network >> shift == ip >> shift
+    }
+}
+
+/// One list file: a name, UUID or IP/CIDR per line; `#` starts a comment.
+#[derive(Debug, Default)]
+struct List {
+    names: HashSet<String>,
+    uuids: HashSet<Uuid>,
+    networks: Vec<Network>,
+}
+
+impl List {
+    fn parse(text: &str) -> List {
+        let mut list = List::default();
+        for entry in text.lines().map(|line| line.split('#').next().unwrap_or("").trim()).filter(|entry| !entry.is_empty()) {
+            if let Ok(uuid) = Uuid::parse_str(entry) {
+                list.uuids.insert(uuid);
+            } else if let Some(network) = Network::parse(entry) {
+                list.networks.push(network);
+            } else {
+                list.names.insert(entry.to_lowercase());
+            }
+        }
+        list
+    }
+
+    fn load(path: &str) -> Result<List, String> {
+        fs::read_to_string(path).map(|text| List::parse(&text)).map_err(|e| e.to_string())
+    }
+
+    fn matches(&self, name: Option<&str>, uuid: Option<Uuid>, ip: IpAddr) -> bool {
+        name.is_some_and(|name| self.names.contains(&name.to_lowercase()))
+            || uuid.is_some_and(|uuid| self.uuids.contains(&uuid))
+            || self.networks.iter().any(|network| network.contains(ip))
+    }
+
+    fn len(&self) -> usize {
+        self.names.len() + self.uuids.len() + self.networks.len()
+    }
+}
+
+/// Why a login was refused.
+pub struct Rejection {
+    /// Metric label.
+    pub kind: &'static str,
+    /// Shown to the player.
+    pub reason: String,
+}
+
+/// The allow and deny lists, reloaded whenever their files change.
+pub struct Access {
+    config: AccessConfig,
+    allowlist: RwLock<Option<List>>,
+    denylist: RwLock<Option<List>>,
+}
+
+impl Access {
+    /// Loads the configured lists and starts watching them. A list that cannot be read
+    /// at startup is a configuration error.
+    pub fn load(config: AccessConfig) -> Arc<Access> {
+        let read = |path: &Option<String>| path.as_ref().map(|path| {
+            let list = List::load(path).unwrap_or_else(|e| panic!("Cannot read access list {}: {}", path, e));
+            info!("Loaded {} entries from {}", list.len(), path);
+            warn_unused_uuids(&config, path, &list);
+            list
+        });
+        let access = Arc::new(Access {
+            allowlist: RwLock::new(read(&config.allowlist)),
+            denylist: RwLock::new(read(&config.denylist)),
+            config,
+        });
+        for (path, allow) in [(access.config.allowlist.clone(), true), (access.config.denylist.clone(), false)] {
+            let Some(path) = path else { continue };
+            let watched = access.clone();
+            config::watch(path.clone(), move || match List::load(&path) {
+                Ok(list) => {
+                    info!("Reloaded {} entries from {}", list.len(), path);
+                    warn_unused_uuids(&watched.config, &path, &list);
+                    let slot = if allow { &watched.allowlist } else { &watched.denylist };
+                    *slot.write().unwrap() = Some(list);
+                }
+                // Keep the previous list rather than opening or closing the server by accident.
+                Err(e) => warn!("Keeping the previous list, cannot read {} reason: {}", path, e),
+            });
+        }
+        access
+    }
+
+    /// Checks a login by the player's name, which may be missing, and address.
+    pub fn check(&self, name: Option<&str>, ip: IpAddr) -> Result<(), Rejection> {
+        let uuid = name.filter(|_| self.config.offline_uuids).map(offline_uuid);
+        if self.denylist.read().unwrap().as_ref().is_some_and(|list| list.matches(name, uuid, ip)) {
+            return Err(Rejection { kind: "denylist", reason: self.config.denied_reason.clone() });
+        }
+        if self.allowlist.read().unwrap().as_ref().is_some_and(|list| !list.matches(name, uuid, ip)) {
+            return Err(Rejection { kind: "not_allowlisted", reason: self.config.not_allowed_reason.clone() });
+        }
+        Ok(())
+    }
+}
+
+/// UUID entries never match without `offline_uuids`; an allowlist of online UUIDs would
+/// otherwise refuse every player, so say so where the list is read.
+fn warn_unused_uuids(config: &AccessConfig, path: &str, list: &List) {
+    if !config.offline_uuids && !list.uuids.is_empty() {
+        warn!("Ignoring the {} UUID entries in {}: the proxy cannot learn online-mode UUIDs before the server does, \
+               list names instead or set access.offline_uuids for offline-mode servers", list.uuids.len(), path);
+    }
+}
+
+/// The UUID an offline-mode server gives the player `name`: version 3, from the MD5 of
+/// `OfflinePlayer:<name>`.
+pub fn offline_uuid(name: &str) -> Uuid {
+    let hash: [u8; 16] = Md5::digest(format!("OfflinePlayer:{}", name)).into();
+    Builder::from_md5_bytes(hash).into_uuid()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(text: &str) -> Network {
+        Network::parse(text).unwrap()
+    }
+
+    #[test]
+    fn networks_match_by_prefix() {
+        let private = network("10.0.0.0/8");
+        assert!(private.contains("10.255.1.2".parse().unwrap()));
+        assert!(!private.contains("11.0.0.1".parse().unwrap()));
+        // IPv4 clients of a dual-stack listener show up as mapped IPv6 addresses.
+        assert!(private.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!private.contains("fd00::1".parse().unwrap()));
+
+        assert!(network("0.0.0.0/0").contains("203.0.113.9".parse().unwrap()));
+        let host = network("192.168.1.7");
+        assert!(host.contains("192.168.1.7".parse().unwrap()));
+        assert!(!host.contains("192.168.1.8".parse().unwrap()));
+
+        let v6 = network("2001:db8::/32");
+        assert!(v6.contains("2001:db8:ffff::1".parse().unwrap()));
+        assert!(!v6.contains("2001:db9::1".parse().unwrap()));
+        assert!(network("::/0").contains("::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn bad_prefixes_are_not_networks() {
+        assert!(Network::parse("10.0.0.0/33").is_none());
+        assert!(Network::parse("::/129").is_none());
+        assert!(Network::parse("10.0.0.0/x").is_none());
+        assert!(Network::parse("Alex").is_none());
+    }
+
+    #[test]
+    fn lists_sort_entries_by_kind() {
+        let list = List::parse("# staff\nAlex\n36532b5e-c442-3dbb-a24c-c7e55d0f979a  # Alex offline\n10.0.0.0/8\n\n");
+        assert_eq!(list.len(), 3);
+        let ip = "127.0.0.1".parse().unwrap();
+        assert!(list.matches(Some("ALEX"), None, ip));
+        assert!(list.matches(None, Some(offline_uuid("Alex")), ip));
+        assert!(list.matches(None, None, "10.1.1.1".parse().unwrap()));
+        assert!(!list.matches(Some("Bob"), Some(offline_uuid("Bob")), ip));
+    }
+
+    fn access(offline_uuids: bool, allowlist: &str) -> Access {
+        Access {
+            config: AccessConfig { offline_uuids, ..AccessConfig::default() },
+            allowlist: RwLock::new(Some(List::parse(allowlist))),
+            denylist: RwLock::new(Some(List::parse("Griefer"))),
+        }
+    }
+
+    #[test]
+    fn uuid_entries_need_offline_servers() {
+        // Notch's online UUID and Alex's offline one.
+        let allowlist = "069a79f4-44e9-4726-a5be-fca90e38aaf5\n36532b5e-c442-3dbb-a24c-c7e55d0f979a\nSteve";
+        let ip = "127.0.0.1".parse().unwrap();
+        let rejected = |access: &Access, name| access.check(Some(name), ip).err().map(|rejection| rejection.kind);
+
+        let online = access(false, allowlist);
+        assert_eq!(rejected(&online, "Notch"), Some("not_allowlisted"));
+        assert_eq!(rejected(&online, "Alex"), Some("not_allowlisted"));
+        assert_eq!(rejected(&online, "Steve"), None);
+        assert_eq!(rejected(&online, "Griefer"), Some("denylist"));
+
+        let offline = access(true, allowlist);
+        assert_eq!(rejected(&offline, "Notch"), Some("not_allowlisted"));
+        assert_eq!(rejected(&offline, "Alex"), None);
+        assert_eq!(rejected(&offline, "Steve"), None);
+        assert_eq!(offline.check(None, ip).err().map(|rejection| rejection.kind), Some("not_allowlisted"));
+    }
+
+    #[test]
+    fn offline_uuids_match_the_server() {
+        // What UUID.nameUUIDFromBytes gives an offline-mode server for "OfflinePlayer:Alex".
+        assert_eq!(offline_uuid("Alex").to_string(), "36532b5e-c442-3dbb-a24c-c7e55d0f979a");
+        assert_eq!(offline_uuid("Alex").get_version_num(), 3);
+    }
+}
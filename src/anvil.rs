@@ -0,0 +1,493 @@
+//! Anvil world files: chunk columns in `.mca` region files and a `level.dat`, as written
+//! by a 1.19.2 server.
+
+use crate::chunk::{self, Chunk, ContainerKind, LightData, BIOMES, BLOCK_STATES, SECTION_SIZE};
+use crate::nbt::{self, Compound, List, Tag};
+use crate::utils::Position;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Data version of 1.19.2 chunks and level.dat.
+pub const DATA_VERSION: i32 = 3120;
+const VERSION_NAME: &str = "1.19.2";
+/// Format version of level.dat itself, unchanged since Anvil.
+const LEVEL_VERSION: i32 = 19133;
+
+/// Region files are split into 4 KiB sectors and hold 32x32 chunks.
+const SECTOR_LEN: usize = 4096;
+const REGION_SIZE: i32 = 32;
+/// Longest chunk a region can hold; longer ones go to a `.mcc` file next to it.
+const MAX_CHUNK_SECTORS: usize = 255;
+const ZLIB: u8 = 2;
+/// Set on the compression byte when the chunk is in its own `.mcc` file.
+const EXTERNAL: u8 = 128;
+
+/// Section palettes on disk are never direct; block states still use at least 4 bits.
+const MIN_BLOCK_BITS: u8 = 4;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Names for the numeric ids the protocol uses, from the reports of the vanilla data
+/// generator (`java -DbundlerMainClass=net.minecraft.data.Main -jar server.jar --reports`).
+/// They must come from the same version as the server.
+pub struct Reports {
+    /// Palette entry, `Name` and `Properties`, of each block state by id.
+    block_states: Vec<Option<Compound>>,
+    block_entity_types: HashMap<i32, String>,
+}
+
+impl Reports {
+    /// Reads `blocks.json` and `registries.json` from the reports directory.
+    pub fn load(dir: &Path) -> io::Result<Reports> {
+        let read = |name: &str| fs::read_to_string(dir.join(name))
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", dir.join(name).display(), e)));
+        Reports::parse(&read("blocks.json")?, &read("registries.json")?)
+    }
+
+    pub fn parse(blocks: &str, registries: &str) -> io::Result<Reports> {
+        let blocks: Value = serde_json::from_str(blocks).map_err(|e| invalid(format!("blocks.json: {}", e)))?;
+        let registries: Value = serde_json::from_str(registries).map_err(|e| invalid(format!("registries.json: {}", e)))?;
+        let mut block_states = Vec::new();
+        for (name, block) in blocks.as_object().ok_or_else(|| invalid("blocks.json: not an object".to_string()))? {
+            for state in block["states"].as_array().map_or(&[][..], |states| &states[..]) {
+                let id = state["id"].as_u64().ok_or_else(|| invalid(format!("blocks.json: state of {} without id", name)))? as usize;
+                let mut entry = Compound::default();
+                entry.insert("Name", Tag::String(name.clone()));
+                if let Some(properties) = state["properties"].as_object() {
+                    let properties = properties.iter()
+                        .map(|(key, value)| (key.clone(), Tag::String(value.as_str().unwrap_or_default().to_string())))
+                        .collect();
+                    entry.insert("Properties", Tag::Compound(Compound(properties)));
+                }
+                if block_states.len() <= id {
+                    block_states.resize(id + 1, None);
+                }
+                block_states[id] = Some(entry);
+            }
+        }
+        let block_entity_types = registries["minecraft:block_entity_type"]["entries"].as_object()
+            .ok_or_else(|| invalid("registries.json: no minecraft:block_entity_type".to_string()))?
+            .iter()
+            .filter_map(|(name, entry)| Some((i32::try_from(entry["protocol_id"].as_u64()?).ok()?, name.clone())))
+            .collect();
+        Ok(Reports { block_states, block_entity_types })
+    }
+
+    /// `Name` and `Properties` of a block state.
+    pub fn block_state(&self, id: i32) -> Option<&Compound> {
+        self.block_states.get(usize::try_from(id).ok()?)?.as_ref()
+    }
+
+    pub fn block_name(&self, id: i32) -> Option<&str> {
+        match self.block_state(id)?.get("Name") {
+            Some(Tag::String(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn block_entity_type(&self, id: i32) -> Option<&str> {
+        self.block_entity_types.get(&id).map(String::as_str)
+    }
+}
+
+/// Ids that had no name while converting; they are written as air, the default biome
+/// or left out.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Unknown {
+    pub block_states: usize,
+    pub biomes: usize,
+    pub block_entities: usize,
+}
+
+/// Converts mirrored chunk columns of one dimension to their on-disk form.
+pub struct ChunkConverter<'a> {
+    pub reports: &'a Reports,
+    /// Biome names by protocol id, from the `minecraft:worldgen/biome` registry sent at login.
+    pub biomes: &'a [String],
+    /// Lowest block of the dimension.
+    pub min_y: i32,
+    pub unknown: Unknown,
+}
+
+impl ChunkConverter<'_> {
+    /// The column as a region file stores it; its light is marked as correct when it has any.
+    pub fn convert(&mut self, chunk: &Chunk) -> Compound {
+        let min_section = self.min_y.div_euclid(SECTION_SIZE as i32);
+        let sections = chunk.sections.iter().enumerate().map(|(i, section)| {
+            let mut nbt = Compound::default();
+            nbt.insert("Y", Tag::Byte((min_section + i as i32) as i8));
+            nbt.insert("block_states", Tag::Compound(self.block_states(&section.block_states.entries(BLOCK_STATES))));
+            nbt.insert("biomes", Tag::Compound(self.biomes(&section.biomes.entries(BIOMES))));
+            // Light bit 0 is the section below the world.
+            if let Some(light) = light_array(&chunk.light.block_light_mask, &chunk.light.block_light, i + 1) {
+                nbt.insert("BlockLight", Tag::ByteArray(light));
+            }
+            if let Some(light) = light_array(&chunk.light.sky_light_mask, &chunk.light.sky_light, i + 1) {
+                nbt.insert("SkyLight", Tag::ByteArray(light));
+            }
+            Tag::Compound(nbt)
+        }).collect();
+        let block_entities = chunk.block_entities.iter().filter_map(|entity| {
+            let Some(name) = self.reports.block_entity_type(entity.kind) else {
+                self.unknown.block_entities += 1;
+                return None;
+            };
+            let mut nbt = Compound::default();
+            nbt.insert("id", Tag::String(name.to_string()));
+            nbt.insert("x", Tag::Int(chunk.x * SECTION_SIZE as i32 + entity.x as i32));
+            nbt.insert("y", Tag::Int(entity.y as i32));
+            nbt.insert("z", Tag::Int(chunk.z * SECTION_SIZE as i32 + entity.z as i32));
+            nbt.insert("keepPacked", Tag::Byte(0));
+            if let Some(Tag::Compound(data)) = &entity.data {
+                for (key, value) in &data.0 {
+                    nbt.insert(key.clone(), value.clone());
+                }
+            }
+            Some(Tag::Compound(nbt))
+        }).collect();
+
+        let mut nbt = Compound::default();
+        nbt.insert("DataVersion", Tag::Int(DATA_VERSION));
+        nbt.insert("xPos", Tag::Int(chunk.x));
+        nbt.insert("yPos", Tag::Int(min_section));
+        nbt.insert("zPos", Tag::Int(chunk.z));
+        nbt.insert("Status", Tag::String("full".to_string()));
+        nbt.insert("LastUpdate", Tag::Long(0));
+        nbt.insert("InhabitedTime", Tag::Long(0));
+        nbt.insert("isLightOn", Tag::Byte(has_light(&chunk.light) as i8));
+        nbt.insert("sections", Tag::List(List { id: nbt::COMPOUND, items: sections }));
+        nbt.insert("block_entities", Tag::List(List { id: nbt::COMPOUND, items: block_entities }));
+        // The missing heightmaps are computed when the chunk is loaded.
+        nbt.insert("Heightmaps", Tag::Compound(chunk.heightmaps.clone()));
+        nbt
+    }
+
+    fn block_states(&mut self, entries: &[i32]) -> Compound {
+        let air = || {
+            let mut air = Compound::default();
+            air.insert("Name", Tag::String("minecraft:air".to_string()));
+            air
+        };
+        let unknown = &mut self.unknown.block_states;
+        let reports = self.reports;
+        container(entries, BLOCK_STATES, MIN_BLOCK_BITS, |id| {
+            Tag::Compound(reports.block_state(id).cloned().unwrap_or_else(|| {
+                *unknown += 1;
+                air()
+            }))
+        })
+    }
+
+    fn biomes(&mut self, entries: &[i32]) -> Compound {
+        let unknown = &mut self.unknown.biomes;
+        let biomes = self.biomes;
+        container(entries, BIOMES, 0, |id| {
+            Tag::String(usize::try_from(id).ok().and_then(|id| biomes.get(id)).filter(|name| !name.is_empty()).cloned().unwrap_or_else(|| {
+                *unknown += 1;
+                "minecraft:plains".to_string()
+            }))
+        })
+    }
+}
+
+/// A paletted container as stored on disk: `palette` with the named entries and, unless
+/// there is only one, `data` with indices of at least `min_bits` bits.
+fn container(entries: &[i32], kind: ContainerKind, min_bits: u8, mut name: impl FnMut(i32) -> Tag) -> Compound {
+    // Two ids may get the same entry, e.g. unknown ones, and a palette must not repeat itself.
+    let mut indices_by_id: HashMap<i32, u64> = HashMap::new();
+    let mut palette: Vec<Tag> = Vec::new();
+    let indices: Vec<u64> = entries.iter().take(kind.entries).map(|id| {
+        *indices_by_id.entry(*id).or_insert_with(|| {
+            let entry = name(*id);
+            palette.iter().position(|known| *known == entry).unwrap_or_else(|| {
+                palette.push(entry);
+                palette.len() - 1
+            }) as u64
+        })
+    }).collect();
+    let mut nbt = Compound::default();
+    let len = palette.len();
+    let palette_id = palette.first().map_or(nbt::END, Tag::id);
+    nbt.insert("palette", Tag::List(List { id: palette_id, items: palette }));
+    if len > 1 {
+        let bits = ((usize::BITS - (len - 1).leading_zeros()) as u8).max(min_bits);
+        nbt.insert("data", Tag::LongArray(chunk::pack(&indices, bits).into_iter().map(|long| long as i64).collect()));
+    }
+    nbt
+}
+
+fn has_light(light: &LightData) -> bool {
+    !light.sky_light.is_empty() || !light.block_light.is_empty()
+}
+
+/// The array for light section `bit`, when the mask has it set.
+fn light_array(mask: &[i64], arrays: &[Vec<u8>], bit: usize) -> Option<Vec<i8>> {
+    let set = |bit: usize| mask.get(bit / 64).is_some_and(|long| long >> (bit % 64) & 1 == 1);
+    if !set(bit) {
+        return None;
+    }
+    let index = (0..bit).filter(|below| set(*below)).count();
+    arrays.get(index).map(|array| array.iter().map(|byte| *byte as i8).collect())
+}
+
+/// Directory a dimension's files go to within a world: the world itself for the overworld,
+/// `DIM-1` and `DIM1` for the nether and the end, `dimensions/<namespace>/<path>` otherwise.
+pub fn dimension_dir(world: &Path, dimension: &str) -> PathBuf {
+    match dimension {
+        "minecraft:overworld" => world.to_path_buf(),
+        "minecraft:the_nether" => world.join("DIM-1"),
+        "minecraft:the_end" => world.join("DIM1"),
+        _ => {
+            let (namespace, path) = dimension.split_once(':').unwrap_or(("minecraft", dimension));
+            let mut dir = world.join("dimensions").join(namespace);
+            // Identifiers may contain `/`, but must not leave the world.
+            for segment in path.split('/').filter(|segment| !matches!(*segment, "" | "." | "..")) {
+                dir.push(segment);
+            }
+            dir
+        }
+    }
+}
+
+/// Writes converted chunks, keyed by chunk x and z, into `r.<x>.<z>.mca` files in `dir`,
+/// replacing existing ones. Returns the number of region files written.
+pub fn write_regions(dir: &Path, chunks: impl IntoIterator<Item = ((i32, i32), Compound)>) -> io::Result<usize> {
+    let mut regions: BTreeMap<(i32, i32), Vec<_>> = BTreeMap::new();
+    for ((x, z), nbt) in chunks {
+        regions.entry((x.div_euclid(REGION_SIZE), z.div_euclid(REGION_SIZE))).or_default().push(((x, z), nbt));
+    }
+    fs::create_dir_all(dir)?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs() as u32);
+    for ((region_x, region_z), chunks) in &regions {
+        let mut header = vec![0; 2 * SECTOR_LEN];
+        let mut body = Vec::new();
+        for ((x, z), nbt) in chunks {
+            let data = nbt::write_file("", &Tag::Compound(nbt.clone()), nbt::Compression::Zlib);
+            let mut payload = Vec::with_capacity(data.len() + 5);
+            if data.len() + 5 > MAX_CHUNK_SECTORS * SECTOR_LEN {
+                fs::write(dir.join(format!("c.{}.{}.mcc", x, z)), &data)?;
+                payload.extend_from_slice(&1u32.to_be_bytes());
+                payload.push(ZLIB | EXTERNAL);
+            } else {
+                payload.extend_from_slice(&(data.len() as u32 + 1).to_be_bytes());
+                payload.push(ZLIB);
+                payload.extend_from_slice(&data);
+            }
+            let sectors = payload.len().div_ceil(SECTOR_LEN);
+            payload.resize(sectors * SECTOR_LEN, 0);
+            let offset = 2 + body.len() / SECTOR_LEN;
+            let index = 4 * (x.rem_euclid(REGION_SIZE) + z.rem_euclid(REGION_SIZE) * REGION_SIZE) as usize;
+            header[index..index + 4].copy_from_slice(&((offset as u32) << 8 | sectors as u32).to_be_bytes());
+            header[SECTOR_LEN + index..SECTOR_LEN + index + 4].copy_from_slice(&timestamp.to_be_bytes());
+            body.extend_from_slice(&payload);
+        }
+        header.extend_from_slice(&body);
+        fs::write(dir.join(format!("r.{}.{}.mca", region_x, region_z)), header)?;
+    }
+    Ok(regions.len())
+}
+
+/// What goes into level.dat besides the fixed settings.
+pub struct Level {
+    pub name: String,
+    pub spawn: Position,
+    /// 0 survival, 1 creative, 2 adventure, 3 spectator.
+    pub game_type: i32,
+}
+
+/// A gzipped level.dat for a world of void superflat dimensions, so that only the
+/// exported chunks have anything in them. Time and weather are frozen at noon.
+pub fn level_dat(level: &Level) -> Vec<u8> {
+    let string = |value: &str| Tag::String(value.to_string());
+    let compound = |entries: Vec<(&str, Tag)>| Tag::Compound(Compound(entries.into_iter().map(|(key, tag)| (key.to_string(), tag)).collect()));
+    let list = |id: u8, items: Vec<Tag>| Tag::List(List { id, items });
+
+    let void = compound(vec![
+        ("layers", list(nbt::COMPOUND, vec![compound(vec![("block", string("minecraft:air")), ("height", Tag::Int(1))])])),
+        ("biome", string("minecraft:the_void")),
+        ("lakes", Tag::Byte(0)),
+        ("features", Tag::Byte(0)),
+    ]);
+    let dimension = |dimension_type: &str| compound(vec![
+        ("type", string(dimension_type)),
+        ("generator", compound(vec![("type", string("minecraft:flat")), ("settings", void.clone())])),
+    ]);
+    let last_played = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as i64);
+    let data = compound(vec![
+        ("DataVersion", Tag::Int(DATA_VERSION)),
+        ("version", Tag::Int(LEVEL_VERSION)),
+        ("Version", compound(vec![
+            ("Id", Tag::Int(DATA_VERSION)),
+            ("Name", string(VERSION_NAME)),
+            ("Series", string("main")),
+            ("Snapshot", Tag::Byte(0)),
+        ])),
+        ("LevelName", string(&level.name)),
+        ("GameType", Tag::Int(level.game_type)),
+        ("hardcore", Tag::Byte(0)),
+        ("allowCommands", Tag::Byte(1)),
+        ("initialized", Tag::Byte(1)),
+        ("LastPlayed", Tag::Long(last_played)),
+        ("Time", Tag::Long(0)),
+        ("DayTime", Tag::Long(6000)),
+        ("SpawnX", Tag::Int(level.spawn.x)),
+        ("SpawnY", Tag::Int(level.spawn.y)),
+        ("SpawnZ", Tag::Int(level.spawn.z)),
+        ("SpawnAngle", Tag::Float(0.0)),
+        ("raining", Tag::Byte(0)),
+        ("thundering", Tag::Byte(0)),
+        ("GameRules", compound(vec![
+            ("doDaylightCycle", string("false")),
+            ("doWeatherCycle", string("false")),
+            ("doMobSpawning", string("false")),
+        ])),
+        ("DataPacks", compound(vec![
+            ("Enabled", list(nbt::STRING, vec![string("vanilla")])),
+            ("Disabled", list(nbt::END, Vec::new())),
+        ])),
+        ("WorldGenSettings", compound(vec![
+            ("seed", Tag::Long(0)),
+            ("generate_features", Tag::Byte(0)),
+            ("bonus_chest", Tag::Byte(0)),
+            ("dimensions", compound(vec![
+                ("minecraft:overworld", dimension("minecraft:overworld")),
+                ("minecraft:the_nether", dimension("minecraft:the_nether")),
+                ("minecraft:the_end", dimension("minecraft:the_end")),
+            ])),
+        ])),
+    ]);
+    nbt::write_file("", &compound(vec![("Data", data)]), nbt::Compression::Gzip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{BlockEntity, ChunkSection, PalettedContainer};
+
+    const BLOCKS: &str = r#"{
+        "minecraft:air": {"states": [{"id": 0, "default": true}]},
+        "minecraft:oak_log": {"properties": {"axis": ["x", "y", "z"]}, "states": [
+            {"id": 1, "properties": {"axis": "x"}},
+            {"id": 2, "properties": {"axis": "y"}, "default": true},
+            {"id": 3, "properties": {"axis": "z"}}
+        ]}
+    }"#;
+    const REGISTRIES: &str = r#"{"minecraft:block_entity_type": {"entries": {"minecraft:chest": {"protocol_id": 1}}}}"#;
+
+    fn name(tag: &Tag) -> &str {
+        match tag.as_compound().and_then(|entry| entry.get("Name")) {
+            Some(Tag::String(name)) => name,
+            _ => panic!("no name in {}", tag),
+        }
+    }
+
+    #[test]
+    fn light_arrays_follow_their_mask() {
+        let arrays = vec![vec![1; 2048], vec![2; 2048], vec![3; 2048]];
+        let mask = [0b1010, 1];
+        assert_eq!(light_array(&mask, &arrays, 0), None);
+        assert_eq!(light_array(&mask, &arrays, 1).unwrap()[0], 1);
+        assert_eq!(light_array(&mask, &arrays, 2), None);
+        assert_eq!(light_array(&mask, &arrays, 3).unwrap()[0], 2);
+        assert_eq!(light_array(&mask, &arrays, 64).unwrap()[0], 3);
+        assert_eq!(light_array(&mask, &arrays, 65), None);
+        // A mask claiming more arrays than were sent.
+        assert_eq!(light_array(&[0b1111], &arrays, 3), None);
+    }
+
+    #[test]
+    fn containers_name_their_palette() {
+        let single = container(&[5; 64], BIOMES, 0, |_| Tag::String("minecraft:plains".to_string()));
+        assert_eq!(single.get("palette"), Some(&Tag::List(List { id: nbt::STRING, items: vec![Tag::String("minecraft:plains".to_string())] })));
+        assert!(single.get("data").is_none());
+
+        // Unknown ids 7 and 8 both become air, which appears in the palette once.
+        let entries: Vec<i32> = (0..4096).map(|index| [0, 7, 8, 2][index % 4]).collect();
+        let reports = Reports::parse(BLOCKS, REGISTRIES).unwrap();
+        let blocks = container(&entries, BLOCK_STATES, MIN_BLOCK_BITS, |id| {
+            Tag::Compound(reports.block_state(id).cloned().unwrap_or_else(|| reports.block_state(0).unwrap().clone()))
+        });
+        let Some(Tag::List(palette)) = blocks.get("palette") else { panic!("no palette") };
+        assert_eq!(palette.items.iter().map(name).collect::<Vec<_>>(), ["minecraft:air", "minecraft:oak_log"]);
+        let Some(Tag::LongArray(data)) = blocks.get("data") else { panic!("no data") };
+        // Two entries still take the 4 bits block states need at least.
+        assert_eq!(data.len(), 4096 / 16);
+        assert_eq!(data[0], 0x1000_1000_1000_1000);
+    }
+
+    #[test]
+    fn chunks_convert_with_names_from_the_reports() {
+        let reports = Reports::parse(BLOCKS, REGISTRIES).unwrap();
+        assert_eq!(reports.block_name(2), Some("minecraft:oak_log"));
+        let biomes = ["minecraft:plains".to_string(), "minecraft:desert".to_string()];
+        let mut converter = ChunkConverter { reports: &reports, biomes: &biomes, min_y: -64, unknown: Unknown::default() };
+        let section = ChunkSection { block_count: 4096, block_states: PalettedContainer::single(99), biomes: PalettedContainer::single(1) };
+        let chunk = Chunk {
+            x: 1,
+            z: -1,
+            heightmaps: Compound::default(),
+            sections: vec![section],
+            block_entities: vec![
+                BlockEntity { x: 2, z: 3, y: -60, kind: 1, data: None },
+                BlockEntity { x: 0, z: 0, y: 0, kind: 50, data: None },
+            ],
+            light: LightData::default(),
+        };
+        let nbt = converter.convert(&chunk);
+        assert_eq!((converter.unknown.block_states, converter.unknown.biomes, converter.unknown.block_entities), (1, 0, 1));
+        assert_eq!(nbt.get("yPos"), Some(&Tag::Int(-4)));
+        assert_eq!(nbt.get("isLightOn"), Some(&Tag::Byte(0)));
+        let Some(Tag::List(sections)) = nbt.get("sections") else { panic!("no sections") };
+        let section = sections.items[0].as_compound().unwrap();
+        assert_eq!(section.get("Y"), Some(&Tag::Byte(-4)));
+        let Some(Tag::Compound(biomes)) = section.get("biomes") else { panic!("no biomes") };
+        assert_eq!(biomes.get("palette"), Some(&Tag::List(List { id: nbt::STRING, items: vec![Tag::String("minecraft:desert".to_string())] })));
+        let Some(Tag::List(block_entities)) = nbt.get("block_entities") else { panic!("no block entities") };
+        let chest = block_entities.items[0].as_compound().unwrap();
+        assert_eq!((chest.get("x"), chest.get("z")), (Some(&Tag::Int(18)), Some(&Tag::Int(-13))));
+    }
+
+    #[test]
+    fn dimensions_stay_inside_the_world() {
+        let world = Path::new("world");
+        assert_eq!(dimension_dir(world, "minecraft:overworld"), world);
+        assert_eq!(dimension_dir(world, "minecraft:the_nether"), world.join("DIM-1"));
+        assert_eq!(dimension_dir(world, "mod:../../etc/sky"), world.join("dimensions/mod/etc/sky"));
+    }
+
+    #[test]
+    fn regions_hold_their_chunks() {
+        let dir = std::env::temp_dir().join(format!("anvil-test-{}", std::process::id()));
+        let mut nbt = Compound::default();
+        nbt.insert("xPos", Tag::Int(-1));
+        assert_eq!(write_regions(&dir, [((-1, 33), nbt.clone())]).unwrap(), 1);
+        let region = fs::read(dir.join("r.-1.1.mca")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let index = 4 * (31 + 32);
+        let location = u32::from_be_bytes(region[index..index + 4].try_into().unwrap());
+        let (offset, sectors) = ((location >> 8) as usize * SECTOR_LEN, (location & 0xff) as usize);
+        assert_eq!((offset, sectors), (2 * SECTOR_LEN, 1));
+        assert_eq!(region.len(), 3 * SECTOR_LEN);
+        let len = u32::from_be_bytes(region[offset..offset + 4].try_into().unwrap()) as usize;
+        assert_eq!(region[offset + 4], ZLIB);
+        let (_, tag, compression) = nbt::read_file(&region[offset + 5..offset + 4 + len]).unwrap();
+        assert_eq!((tag, compression), (Tag::Compound(nbt), nbt::Compression::Zlib));
+    }
+
+    #[test]
+    fn level_dat_is_gzipped_nbt() {
+        let level = level_dat(&Level { name: "Export".to_string(), spawn: Position { x: 8, y: 64, z: 8 }, game_type: 1 });
+        let (_, tag, compression) = nbt::read_file(&level).unwrap();
+        assert_eq!(compression, nbt::Compression::Gzip);
+        let data = tag.as_compound().and_then(|root| root.get("Data")).and_then(Tag::as_compound).unwrap();
+        assert_eq!(data.get("LevelName"), Some(&Tag::String("Export".to_string())));
+        assert_eq!(data.get("SpawnY"), Some(&Tag::Int(64)));
+    }
+}
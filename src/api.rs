@@ -0,0 +1,477 @@
+use crate::config::Routing;
+use crate::entity_tracker::{Entity, EntityKind};
+use crate::metadata::{MetadataValue, EQUIPMENT_SLOTS};
+use crate::events::EVENTS;
+use crate::impair::Impairment;
+use crate::session::{Session, Sessions};
+use crate::utils::{Direction, Position, State};
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{info, warn};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+/// Browser packet viewer, served on `/`; it reads `/sessions` and `/events/ws`.
+const INSPECTOR: &str = include_str!("../static/inspector.html");
+
+/// Events returned by `GET /events` when no `limit` is given.
+const DEFAULT_EVENT_LIMIT: usize = 100;
+
+/// An event stream sends a comment this often when idle, which also notices gone clients.
+#[cfg(not(test))]
+const STREAM_KEEP_ALIVE: Duration = Duration::from_secs(15);
+#[cfg(test)]
+const STREAM_KEEP_ALIVE: Duration = Duration::from_millis(100);
+
+/// Serves the management API on `addr` from a background thread, one thread per request.
+///
+/// - `GET /sessions`, `GET /sessions/<id>`, `GET /sessions/<id>/stats`
+/// - `GET /sessions/<id>/world`, `GET /sessions/<id>/block?x=<x>&y=<y>&z=<z>`
+/// - `GET /sessions/<id>/entities`, `GET /sessions/<id>/entities/<entity id>`
+/// - `GET /sessions/<id>/movement`, `GET /sessions/<id>/path?format=json|csv`
+/// - `POST /sessions/<id>/kick` with `{"message": ...}` (optional)
+/// - `GET /sessions/<id>/impairment`, `PUT /sessions/<id>/impairment` with the `[impairment]` keys as JSON
+/// - `POST /broadcast` with `{"message": ...}`
+/// - `GET /routing`, `PUT /routing` with `{"upstream": "host:port"}`
+/// - `GET /events?session=<id>&limit=<n>`, recent packet events
+/// - `GET /events/stream?session=<id>`, decoded packets as server-sent events
+/// - `GET /events/ws?session=<id>`, the same as a WebSocket of JSON text messages
+/// - `GET /`, the bundled packet inspector page
+pub fn serve(addr: &str, sessions: Arc<Sessions>, routing: Arc<RwLock<Routing>>) {
+    let server = match Server::http(addr) {
+        Ok(server) => server,
+        Err(e) => {
+            warn!("Cannot bind API to {} reason: {}", addr, e);
+            return;
+        }
+    };
+    info!("Serving API on http://{}", addr);
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let sessions = sessions.clone();
+            let routing = routing.clone();
+            thread::spawn(move || handle(request, &sessions, &routing));
+        }
+    });
+}
+
+fn handle(mut request: Request, sessions: &Sessions, routing: &RwLock<Routing>) {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+    let session_param = match query_param(query, "session").map(str::parse::<u64>).transpose() {
+        Ok(session) => session,
+        Err(_) => return respond(request, error(400, "session must be a session id")),
+    };
+
+    let response = match (request.method(), segments.as_slice()) {
+        (Method::Get, ["sessions"]) => {
+            Ok(Value::Array(sessions.list().iter().map(|session| session_json(session)).collect()))
+        }
+        (Method::Get, ["sessions", id]) => find(sessions, id).map(|session| session_json(&session)),
+        (Method::Get, ["sessions", id, "stats"]) => find(sessions, id).map(|session| stats_json(&session)),
+        (Method::Get, ["sessions", id, "world"]) => find(sessions, id).map(|session| world_json(&session)),
+        (Method::Get, ["sessions", id, "block"]) => find(sessions, id).and_then(|session| {
+            let coord = |name| query_param(query, name).and_then(|value| value.parse().ok())
+                .ok_or((400, format!("{} must be a block coordinate", name)));
+            let position = Position { x: coord("x")?, y: coord("y")?, z: coord("z")? };
+            Ok(block_json(&session, position))
+        }),
+        (Method::Get, ["sessions", id, "entities"]) => find(sessions, id).map(|session| entities_json(&session)),
+        (Method::Get, ["sessions", id, "entities", entity_id]) => find(sessions, id).and_then(|session| {
+            let entity_id: i32 = entity_id.parse().map_err(|_| (400, "entity id must be a number".to_string()))?;
+            let entities = session.entities();
+            let entity = entities.get(entity_id).ok_or((404, format!("no entity {}", entity_id)))?;
+            Ok(entity_json(entity))
+        }),
+        (Method::Get, ["sessions", id, "movement"]) => find(sessions, id).map(|session| movement_json(&session)),
+        (Method::Get, ["sessions", id, "path"]) => match (find(sessions, id), query_param(query, "format")) {
+            (Ok(session), Some("csv")) => {
+                let content_type = Header::from_bytes("Content-Type", "text/csv").unwrap();
+                let _ = request.respond(Response::from_string(session.movement().path_csv()).with_header(content_type));
+                return;
+            }
+            (session, None | Some("json")) => session.map(|session| json!(session.movement().path().collect::<Vec<_>>())),
+            (_, Some(_)) => Err((400, "format must be json or csv".to_string())),
+        },
+        (Method::Post, ["sessions", id, "kick"]) => find(sessions, id).and_then(|session| {
+            let body = read_json(&mut request)?;
+            let message = body.get("message").and_then(Value::as_str).unwrap_or("Kicked by the proxy");
+            session.kick(message).map_err(|e| (409, e.to_string()))?;
+            info!("API: kicked session {}", session.id);
+            Ok(json!({ "kicked": session.id }))
+        }),
+        (Method::Get, ["sessions", id, "impairment"]) => find(sessions, id).map(|session| json!(session.impairment())),
+        (Method::Put, ["sessions", id, "impairment"]) => find(sessions, id).and_then(|session| {
+            let impairment: Impairment = serde_json::from_value(read_json(&mut request)?).map_err(|e| (400, e.to_string()))?;
+            info!("API: impairment of session {} now {}", session.id, impairment);
+            session.set_impairment(impairment);
+            Ok(json!(impairment))
+        }),
+        (Method::Post, ["broadcast"]) => read_json(&mut request).and_then(|body| {
+            let message = body.get("message").and_then(Value::as_str).ok_or((400, "message is required".to_string()))?;
+            let sent = sessions.list().iter().filter(|session| session.send_chat(message).is_ok()).count();
+            Ok(json!({ "sent": sent }))
+        }),
+        (Method::Get, ["routing"]) => Ok(json!(*routing.read().unwrap())),
+        (Method::Put, ["routing"]) => read_json(&mut request).and_then(|body| {
+            let new: Routing = serde_json::from_value(body).map_err(|e| (400, e.to_string()))?;
+            info!("API: new sessions now go to {}", new.upstream);
+            *routing.write().unwrap() = new.clone();
+            Ok(json!(new))
+        }),
+        (Method::Get, ["events"]) => {
+            let limit = query_param(query, "limit").and_then(|limit| limit.parse().ok()).unwrap_or(DEFAULT_EVENT_LIMIT);
+            EVENTS.keep_recent();
+            Ok(Value::Array(EVENTS.recent(session_param, limit).iter().map(|event| json!(**event)).collect()))
+        }
+        (Method::Get, ["events", "stream"]) => return stream_events(request, session_param),
+        (Method::Get, ["events", "ws"]) => return stream_websocket(request, session_param),
+        (Method::Get, [] | ["inspector"]) => {
+            let content_type = Header::from_bytes("Content-Type", "text/html; charset=utf-8").unwrap();
+            let _ = request.respond(Response::from_string(INSPECTOR).with_header(content_type));
+            return;
+        }
+        _ => Err((404, "not found".to_string())),
+    };
+    match response {
+        Ok(value) => respond(request, (200, value)),
+        Err((status, message)) => respond(request, error(status, &message)),
+    }
+}
+
+type ApiResult = Result<Value, (u16, String)>;
+
+fn error(status: u16, message: &str) -> (u16, Value) {
+    (status, json!({ "error": message }))
+}
+
+fn respond(request: Request, (status, value): (u16, Value)) {
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let response = Response::from_string(value.to_string()).with_status_code(status).with_header(content_type);
+    let _ = request.respond(response);
+}
+
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query.split('&').find_map(|pair| pair.split_once('=').filter(|(key, _)| *key == name).map(|(_, value)| value))
+}
+
+fn find(sessions: &Sessions, id: &str) -> Result<Arc<Session>, (u16, String)> {
+    sessions.find(id).ok_or((404, format!("no session {}", id)))
+}
+
+/// The request body as JSON; an empty body reads as `{}`.
+fn read_json(request: &mut Request) -> ApiResult {
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body).map_err(|e| (400, e.to_string()))?;
+    if body.trim().is_empty() {
+        return Ok(json!({}));
+    }
+    serde_json::from_str(&body).map_err(|e| (400, format!("invalid JSON: {}", e)))
+}
+
+fn session_json(session: &Session) -> Value {
+    let (player, uuid) = session.player().unwrap_or_default();
+    json!({
+        "id": session.id,
+        "player": player,
+        "uuid": uuid.map(|uuid| uuid.to_string()),
+        "state": format!("{:?}", session.state()),
+        "address": session.client_addr.to_string(),
+        "duration": session.stats().elapsed().as_secs_f64(),
+        "decoding": session.decoding(),
+        "inspecting": session.inspecting(),
+    })
+}
+
+fn stats_json(session: &Session) -> Value {
+    let stats = session.stats();
+    let ms = |duration: Option<Duration>| duration.map(|duration| duration.as_secs_f64() * 1000.0);
+    let states: serde_json::Map<String, Value> = [State::Handshake, State::Status, State::Login, State::Play].iter()
+        .map(|state| (format!("{:?}", state), json!(stats.time_in(*state).as_secs_f64())))
+        .collect();
+    let packets: Vec<Value> = stats.packets().map(|(direction, state, id, packet)| json!({
+        "direction": direction.to_string(),
+        "state": format!("{:?}", state),
+        "id": id,
+        "name": crate::packets::names::packet_name(state, direction, id),
+        "count": packet.count,
+        "wire_bytes": packet.wire_bytes,
+        "uncompressed_bytes": packet.uncompressed_bytes,
+        "largest": packet.largest,
+    })).collect();
+    let totals: serde_json::Map<String, Value> = [Direction::Serverbound, Direction::Clientbound].iter()
+        .map(|direction| {
+            let total = stats.total(*direction);
+            (direction.to_string(), json!({
+                "count": total.count,
+                "wire_bytes": total.wire_bytes,
+                "uncompressed_bytes": total.uncompressed_bytes,
+                "largest": total.largest,
+            }))
+        })
+        .collect();
+    json!({
+        "id": session.id,
+        "duration": stats.elapsed().as_secs_f64(),
+        "states": states,
+        "latency_ms": {
+            "last": ms(stats.latency.last),
+            "average": ms(stats.latency.smoothed),
+            "min": ms(stats.latency.min),
+            "max": ms(stats.latency.max),
+            "samples": stats.latency.samples,
+            "server_reported": ms(stats.latency.server_reported),
+            "upstream": ms(stats.latency.upstream()),
+        },
+        "packets": packets,
+        "totals": totals,
+    })
+}
+
+fn world_json(session: &Session) -> Value {
+    let world = session.world();
+    let mut chunks: Vec<(i32, i32)> = world.chunks().map(|chunk| (chunk.x, chunk.z)).collect();
+    chunks.sort();
+    json!({
+        "id": session.id,
+        "dimension": world.dimension,
+        "dimension_type": world.dimension_type,
+        "min_y": world.min_y,
+        "height": world.height,
+        "chunks": chunks,
+    })
+}
+
+/// `block` is null when the position is not in a loaded chunk.
+fn block_json(session: &Session, position: Position) -> Value {
+    let world = session.world();
+    let block_entity = world.block_entity_at(position).map(|entity| json!({
+        "kind": entity.kind,
+        "nbt": entity.data.as_ref().map(|data| data.to_string()),
+    }));
+    json!({
+        "x": position.x,
+        "y": position.y,
+        "z": position.z,
+        "block": world.block_at(position),
+        "biome": world.biome_at(position),
+        "block_entity": block_entity,
+    })
+}
+
+fn movement_json(session: &Session) -> Value {
+    let movement = session.movement();
+    let (yaw, pitch) = movement.rotation();
+    json!({
+        "id": session.id,
+        "position": movement.position(),
+        "yaw": yaw,
+        "pitch": pitch,
+        "on_ground": movement.on_ground(),
+        "distance": movement.distance(),
+        "path_points": movement.path().count(),
+        "corrections": movement.corrections().collect::<Vec<_>>(),
+    })
+}
+
+fn entities_json(session: &Session) -> Value {
+    let entities = session.entities();
+    let mut list: Vec<&Entity> = entities.iter().collect();
+    list.sort_by_key(|entity| entity.id);
+    json!({
+        "id": session.id,
+        "own_id": entities.own_id,
+        "entities": list.into_iter().map(entity_json).collect::<Vec<_>>(),
+    })
+}
+
+fn entity_json(entity: &Entity) -> Value {
+    let (kind, type_id) = match entity.kind {
+        EntityKind::Entity(id) => ("entity", Some(id)),
+        EntityKind::Player => ("player", None),
+        EntityKind::ExperienceOrb { .. } => ("experience_orb", None),
+    };
+    let metadata: serde_json::Map<String, Value> = entity.metadata.iter()
+        .map(|(index, value)| (index.to_string(), metadata_json(value)))
+        .collect();
+    let equipment: serde_json::Map<String, Value> = entity.equipment.iter()
+        .map(|(slot, item)| {
+            let name = EQUIPMENT_SLOTS.get(*slot as usize).map_or_else(|| slot.to_string(), |name| name.to_string());
+            (name, json!({ "item": item.item, "count": item.count, "nbt": item.nbt.as_ref().map(|nbt| nbt.to_string()) }))
+        })
+        .collect();
+    json!({
+        "id": entity.id,
+        "uuid": entity.uuid.map(|uuid| uuid.to_string()),
+        "kind": kind,
+        "type": type_id,
+        "count": match entity.kind { EntityKind::ExperienceOrb { count } => Some(count), _ => None },
+        "x": entity.x,
+        "y": entity.y,
+        "z": entity.z,
+        "yaw": entity.yaw,
+        "pitch": entity.pitch,
+        "head_yaw": entity.head_yaw,
+        "velocity": entity.velocity,
+        "on_ground": entity.on_ground,
+        "data": entity.data,
+        "metadata": metadata,
+        "equipment": equipment,
+        "age": entity.spawned.elapsed().as_secs_f64(),
+        "since_update": entity.updated.elapsed().as_secs_f64(),
+    })
+}
+
+/// Numbers, booleans and strings as themselves, anything else as its text.
+fn metadata_json(value: &MetadataValue) -> Value {
+    match value {
+        MetadataValue::Byte(value) => json!(value),
+        MetadataValue::VarInt(value) => json!(value),
+        MetadataValue::Float(value) => json!(value),
+        MetadataValue::Boolean(value) => json!(value),
+        MetadataValue::String(value) | MetadataValue::Chat(value) => json!(value),
+        other => json!(other.to_string()),
+    }
+}
+
+/// Writes decoded packets to `request` as server-sent events until the client goes away.
+/// tiny_http buffers streamed bodies, so the response is written on the raw connection.
+fn stream_events(request: Request, session: Option<u64>) {
+    let events = EVENTS.subscribe();
+    let mut out = request.into_writer();
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n";
+    if out.write_all(head.as_bytes()).and_then(|_| out.flush()).is_err() {
+        return;
+    }
+    loop {
+        let chunk = match events.recv_timeout(STREAM_KEEP_ALIVE) {
+            Ok(event) if session.is_none_or(|session| event.session == session) => format!("data: {}\n\n", json!(*event)),
+            Ok(_) => continue,
+            Err(RecvTimeoutError::Timeout) => ": keep-alive\n\n".to_string(),
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        if out.write_all(chunk.as_bytes()).and_then(|_| out.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Sends decoded packets to a WebSocket client as JSON text messages until it goes away.
+/// tiny_http hands the connection over as one blocking stream, so the client is only read after
+/// each keep-alive Ping, up to its Pong; a Close read there is answered and ends the stream.
+fn stream_websocket(request: Request, session: Option<u64>) {
+    let accept = match websocket_accept(request.headers()) {
+        Ok(accept) => accept,
+        Err((426, message)) => {
+            let version = Header::from_bytes("Sec-WebSocket-Version", "13").unwrap();
+            let response = Response::from_string(json!({ "error": message }).to_string()).with_status_code(426);
+            let _ = request.respond(response.with_header(version));
+            return;
+        }
+        Err((status, message)) => return respond(request, error(status, message)),
+    };
+    let events = EVENTS.subscribe();
+    let response = Response::empty(101)
+        .with_header(Header::from_bytes("Sec-WebSocket-Accept", accept).unwrap());
+    let stream = request.upgrade("websocket", response);
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    loop {
+        let message = match events.recv_timeout(STREAM_KEEP_ALIVE) {
+            Ok(event) if session.is_none_or(|session| event.session == session) => Message::text(json!(*event).to_string()),
+            Ok(_) => continue,
+            Err(RecvTimeoutError::Timeout) => {
+                if socket.send(Message::Ping(Default::default())).is_err() || !await_pong(&mut socket) {
+                    return;
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                let _ = socket.close(None).and_then(|_| socket.flush());
+                return;
+            }
+        };
+        if socket.send(message).is_err() {
+            return;
+        }
+    }
+}
+
+/// The `Sec-WebSocket-Accept` value for a version 13 WebSocket upgrade request.
+fn websocket_accept(headers: &[Header]) -> Result<String, (u16, &'static str)> {
+    let header = |name: &'static str| headers.iter().find(|header| header.field.equiv(name)).map(|header| header.value.as_str());
+    if !header("Upgrade").is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket")) {
+        return Err((400, "expected a WebSocket upgrade"));
+    }
+    let key = header("Sec-WebSocket-Key").ok_or((400, "Sec-WebSocket-Key is required"))?;
+    if header("Sec-WebSocket-Version").map(str::trim) != Some("13") {
+        return Err((426, "only WebSocket version 13 is supported"));
+    }
+    Ok(derive_accept_key(key.as_bytes()))
+}
+
+/// Reads messages until the client's Pong; false when it closed the connection instead.
+/// tungstenite queues the reply to a Close, which the flush sends.
+fn await_pong<S: Read + Write>(socket: &mut WebSocket<S>) -> bool {
+    loop {
+        match socket.read() {
+            Ok(Message::Pong(_)) => return true,
+            Ok(Message::Close(_)) => {
+                let _ = socket.flush();
+                return false;
+            }
+            Ok(_) => continue,
+            Err(_) => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+
+    fn headers(headers: &[(&str, &str)]) -> Vec<Header> {
+        headers.iter().map(|(field, value)| Header::from_bytes(*field, *value).unwrap()).collect()
+    }
+
+    #[test]
+    fn websocket_handshakes_are_checked() {
+        let key = ("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==");
+        let accept = websocket_accept(&headers(&[("Upgrade", "WebSocket"), key, ("Sec-WebSocket-Version", "13")]));
+        assert_eq!(accept.unwrap(), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(websocket_accept(&headers(&[key, ("Sec-WebSocket-Version", "13")])).unwrap_err().0, 400);
+        assert_eq!(websocket_accept(&headers(&[("Upgrade", "h2c"), key, ("Sec-WebSocket-Version", "13")])).unwrap_err().0, 400);
+        assert_eq!(websocket_accept(&headers(&[("Upgrade", "websocket"), ("Sec-WebSocket-Version", "13")])).unwrap_err().0, 400);
+        assert_eq!(websocket_accept(&headers(&[("Upgrade", "websocket"), key, ("Sec-WebSocket-Version", "8")])).unwrap_err().0, 426);
+        assert_eq!(websocket_accept(&headers(&[("Upgrade", "websocket"), key])).unwrap_err().0, 426);
+    }
+
+    #[test]
+    fn websocket_clients_can_close_the_stream() {
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        serve(&addr, Arc::new(Sessions::default()), Arc::new(RwLock::new(Routing { upstream: "localhost:1".to_string() })));
+        let stream = TcpStream::connect(&addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let (mut socket, _) = tungstenite::client(format!("ws://{}/events/ws?session=0", addr), stream).unwrap();
+
+        // The stream goes on after the client's Pong to the first keep-alive.
+        for _ in 0..2 {
+            assert!(matches!(socket.read().unwrap(), Message::Ping(_)));
+        }
+        socket.close(None).unwrap();
+        loop {
+            match socket.read() {
+                Ok(_) => continue,
+                Err(tungstenite::Error::ConnectionClosed) => break,
+                Err(e) => panic!("Close was not answered: {}", e),
+            }
+        }
+    }
+}
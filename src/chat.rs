@@ -0,0 +1,190 @@
+use crate::session::Session;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Told to the player when a rule without a `reason` blocks their message.
+const DEFAULT_BLOCK_REASON: &str = "Your message was not sent.";
+/// Told to the player when a rule without a `reason` changes their message.
+const DEFAULT_REWRITE_REASON: &str = "Your message was changed before it was sent.";
+
+/// Logging and filtering what players say.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChatConfig {
+    /// Directory for the chat logs, one file a day named `<yyyy-mm-dd>.log`, in UTC.
+    /// Nothing is logged when unset.
+    pub log_dir: Option<String>,
+    /// Also log the player and system messages every player is shown. Each session logs
+    /// its own copy, so a message to everyone is logged once per player. These are Player
+    /// Chat and System Chat: Disguised Chat only exists from 1.19.3, and protocol 760 has
+    /// no such packet to log.
+    pub log_received: bool,
+    /// Checked in order against what players send; the first match applies.
+    pub filters: Vec<FilterRule>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterAction {
+    /// The message never reaches the server.
+    Block,
+    /// Every match is replaced with `replacement` and the result is sent unsigned.
+    Rewrite,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilterRule {
+    #[serde(deserialize_with = "regex")]
+    pub pattern: Regex,
+    pub action: FilterAction,
+    /// For `rewrite`; `$1` and `${name}` refer to groups of `pattern`.
+    #[serde(default)]
+    pub replacement: String,
+    /// Shown to the player as a system message; a generic one when unset.
+    pub reason: Option<String>,
+    /// Also check commands, such as `/msg`. Commands are checked without the slash.
+    #[serde(default)]
+    pub commands: bool,
+}
+
+fn regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+/// What the filter made of a message.
+pub enum Filtered {
+    Pass,
+    Block { reason: String },
+    Rewrite { text: String, reason: String },
+}
+
+/// The chat filter and log, shared by every session.
+pub struct Chat {
+    config: ChatConfig,
+    /// The day the open file is for, and the file.
+    log: Mutex<Option<(String, File)>>,
+}
+
+impl Chat {
+    pub fn new(config: ChatConfig) -> Arc<Chat> {
+        Arc::new(Chat { config, log: Mutex::new(None) })
+    }
+
+    pub fn log_received(&self) -> bool {
+        self.config.log_received
+    }
+
+    /// Runs the filter rules over a chat message or, when `command` is set, a command.
+    pub fn filter(&self, text: &str, command: bool) -> Filtered {
+        let Some(rule) = self.config.filters.iter().find(|rule| (rule.commands || !command) && rule.pattern.is_match(text)) else {
+            return Filtered::Pass;
+        };
+        match rule.action {
+            FilterAction::Block => Filtered::Block {
+                reason: rule.reason.clone().unwrap_or_else(|| DEFAULT_BLOCK_REASON.to_string()),
+            },
+            FilterAction::Rewrite => Filtered::Rewrite {
+                text: rule.pattern.replace_all(text, rule.replacement.as_str()).into_owned(),
+                reason: rule.reason.clone().unwrap_or_else(|| DEFAULT_REWRITE_REASON.to_string()),
+            },
+        }
+    }
+
+    /// Appends `<time> <player> (<uuid>) <kind>: <text>` to today's log, if logging is on.
+    pub fn log(&self, session: &Session, kind: &str, text: &str) {
+        let Some(dir) = &self.config.log_dir else { return };
+        let (date, time) = utc_now();
+        let (name, uuid) = session.player().unwrap_or_default();
+        let uuid = uuid.map_or_else(|| "-".to_string(), |uuid| uuid.to_string());
+        let line = format!("{} {} ({}) {}: {}\n", time, name, uuid, kind, text.replace('\n', "\\n"));
+
+        let mut log = self.log.lock().unwrap();
+        if log.as_ref().is_none_or(|(day, _)| *day != date) {
+            let path = Path::new(dir).join(format!("{}.log", date));
+            let opened = fs::create_dir_all(dir).and_then(|_| OpenOptions::new().create(true).append(true).open(&path));
+            *log = match opened {
+                Ok(file) => Some((date, file)),
+                Err(e) => {
+                    warn!("Cannot open chat log {} reason: {}", path.display(), e);
+                    None
+                }
+            };
+        }
+        if let Some((_, file)) = log.as_mut() {
+            if let Err(e) = file.write_all(line.as_bytes()) {
+                warn!("Cannot write chat log reason: {}", e);
+            }
+        }
+    }
+}
+
+/// Today's date as `yyyy-mm-dd` and the time as `hh:mm:ss`, in UTC.
+fn utc_now() -> (String, String) {
+    utc_date_time(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs()))
+}
+
+/// `secs` since the Unix epoch as a UTC date and time, formatted like `utc_now`.
+fn utc_date_time(secs: u64) -> (String, String) {
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Days since 1970-01-01 to a civil date, counting in 400 year eras from 0000-03-01.
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (format!("{:04}-{:02}-{:02}", year, month, day), format!("{:02}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> String {
+        let (date, time) = utc_date_time(secs);
+        format!("{} {}", date, time)
+    }
+
+    #[test]
+    fn dates_across_leap_days_and_centuries() {
+        assert_eq!(at(0), "1970-01-01 00:00:00");
+        assert_eq!(at(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(at(951_868_800), "2000-03-01 00:00:00");
+        assert_eq!(at(1_709_251_199), "2024-02-29 23:59:59");
+        assert_eq!(at(1_792_355_325), "2026-10-18 20:28:45");
+        assert_eq!(at(4_102_444_799), "2099-12-31 23:59:59");
+    }
+
+    #[test]
+    fn first_matching_rule_applies() {
+        let config: ChatConfig = toml::from_str(r#"
+            [[filters]]
+            pattern = "(?i)badword"
+            action = "rewrite"
+            replacement = "***"
+            [[filters]]
+            pattern = "^spam"
+            action = "block"
+            reason = "No spam."
+            commands = true
+        "#).unwrap();
+        let chat = Chat::new(config);
+        assert!(matches!(chat.filter("hello", false), Filtered::Pass));
+        assert!(matches!(chat.filter("a BadWord b", false), Filtered::Rewrite { text, .. } if text == "a *** b"));
+        // The rewrite rule does not cover commands.
+        assert!(matches!(chat.filter("msg Bob badword", true), Filtered::Pass));
+        assert!(matches!(chat.filter("spam badword", false), Filtered::Rewrite { .. }));
+        assert!(matches!(chat.filter("spam", true), Filtered::Block { reason } if reason == "No spam."));
+    }
+}
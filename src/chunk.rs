@@ -0,0 +1,511 @@
+//! Chunk columns as sent in Chunk Data and Update Light.
+
+use crate::nbt::{self, Compound, Tag};
+use crate::utils::{check_length, read_bytes, read_field, read_var_int, ProtocolError, MAX_FRAME_LEN};
+use byteorder::{BigEndian, ReadBytesExt};
+use std::io::{self, Cursor};
+
+/// Blocks along each side of a section.
+pub const SECTION_SIZE: usize = 16;
+/// Biome cells along each side of a section; each covers 4x4x4 blocks.
+pub const BIOME_SIZE: usize = 4;
+
+/// Entry count and palette limits of one kind of paletted container.
+#[derive(Clone, Copy, Debug)]
+pub struct ContainerKind {
+    pub entries: usize,
+    /// Smallest bits per entry an indirect palette is stored with.
+    min_indirect_bits: u8,
+    /// Largest bits per entry that still uses a palette; above it values are global ids.
+    max_indirect_bits: u8,
+    /// Width of global ids in protocol 760, used when a rewritten container outgrows its palette.
+    global_bits: u8,
+}
+
+pub const BLOCK_STATES: ContainerKind = ContainerKind { entries: 4096, min_indirect_bits: 4, max_indirect_bits: 8, global_bits: 15 };
+pub const BIOMES: ContainerKind = ContainerKind { entries: 64, min_indirect_bits: 1, max_indirect_bits: 3, global_bits: 6 };
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Palette {
+    /// Every entry is this id, and there is no data array.
+    Single(i32),
+    /// Entries index into the list of ids.
+    Indirect(Vec<i32>),
+    /// Entries are global ids.
+    Direct,
+}
+
+/// Block states or biomes of one section, packed into longs. Entries never span two longs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PalettedContainer {
+    pub bits: u8,
+    pub palette: Palette,
+    pub data: Vec<u64>,
+}
+
+impl PalettedContainer {
+    pub fn single(id: i32) -> PalettedContainer {
+        PalettedContainer { bits: 0, palette: Palette::Single(id), data: Vec::new() }
+    }
+
+    pub fn read(cr: &mut Cursor<&[u8]>, kind: ContainerKind) -> io::Result<PalettedContainer> {
+        let sent_bits = read_field(cr, "bits_per_entry", |cr| cr.read_u8())?;
+        let (bits, palette) = match sent_bits {
+            0 => (0, Palette::Single(read_field(cr, "value", |cr| read_var_int(cr))?)),
+            bits if bits <= kind.max_indirect_bits => {
+                let len = read_field(cr, "palette_length", |cr| read_var_int(cr).and_then(|len| check_length(len, kind.entries)))?;
+                let mut palette = Vec::new();
+                for _ in 0..len {
+                    palette.push(read_field(cr, "palette", |cr| read_var_int(cr))?);
+                }
+                (bits.max(kind.min_indirect_bits), Palette::Indirect(palette))
+            }
+            // Servers send the width of the global palette their registries need.
+            bits if bits <= 32 => (bits, Palette::Direct),
+            bits => return Err(ProtocolError::BadChunk(format!("{} bits per entry", bits)).into_error()),
+        };
+        let len = read_field(cr, "data_array_length", |cr| read_var_int(cr).and_then(|len| check_length(len, MAX_FRAME_LEN / 8)))?;
+        let bytes = read_field(cr, "data_array", |cr| read_bytes(cr, len * 8))?;
+        let data: Vec<u64> = bytes.chunks_exact(8).map(|chunk| u64::from_be_bytes(chunk.try_into().unwrap())).collect();
+        let expected = if bits == 0 { 0 } else { kind.entries.div_ceil(64 / bits as usize) };
+        if data.len() != expected {
+            let reason = format!("{} longs for {} entries of {} bits, expected {}", data.len(), kind.entries, bits, expected);
+            return Err(ProtocolError::BadChunk(reason).into_error());
+        }
+        Ok(PalettedContainer { bits, palette, data })
+    }
+
+    /// The id stored at `index`, or `None` when it points outside the palette.
+    pub fn get(&self, index: usize) -> Option<i32> {
+        if let Palette::Single(id) = self.palette {
+            return Some(id);
+        }
+        let per_long = 64 / self.bits as usize;
+        let long = *self.data.get(index / per_long)?;
+        let value = (long >> ((index % per_long) * self.bits as usize)) & ((1u64 << self.bits) - 1);
+        match &self.palette {
+            Palette::Indirect(palette) => palette.get(value as usize).copied(),
+            _ => Some(value as i32),
+        }
+    }
+
+    /// Stores `id` at `index` in place when the container can already hold it: as its single
+    /// value, from its palette, or as a global id that fits. Returns false otherwise.
+    pub fn set(&mut self, index: usize, id: i32) -> bool {
+        let value = match &self.palette {
+            Palette::Single(single) => return *single == id,
+            Palette::Indirect(palette) => match palette.iter().position(|entry| *entry == id) {
+                Some(position) => position as u64,
+                None => return false,
+            },
+            Palette::Direct if (id as u32 as u64) >> self.bits == 0 => id as u32 as u64,
+            Palette::Direct => return false,
+        };
+        let per_long = 64 / self.bits as usize;
+        let Some(long) = self.data.get_mut(index / per_long) else { return false };
+        let shift = (index % per_long) * self.bits as usize;
+        *long = (*long & !(((1u64 << self.bits) - 1) << shift)) | value << shift;
+        true
+    }
+
+    /// Every entry, unpacked; `kind` says how many there are.
+    pub fn entries(&self, kind: ContainerKind) -> Vec<i32> {
+        (0..kind.entries).map(|index| self.get(index).unwrap_or(0)).collect()
+    }
+
+    /// Packs `entries` the way the server would: a single value, the smallest palette
+    /// that fits, or global ids.
+    pub fn from_entries(entries: &[i32], kind: ContainerKind) -> PalettedContainer {
+        let mut palette: Vec<i32> = Vec::new();
+        for id in entries {
+            if !palette.contains(id) {
+                palette.push(*id);
+            }
+        }
+        if palette.len() == 1 {
+            return PalettedContainer::single(palette[0]);
+        }
+        let needed = (usize::BITS - (palette.len() - 1).leading_zeros()) as u8;
+        let (bits, palette) = if needed <= kind.max_indirect_bits {
+            (needed.max(kind.min_indirect_bits), Palette::Indirect(palette))
+        } else {
+            let widest = palette.iter().map(|id| u32::BITS - (*id as u32).leading_zeros()).max().unwrap_or(0) as u8;
+            (kind.global_bits.max(widest), Palette::Direct)
+        };
+        let values: Vec<u64> = match &palette {
+            Palette::Indirect(ids) => entries.iter().map(|id| ids.iter().position(|entry| entry == id).unwrap() as u64).collect(),
+            _ => entries.iter().map(|id| *id as u32 as u64).collect(),
+        };
+        PalettedContainer { bits, data: pack(&values, bits), palette }
+    }
+
+    /// Every distinct id in the container, for containers with a palette.
+    pub fn palette_ids(&self) -> Option<&[i32]> {
+        match &self.palette {
+            Palette::Single(id) => Some(std::slice::from_ref(id)),
+            Palette::Indirect(palette) => Some(palette),
+            Palette::Direct => None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.palette {
+            Palette::Single(id) => format!("single {}", id),
+            Palette::Indirect(palette) => format!("indirect {} bits {:?}", self.bits, palette),
+            Palette::Direct => format!("direct {} bits", self.bits),
+        }
+    }
+}
+
+/// Packs `bits`-wide values into longs, lowest bits first, without spanning two longs.
+pub fn pack(values: &[u64], bits: u8) -> Vec<u64> {
+    let per_long = 64 / bits as usize;
+    values.chunks(per_long)
+        .map(|chunk| chunk.iter().enumerate().fold(0, |long, (i, value)| long | value << (i * bits as usize)))
+        .collect()
+}
+
+/// Index of a block in a section's block states; coordinates are 0 to 15.
+pub fn block_index(x: usize, y: usize, z: usize) -> usize {
+    (y * SECTION_SIZE + z) * SECTION_SIZE + x
+}
+
+/// Index of a biome cell in a section's biomes; coordinates are 0 to 3.
+pub fn biome_index(x: usize, y: usize, z: usize) -> usize {
+    (y * BIOME_SIZE + z) * BIOME_SIZE + x
+}
+
+/// A 16x16x16 slice of a chunk column.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChunkSection {
+    /// Blocks that are not air, as counted by the server.
+    pub block_count: i16,
+    pub block_states: PalettedContainer,
+    pub biomes: PalettedContainer,
+}
+
+impl ChunkSection {
+    pub fn read(cr: &mut Cursor<&[u8]>) -> io::Result<ChunkSection> {
+        Ok(ChunkSection {
+            block_count: read_field(cr, "block_count", |cr| cr.read_i16::<BigEndian>())?,
+            block_states: PalettedContainer::read(cr, BLOCK_STATES)?,
+            biomes: PalettedContainer::read(cr, BIOMES)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.block_count == 0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockEntity {
+    /// Position within the chunk column: x and z are 0 to 15, y is absolute.
+    pub x: u8,
+    pub z: u8,
+    pub y: i16,
+    /// Block entity type id, e.g. a chest or a sign.
+    pub kind: i32,
+    pub data: Option<Tag>,
+}
+
+impl BlockEntity {
+    pub fn read(cr: &mut Cursor<&[u8]>) -> io::Result<BlockEntity> {
+        let xz = read_field(cr, "packed_xz", |cr| cr.read_u8())?;
+        Ok(BlockEntity {
+            x: xz >> 4,
+            z: xz & 0x0f,
+            y: read_field(cr, "y", |cr| cr.read_i16::<BigEndian>())?,
+            kind: read_field(cr, "type", |cr| read_var_int(cr))?,
+            data: read_field(cr, "data", |cr| nbt::read_named(cr))?.map(|(_, tag)| tag),
+        })
+    }
+}
+
+/// Light for a column, shared by Chunk Data and Update Light. Bit `i` of a mask stands for
+/// section `i - 1`: the light sections reach one past the world at the bottom and top.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LightData {
+    pub trust_edges: bool,
+    pub sky_light_mask: Vec<i64>,
+    pub block_light_mask: Vec<i64>,
+    pub empty_sky_light_mask: Vec<i64>,
+    pub empty_block_light_mask: Vec<i64>,
+    /// One 2048-byte nibble array per set bit of the matching mask, lowest bit first.
+    pub sky_light: Vec<Vec<u8>>,
+    pub block_light: Vec<Vec<u8>>,
+}
+
+/// Light arrays hold a nibble per block of a section.
+pub const LIGHT_ARRAY_LEN: usize = 2048;
+
+fn read_bit_set(cr: &mut Cursor<&[u8]>, field: &'static str) -> io::Result<Vec<i64>> {
+    read_field(cr, field, |cr| {
+        let len = check_length(read_var_int(cr)?, MAX_FRAME_LEN / 8)?;
+        let bytes = read_bytes(cr, len * 8)?;
+        Ok(bytes.chunks_exact(8).map(|chunk| i64::from_be_bytes(chunk.try_into().unwrap())).collect())
+    })
+}
+
+fn read_light_arrays(cr: &mut Cursor<&[u8]>, field: &'static str) -> io::Result<Vec<Vec<u8>>> {
+    read_field(cr, field, |cr| {
+        let count = check_length(read_var_int(cr)?, MAX_FRAME_LEN / LIGHT_ARRAY_LEN)?;
+        let mut arrays = Vec::new();
+        for _ in 0..count {
+            let len = check_length(read_var_int(cr)?, LIGHT_ARRAY_LEN)?;
+            arrays.push(read_bytes(cr, len)?);
+        }
+        Ok(arrays)
+    })
+}
+
+/// Number of set bits in a mask.
+pub fn mask_count(mask: &[i64]) -> u32 {
+    mask.iter().map(|long| long.count_ones()).sum()
+}
+
+impl LightData {
+    pub fn read(cr: &mut Cursor<&[u8]>) -> io::Result<LightData> {
+        Ok(LightData {
+            trust_edges: read_field(cr, "trust_edges", |cr| cr.read_u8())? != 0,
+            sky_light_mask: read_bit_set(cr, "sky_light_mask")?,
+            block_light_mask: read_bit_set(cr, "block_light_mask")?,
+            empty_sky_light_mask: read_bit_set(cr, "empty_sky_light_mask")?,
+            empty_block_light_mask: read_bit_set(cr, "empty_block_light_mask")?,
+            sky_light: read_light_arrays(cr, "sky_light_arrays")?,
+            block_light: read_light_arrays(cr, "block_light_arrays")?,
+        })
+    }
+
+    pub fn summary(&self) -> String {
+        format!("sky_light:{} block_light:{} empty_sky:{} empty_block:{} trust_edges:{}",
+                self.sky_light.len(), self.block_light.len(),
+                mask_count(&self.empty_sky_light_mask), mask_count(&self.empty_block_light_mask), self.trust_edges)
+    }
+}
+
+/// One chunk column: its sections from the bottom of the world up, block entities and light.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chunk {
+    pub x: i32,
+    pub z: i32,
+    /// `MOTION_BLOCKING` and `WORLD_SURFACE`, as packed long arrays.
+    pub heightmaps: Compound,
+    pub sections: Vec<ChunkSection>,
+    pub block_entities: Vec<BlockEntity>,
+    pub light: LightData,
+}
+
+impl Chunk {
+    /// Reads Chunk Data and Update Light after the packet id. The packet does not say how
+    /// many sections there are, so they are read until the data array runs out.
+    pub fn read(cr: &mut Cursor<&[u8]>) -> io::Result<Chunk> {
+        let x = read_field(cr, "chunk_x", |cr| cr.read_i32::<BigEndian>())?;
+        let z = read_field(cr, "chunk_z", |cr| cr.read_i32::<BigEndian>())?;
+        let heightmaps = match read_field(cr, "heightmaps", |cr| nbt::read_named(cr))? {
+            Some((_, Tag::Compound(heightmaps))) => heightmaps,
+            _ => Compound::default(),
+        };
+        let size = read_field(cr, "size", |cr| read_var_int(cr).and_then(|len| check_length(len, MAX_FRAME_LEN)))?;
+        let sections = read_field(cr, "data", |cr| {
+            let data = read_bytes(cr, size)?;
+            let mut data_cr = Cursor::new(&data[..]);
+            let mut sections = Vec::new();
+            while (data_cr.position() as usize) < data.len() {
+                sections.push(ChunkSection::read(&mut data_cr)?);
+            }
+            Ok(sections)
+        })?;
+        let count = read_field(cr, "block_entity_count", |cr| read_var_int(cr).and_then(|len| check_length(len, MAX_FRAME_LEN)))?;
+        let mut block_entities = Vec::new();
+        for _ in 0..count {
+            block_entities.push(BlockEntity::read(cr)?);
+        }
+        Ok(Chunk { x, z, heightmaps, sections, block_entities, light: LightData::read(cr)? })
+    }
+
+    /// Block state id at a position within the column: `x` and `z` are 0 to 15 and
+    /// `section_y` counts sections from the bottom of the world.
+    pub fn block(&self, x: usize, section_y: usize, y: usize, z: usize) -> Option<i32> {
+        self.sections.get(section_y)?.block_states.get(block_index(x, y, z))
+    }
+
+    /// One line per section and block entity, for trace logging.
+    pub fn details(&self) -> String {
+        let sections = self.sections.iter().enumerate().map(|(i, section)| {
+            format!("section {}: blocks:{} states:{} biomes:{}", i, section.block_count,
+                    section.block_states.describe(), section.biomes.describe())
+        });
+        let block_entities = self.block_entities.iter().map(|entity| {
+            let data = entity.data.as_ref().map_or(String::new(), |data| format!(" {}", data));
+            format!("block_entity {},{},{} type:{}{}", entity.x, entity.y, entity.z, entity.kind, data)
+        });
+        sections.chain(block_entities).collect::<Vec<_>>().join("\n")
+    }
+
+    pub fn summary(&self) -> String {
+        let blocks: i64 = self.sections.iter().map(|section| section.block_count as i64).sum();
+        let heightmaps: Vec<&str> = self.heightmaps.0.iter().map(|(name, _)| name.as_str()).collect();
+        format!("x:{} z:{} sections:{} (non-empty {}) blocks:{} block_entities:{} heightmaps:{} {}",
+                self.x, self.z, self.sections.len(), self.sections.iter().filter(|section| !section.is_empty()).count(),
+                blocks, self.block_entities.len(), heightmaps.join(","), self.light.summary())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::write_var_int;
+
+    /// A container as the server sends it.
+    fn write_container(out: &mut Vec<u8>, bits: u8, palette: &[i32], data: &[u64]) {
+        out.push(bits);
+        if bits == 0 {
+            write_var_int(out, palette[0]);
+        } else if !palette.is_empty() {
+            write_var_int(out, palette.len() as i32);
+            palette.iter().for_each(|id| write_var_int(out, *id));
+        }
+        write_var_int(out, data.len() as i32);
+        data.iter().for_each(|long| out.extend_from_slice(&long.to_be_bytes()));
+    }
+
+    fn read(bytes: &[u8], kind: ContainerKind) -> io::Result<PalettedContainer> {
+        PalettedContainer::read(&mut Cursor::new(bytes), kind)
+    }
+
+    #[test]
+    fn pack_never_spans_longs() {
+        assert_eq!(pack(&[1, 2, 3], 4), vec![0x321]);
+        // 12 five-bit values fill 60 bits; the 13th starts the next long.
+        let values: Vec<u64> = (1..=13).collect();
+        let packed = pack(&values, 5);
+        assert_eq!(packed.len(), 2);
+        assert_eq!(packed[0] >> 55, 12);
+        assert_eq!(packed[1], 13);
+    }
+
+    #[test]
+    fn indirect_containers_decode_through_the_palette() {
+        let entries: Vec<u64> = (0..4096).map(|index| (index % 3) as u64).collect();
+        let mut bytes = Vec::new();
+        write_container(&mut bytes, 4, &[0, 9, 70], &pack(&entries, 4));
+        let container = read(&bytes, BLOCK_STATES).unwrap();
+        assert_eq!(container.palette_ids(), Some(&[0, 9, 70][..]));
+        assert_eq!(container.get(block_index(0, 0, 0)), Some(0));
+        assert_eq!(container.get(block_index(1, 0, 0)), Some(9));
+        assert_eq!(container.get(block_index(15, 15, 15)), Some(0));
+    }
+
+    #[test]
+    fn narrow_palettes_are_widened_to_the_minimum() {
+        // Block states are never stored with fewer than 4 bits, whatever the server says.
+        let entries: Vec<u64> = (0..4096).map(|index| (index % 2) as u64).collect();
+        let mut bytes = Vec::new();
+        write_container(&mut bytes, 1, &[5, 6], &pack(&entries, 4));
+        let container = read(&bytes, BLOCK_STATES).unwrap();
+        assert_eq!(container.bits, 4);
+        assert_eq!(container.get(1), Some(6));
+    }
+
+    #[test]
+    fn single_and_direct_containers() {
+        let mut bytes = Vec::new();
+        write_container(&mut bytes, 0, &[42], &[]);
+        let container = read(&bytes, BIOMES).unwrap();
+        assert_eq!(container.entries(BIOMES), vec![42; 64]);
+
+        let entries: Vec<u64> = (0..64).map(|index| index * 3).collect();
+        let mut bytes = Vec::new();
+        write_container(&mut bytes, 8, &[], &pack(&entries, 8));
+        let container = read(&bytes, BIOMES).unwrap();
+        assert_eq!(container.palette, Palette::Direct);
+        assert_eq!(container.get(biome_index(3, 3, 3)), Some(189));
+    }
+
+    #[test]
+    fn data_of_the_wrong_length_is_rejected() {
+        let mut bytes = Vec::new();
+        write_container(&mut bytes, 4, &[0, 1], &[0; 255]);
+        assert!(read(&bytes, BLOCK_STATES).is_err());
+        let mut bytes = Vec::new();
+        write_container(&mut bytes, 33, &[], &[]);
+        assert!(read(&bytes, BLOCK_STATES).is_err());
+    }
+
+    #[test]
+    fn from_entries_picks_the_smallest_encoding() {
+        let single = PalettedContainer::from_entries(&[7; 4096], BLOCK_STATES);
+        assert_eq!(single, PalettedContainer::single(7));
+
+        let two: Vec<i32> = (0..4096).map(|index| if index % 5 == 0 { 1 } else { 100 }).collect();
+        let container = PalettedContainer::from_entries(&two, BLOCK_STATES);
+        assert_eq!((container.bits, container.palette_ids()), (4, Some(&[1, 100][..])));
+        assert_eq!(container.entries(BLOCK_STATES), two);
+
+        let many: Vec<i32> = (0..4096).collect();
+        let container = PalettedContainer::from_entries(&many, BLOCK_STATES);
+        assert_eq!((container.bits, &container.palette), (15, &Palette::Direct));
+        assert_eq!(container.entries(BLOCK_STATES), many);
+
+        // Nine biomes need 4 bits, past the indirect limit of 3.
+        let biomes: Vec<i32> = (0..64).map(|index| index % 9).collect();
+        let container = PalettedContainer::from_entries(&biomes, BIOMES);
+        assert_eq!((container.bits, &container.palette), (6, &Palette::Direct));
+        assert_eq!(container.entries(BIOMES), biomes);
+    }
+
+    #[test]
+    fn set_writes_in_place_only_what_fits() {
+        let entries: Vec<i32> = (0..4096).map(|index| if index % 2 == 0 { 1 } else { 9 }).collect();
+        let mut container = PalettedContainer::from_entries(&entries, BLOCK_STATES);
+        assert!(container.set(block_index(15, 15, 15), 1));
+        assert!(container.set(0, 9));
+        assert!(!container.set(1, 2));
+        assert_eq!((container.get(0), container.get(1), container.get(4095)), (Some(9), Some(9), Some(1)));
+        assert_eq!(container.get(2), Some(1));
+
+        let mut single = PalettedContainer::single(0);
+        assert!(single.set(7, 0));
+        assert!(!single.set(7, 1));
+
+        let many: Vec<i32> = (0..4096).collect();
+        let mut direct = PalettedContainer::from_entries(&many, BLOCK_STATES);
+        assert!(direct.set(5, 0x7fff));
+        assert!(!direct.set(5, 0x8000));
+        assert_eq!((direct.get(4), direct.get(5), direct.get(6)), (Some(4), Some(0x7fff), Some(6)));
+    }
+
+    #[test]
+    fn chunk_data_reads_sections_until_the_data_runs_out() {
+        let mut sections = Vec::new();
+        for block in [1, 0] {
+            sections.extend_from_slice(&(if block == 0 { 0i16 } else { 4096 }).to_be_bytes());
+            write_container(&mut sections, 0, &[block], &[]);
+            write_container(&mut sections, 0, &[3], &[]);
+        }
+        let mut heightmaps = Compound::default();
+        heightmaps.insert("MOTION_BLOCKING", Tag::LongArray(vec![0; 37]));
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(-2i32).to_be_bytes());
+        bytes.extend_from_slice(&5i32.to_be_bytes());
+        nbt::write_named(&mut bytes, "", &Tag::Compound(heightmaps));
+        write_var_int(&mut bytes, sections.len() as i32);
+        bytes.extend_from_slice(&sections);
+        write_var_int(&mut bytes, 1);
+        bytes.extend_from_slice(&[0x3a, 0, 64]);
+        write_var_int(&mut bytes, 7);
+        bytes.push(nbt::END);
+        // Light: trust edges, four empty masks, no arrays.
+        bytes.push(1);
+        bytes.extend_from_slice(&[0; 6]);
+
+        let chunk = Chunk::read(&mut Cursor::new(&bytes[..])).unwrap();
+        assert_eq!((chunk.x, chunk.z, chunk.sections.len()), (-2, 5, 2));
+        assert_eq!(chunk.block(0, 0, 0, 0), Some(1));
+        assert!(chunk.sections[1].is_empty());
+        assert_eq!(chunk.block_entities, vec![BlockEntity { x: 3, z: 10, y: 64, kind: 7, data: None }]);
+        assert!(chunk.heightmaps.get("MOTION_BLOCKING").is_some());
+        assert!(chunk.light.trust_edges);
+    }
+}
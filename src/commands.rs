@@ -0,0 +1,312 @@
+//! `/proxy` commands: typed by players in chat, answered by the proxy and never
+//! forwarded to the server.
+
+use crate::metrics::METRICS;
+use crate::packets::server::{CommandNode, CommandsPacket};
+use crate::session::Session;
+use crate::switch;
+use crate::text::Component;
+use crate::utils::Direction;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// The literal every proxy command starts with.
+const ROOT: &str = "proxy";
+
+const USAGE: &str = "§6/proxy stats§7: this connection's traffic\n§6/proxy ping§7: your latency\n§6/proxy server [name]§7: list servers or move to one";
+
+/// The proxy's own commands, shared by every session.
+pub struct Commands {
+    enabled: bool,
+    /// Backend addresses by name.
+    servers: BTreeMap<String, String>,
+    /// Where a player's next login goes, by lowercase name, after `/proxy server`.
+    next: Mutex<HashMap<String, String>>,
+}
+
+impl Commands {
+    pub fn new(enabled: bool, servers: BTreeMap<String, String>) -> Arc<Commands> {
+        Arc::new(Commands { enabled, servers, next: Mutex::new(HashMap::new()) })
+    }
+
+    /// Whether `command`, without its slash, is one for the proxy.
+    pub fn handles(&self, command: &str) -> bool {
+        self.enabled && command.split(' ').next() == Some(ROOT)
+    }
+
+    /// The backend `player` asked for with `/proxy server`, once.
+    pub fn take_next(&self, player: &str) -> Option<String> {
+        self.next.lock().unwrap().remove(&player.to_lowercase())
+    }
+
+    /// The name of the server at `address`, if it has one.
+    pub fn server_name(&self, address: &str) -> Option<&str> {
+        self.servers.iter().find(|(_, server)| *server == address).map(|(name, _)| name.as_str())
+    }
+
+    /// Runs a command `handles` accepted and answers the player in chat.
+    pub fn execute(self: &Arc<Self>, session: &Arc<Session>, command: &str) {
+        let mut words = command.split(' ').skip(1).filter(|word| !word.is_empty());
+        let (label, reply) = match (words.next(), words.next()) {
+            (Some("stats"), None) => ("stats", Some(stats(session))),
+            (Some("ping"), None) => ("ping", Some(ping(session))),
+            (Some("server"), None) => ("server", Some(self.list_servers(session))),
+            (Some("server"), Some(name)) => ("server", self.move_to(session, name)),
+            _ => ("help", Some(Component::from_legacy(USAGE))),
+        };
+        METRICS.commands.with_label_values(&[label]).inc();
+        let Some(reply) = reply else { return };
+        if let Err(e) = session.send_text(&reply) {
+            debug!(target: "commands", "Cannot answer /{} reason: {}", command, e);
+        }
+    }
+
+    fn list_servers(&self, session: &Session) -> Component {
+        if self.servers.is_empty() {
+            return Component::from_legacy("§7No servers are configured.");
+        }
+        let upstream = session.upstream();
+        let mut reply = Component::from_legacy(&format!("§7You are on §6{}§7. Servers:", self.server_name(&upstream).unwrap_or(&upstream)));
+        for name in self.servers.keys() {
+            let mut entry = Component::from_legacy(&format!(" §6{}", name));
+            entry.style.click_event = Some(json!({ "action": "run_command", "value": format!("/{} server {}", ROOT, name) }));
+            entry.style.hover_event = Some(json!({ "action": "show_text", "contents": format!("Move to {}", name) }));
+            reply.extra.push(entry);
+        }
+        reply
+    }
+
+    /// Moves the player to server `name` in place, or when that cannot be done, routes
+    /// their next login there and asks them to reconnect. Returns the reply; how the move
+    /// went is told once the login is through.
+    fn move_to(self: &Arc<Self>, session: &Arc<Session>, name: &str) -> Option<Component> {
+        let Some(address) = self.servers.get(name) else {
+            return Some(Component::from_legacy(&format!("§cThere is no server named {}.", name)));
+        };
+        if *address == session.upstream() {
+            return Some(Component::from_legacy(&format!("§7You are already on §6{}§7.", name)));
+        }
+        let moving = session.switch().moving_to().map(str::to_string);
+        if let Some(target) = moving {
+            return Some(Component::from_legacy(&format!("§7You are already moving to §6{}§7.", target)));
+        }
+        let commands = self.clone();
+        let (target, target_address) = (name.to_string(), address.clone());
+        switch::switch(session.clone(), name, address, move |session, result| {
+            let reply = match result {
+                Ok(()) => None,
+                Err(e) if e.kind() == ErrorKind::Unsupported => {
+                    info!(target: "commands", "Cannot move the player to {} in place, reason: {}", target, e);
+                    METRICS.server_switches.with_label_values(&["reconnect"]).inc();
+                    commands.reconnect_to(session, &target, &target_address)
+                }
+                Err(e) => {
+                    warn!(target: "commands", "Failed to move the player to {} ({}) reason: {}", target, target_address, e);
+                    METRICS.server_switches.with_label_values(&["failed"]).inc();
+                    Some(Component::from_legacy(&format!("§cCould not move you to {}: {}.", target, e)))
+                }
+            };
+            if let Some(reply) = reply {
+                if let Err(e) = session.send_text(&reply) {
+                    debug!(target: "commands", "Cannot tell the player about the move reason: {}", e);
+                }
+            }
+        });
+        Some(Component::from_legacy(&format!("§7Moving you to §6{}§7.", name)))
+    }
+
+    /// Routes the player's next login to `address` and disconnects them asking to
+    /// reconnect. Returns the reply when they stay.
+    fn reconnect_to(&self, session: &Session, name: &str, address: &str) -> Option<Component> {
+        let Some(player) = session.requested_name() else {
+            return Some(Component::from_legacy("§cYour name is not known, so the move cannot be remembered."));
+        };
+        info!(target: "commands", "Moving {} to {} ({}) on their next login", player, name, address);
+        self.next.lock().unwrap().insert(player.to_lowercase(), address.to_string());
+        let _ = session.kick(&format!("Reconnect to join {}.", name));
+        None
+    }
+
+    /// Adds the `/proxy` branch to the server's command graph, in place of any the
+    /// server has itself.
+    pub fn extend_tree(&self, packet: &mut CommandsPacket) {
+        if !self.enabled {
+            return;
+        }
+        let root = packet.root as usize;
+        let nodes = &mut packet.nodes;
+        let existing: Vec<i32> = nodes[root].children.iter().copied()
+            .filter(|child| nodes[*child as usize].kind() == 1 && nodes[*child as usize].name.as_deref() == Some(ROOT))
+            .collect();
+        nodes[root].children.retain(|child| !existing.contains(child));
+
+        let mut add = |node: CommandNode| {
+            nodes.push(node);
+            nodes.len() as i32 - 1
+        };
+        let names = self.servers.keys().map(|name| add(CommandNode::literal(name, true, Vec::new()))).collect();
+        let children = vec![
+            add(CommandNode::literal("stats", true, Vec::new())),
+            add(CommandNode::literal("ping", true, Vec::new())),
+            add(CommandNode::literal("server", true, names)),
+        ];
+        let proxy = add(CommandNode::literal(ROOT, true, children));
+        nodes[root].children.push(proxy);
+    }
+}
+
+fn stats(session: &Session) -> Component {
+    let stats = session.stats();
+    let (sent, received) = (stats.total(Direction::Serverbound), stats.total(Direction::Clientbound));
+    Component::from_legacy(&format!(
+        "§7Session §6{}§7, connected for §6{}§7 to §6{}§7.\nSent §6{}§7 packets (§6{}§7), received §6{}§7 packets (§6{}§7).",
+        session.id, duration(stats.elapsed()), session.upstream(),
+        sent.count, bytes(sent.wire_bytes), received.count, bytes(received.wire_bytes)))
+}
+
+fn ping(session: &Session) -> Component {
+    let latency = session.stats().latency;
+    let ms = |d: Duration| format!("{:.1}ms", d.as_secs_f64() * 1000.0);
+    let (Some(last), Some(average)) = (latency.last, latency.smoothed) else {
+        return Component::from_legacy("§7No round trip to the proxy measured yet; try again in a few seconds.");
+    };
+    let mut reply = format!("§7You ↔ proxy: §6{}§7 (average §6{}§7).", ms(last), ms(average));
+    if let Some(upstream) = latency.upstream() {
+        reply.push_str(&format!(" Proxy ↔ server: about §6{}§7.", ms(upstream)));
+    }
+    Component::from_legacy(&reply)
+}
+
+/// `1h 2m 3s`, leaving out leading zero units.
+fn duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, s) => format!("{}h {}m {}s", h, m, s),
+    }
+}
+
+fn bytes(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{} B", bytes),
+        1024..1048576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1048576.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impair::{Impairment, Link};
+    use crate::packets::server::SystemChatPacket;
+    use crate::packets::{Packet, PacketWrite};
+    use crate::text;
+    use crate::utils::{self, State};
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+    use tracing::Span;
+
+    fn commands(enabled: bool) -> Arc<Commands> {
+        let servers = [("lobby", "localhost:25566"), ("survival", "localhost:25568")];
+        Commands::new(enabled, servers.iter().map(|(name, address)| (name.to_string(), address.to_string())).collect())
+    }
+
+    /// A server graph with its root last: `tp <target>`, and a `proxy` of the server's own.
+    fn server_tree() -> CommandsPacket {
+        let mut target = CommandNode::literal("target", false, Vec::new());
+        target.flags = 0x02 | CommandNode::EXECUTABLE;
+        target.parser = Some((5, vec![0]));
+        let mut root = CommandNode::literal("", false, vec![1, 3]);
+        root.flags = 0;
+        root.name = None;
+        CommandsPacket {
+            nodes: vec![target, CommandNode::literal("tp", false, vec![0]), CommandNode::literal("help", true, Vec::new()),
+                        CommandNode::literal(ROOT, true, vec![2]), root],
+            root: 4,
+        }
+    }
+
+    fn names(packet: &CommandsPacket, children: &[i32]) -> Vec<String> {
+        children.iter().map(|child| packet.nodes[*child as usize].name.clone().unwrap_or_default()).collect()
+    }
+
+    #[test]
+    fn only_proxy_commands_are_handled() {
+        let commands = commands(true);
+        assert!(commands.handles("proxy") && commands.handles("proxy server lobby"));
+        assert!(!commands.handles("proxyx") && !commands.handles("tp proxy") && !commands.handles(""));
+        assert!(!self::commands(false).handles("proxy"));
+    }
+
+    #[test]
+    fn the_tree_gets_a_proxy_branch() {
+        let mut packet = server_tree();
+        commands(true).extend_tree(&mut packet);
+        let root = &packet.nodes[4];
+        // The server's own proxy is gone from the root; the new one is the last node.
+        assert_eq!(names(&packet, &root.children), ["tp", ROOT]);
+        let proxy = *root.children.last().unwrap();
+        assert_eq!(proxy as usize, packet.nodes.len() - 1);
+        assert_eq!(names(&packet, &packet.nodes[proxy as usize].children), ["stats", "ping", "server"]);
+        let server = packet.nodes[proxy as usize].children[2] as usize;
+        assert_eq!(names(&packet, &packet.nodes[server].children), ["lobby", "survival"]);
+        assert!(packet.nodes[5..].iter().all(|node| node.kind() == 1 && node.flags & CommandNode::EXECUTABLE != 0));
+
+        // The client reads it back the same, the server's argument included.
+        let bytes = packet.as_bytes();
+        let parsed = CommandsPacket::parse(&bytes[1..]).unwrap();
+        assert_eq!((parsed.nodes.len(), parsed.root), (packet.nodes.len(), 4));
+        for (parsed, node) in parsed.nodes.iter().zip(&packet.nodes) {
+            assert_eq!((parsed.flags, &parsed.children, &parsed.name, &parsed.parser), (node.flags, &node.children, &node.name, &node.parser));
+        }
+
+        let mut unchanged = server_tree();
+        commands(false).extend_tree(&mut unchanged);
+        assert_eq!(unchanged.nodes.len(), 5);
+        assert_eq!(unchanged.nodes[4].children, [1, 3]);
+    }
+
+    /// A session in Play whose clientbound frames can be read from the returned stream.
+    fn session() -> (Arc<Session>, Link, TcpStream) {
+        let session = Arc::new(Session::new(1, "127.0.0.1:1".parse().unwrap(), Impairment::default()));
+        session.set_state(State::Play);
+        session.set_upstream("localhost:25566");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let link = Link::spawn(TcpStream::connect(listener.local_addr().unwrap()).unwrap(), Span::none());
+        session.attach(Direction::Clientbound, link.injector());
+        (session, link, listener.accept().unwrap().0)
+    }
+
+    /// The plain text of the next System Chat sent to the client.
+    fn reply(client: &mut TcpStream) -> String {
+        let len = utils::read_var_int(client).unwrap();
+        let mut frame = Vec::new();
+        utils::write_var_int(&mut frame, len);
+        let start = frame.len();
+        frame.resize(start + len as usize, 0);
+        client.read_exact(&mut frame[start..]).unwrap();
+        let packet = utils::decode_frame(&frame, None).unwrap();
+        text::plain(&SystemChatPacket::parse(&packet.data).unwrap().content)
+    }
+
+    #[test]
+    fn commands_are_answered_in_chat() {
+        let commands = commands(true);
+        let (session, _link, mut client) = session();
+        commands.execute(&session, "proxy server lobby");
+        assert_eq!(reply(&mut client), "You are already on lobby.");
+        commands.execute(&session, "proxy server nowhere");
+        assert_eq!(reply(&mut client), "There is no server named nowhere.");
+        commands.execute(&session, "proxy server");
+        assert_eq!(reply(&mut client), "You are on lobby. Servers: lobby survival");
+        commands.execute(&session, "proxy  what");
+        assert!(reply(&mut client).starts_with("/proxy stats: "));
+        commands.execute(&session, "proxy ping");
+        assert!(reply(&mut client).starts_with("No round trip"));
+    }
+}
@@ -0,0 +1,118 @@
+use crate::access::AccessConfig;
+use crate::chat::ChatConfig;
+use crate::export::ExportConfig;
+use crate::impair::Impairment;
+use crate::limits::LimitsConfig;
+use crate::sanity::SanityConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// Read when no path is given on the command line and the file exists.
+const DEFAULT_PATH: &str = "proxy.toml";
+
+/// How often `watch` checks the file for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Proxy settings, loaded from a TOML file. Every key is optional.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Address players connect to.
+    pub listen: String,
+    /// Minecraft server every session is forwarded to.
+    pub upstream: String,
+    /// Backends players can pick with `/proxy server <name>`, by name.
+    pub servers: BTreeMap<String, String>,
+    /// Address of the Prometheus `/metrics` endpoint; disabled when unset.
+    pub metrics: Option<String>,
+    /// Address of the HTTP/JSON management API; disabled when unset.
+    pub api: Option<String>,
+    /// Simulated network conditions, reloaded when the file changes.
+    pub impairment: Impairment,
+    /// Allow and deny lists checked at login.
+    pub access: AccessConfig,
+    /// Anti-flood limits.
+    pub limits: LimitsConfig,
+    /// Enforce every length limit from the protocol spec and disconnect clients that
+    /// send malformed packets, instead of logging them and forwarding as-is.
+    pub strict: bool,
+    /// A Minecraft `en_us.json`, read at startup to translate keys the bundled table lacks.
+    pub lang: Option<String>,
+    /// Saving what players saw as singleplayer worlds.
+    pub export: ExportConfig,
+    /// Movement checks against modified clients.
+    pub sanity: SanityConfig,
+    /// Chat log and filter.
+    pub chat: ChatConfig,
+    /// Answer `/proxy` commands in the proxy and add them to players' command trees.
+    pub commands: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            listen: "0.0.0.0:25567".to_string(),
+            upstream: "localhost:25566".to_string(),
+            servers: BTreeMap::new(),
+            metrics: Some("127.0.0.1:9940".to_string()),
+            api: Some("127.0.0.1:9941".to_string()),
+            impairment: Impairment::default(),
+            access: AccessConfig::default(),
+            limits: LimitsConfig::default(),
+            strict: false,
+            lang: None,
+            export: ExportConfig::default(),
+            sanity: SanityConfig::default(),
+            chat: ChatConfig::default(),
+            commands: true,
+        }
+    }
+}
+
+impl Config {
+    /// The config file to use: `arg` if given, else `proxy.toml` if it exists.
+    pub fn path(arg: Option<String>) -> Option<String> {
+        arg.or_else(|| Path::new(DEFAULT_PATH).exists().then(|| DEFAULT_PATH.to_string()))
+    }
+
+    /// Loads `path`, or falls back to the defaults when there is none.
+    pub fn load(path: Option<&str>) -> Config {
+        match path {
+            Some(path) => Config::read(path).unwrap_or_else(|e| panic!("Invalid config {}: {}", path, e)),
+            None => Config::default(),
+        }
+    }
+
+    pub fn read(path: &str) -> Result<Config, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        toml::from_str(&text).map_err(|e| e.to_string())
+    }
+}
+
+/// Where new sessions are forwarded. Starts from the config file and can be
+/// replaced at runtime; running sessions keep their server.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Routing {
+    pub upstream: String,
+}
+
+/// Calls `on_change` from a background thread whenever `path`'s modification time changes.
+pub fn watch(path: String, on_change: impl Fn() + Send + 'static) {
+    thread::spawn(move || {
+        let modified = || fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+        let mut last = modified();
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+            let now = modified();
+            if now != last {
+                last = now;
+                on_change();
+            }
+        }
+    });
+}
@@ -0,0 +1,212 @@
+use crate::entity_tracker::{Entity, EntityKind};
+use crate::export::Exporter;
+use crate::impair::Impairment;
+use crate::logging;
+use crate::session::Sessions;
+use crate::text::Component;
+use crate::utils::Position;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use tracing::{info, warn};
+
+/// Corrections listed by `movement`.
+const RECENT_CORRECTIONS: usize = 5;
+
+const HELP: &str = "\
+commands:
+  list                          sessions with player, uuid, state and address
+  kick <session> [message]      disconnect a player
+  inspect <session> on|off      log Play packets and publish their fields, or not
+  say <session> <message>       show a chat message to a player
+  impair <session> [json]       show or replace a player's [impairment], as JSON
+  world <session>               dimension and loaded chunks of a player's world
+  block <session> <x> <y> <z>   block state id at a position in a player's world
+  entities <session>            entities a player's client knows about
+  movement <session>            a player's position, distance travelled and recent corrections
+  path <session> <file>         save a player's path as CSV, or JSON if the file ends in .json
+  export <session> [dir]        save a player's world as a singleplayer world
+  log [filter]                  show or replace the log filter (RUST_LOG syntax)
+  help
+<session> is a session id or a player name.";
+
+/// Reads admin commands from stdin on a background thread.
+pub fn spawn(sessions: Arc<Sessions>, exporter: Arc<Exporter>) {
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            match line {
+                Ok(line) => println!("{}", execute(&sessions, &exporter, line.trim())),
+                Err(e) => {
+                    warn!("Console stopped reason: {}", e);
+                    return;
+                }
+            }
+        }
+    });
+}
+
+/// Runs one command line and returns the text to show.
+pub fn execute(sessions: &Sessions, exporter: &Exporter, line: &str) -> String {
+    let (command, args) = line.split_once(' ').map_or((line, ""), |(command, args)| (command, args.trim()));
+    let (target, rest) = args.split_once(' ').map_or((args, ""), |(target, rest)| (target, rest.trim()));
+    match command {
+        "" => String::new(),
+        "help" => HELP.to_string(),
+        "list" => {
+            let mut out = format!("{:<5} {:<16} {:<36} {:<9} {:<21} {:>9}", "id", "player", "uuid", "state", "address", "time");
+            for session in sessions.list() {
+                let (player, uuid) = session.player().unwrap_or_default();
+                out.push_str(&format!("\n{:<5} {:<16} {:<36} {:<9} {:<21} {:>8.0}s",
+                                      session.id, player, uuid.map(|uuid| uuid.to_string()).unwrap_or_default(),
+                                      format!("{:?}", session.state()), session.client_addr.to_string(),
+                                      session.stats().elapsed().as_secs_f64()));
+            }
+            out
+        }
+        "kick" | "inspect" | "say" => {
+            let Some(session) = sessions.find(target) else {
+                return format!("no session {:?}", target);
+            };
+            let result = match command {
+                "kick" => session.kick(if rest.is_empty() { "Kicked by the proxy" } else { rest })
+                    .map(|_| format!("kicked session {}", session.id)),
+                "say" if rest.is_empty() => return "usage: say <session> <message>".to_string(),
+                "say" => session.send_chat(rest).map(|_| format!("sent to session {}: {}", session.id, render(&Component::from_legacy(rest)))),
+                _ => match rest {
+                    "on" | "off" => {
+                        session.set_inspecting(rest == "on");
+                        Ok(format!("inspecting {} for session {}", rest, session.id))
+                    }
+                    _ => return "usage: inspect <session> on|off".to_string(),
+                },
+            };
+            match result {
+                Ok(done) => {
+                    info!("Console: {}", line);
+                    done
+                }
+                Err(e) => format!("{} failed: {}", command, e),
+            }
+        }
+        "impair" => {
+            let Some(session) = sessions.find(target) else {
+                return format!("no session {:?}", target);
+            };
+            if rest.is_empty() {
+                return session.impairment().to_string();
+            }
+            match serde_json::from_str::<Impairment>(rest) {
+                Ok(impairment) => {
+                    session.set_impairment(impairment);
+                    info!("Console: {}", line);
+                    format!("session {}: {}", session.id, impairment)
+                }
+                Err(e) => format!("invalid impairment: {}", e),
+            }
+        }
+        "world" | "block" => {
+            let Some(session) = sessions.find(target) else {
+                return format!("no session {:?}", target);
+            };
+            let world = session.world();
+            if command == "world" {
+                return world.summary();
+            }
+            let coords: Vec<i32> = rest.split_whitespace().filter_map(|coord| coord.parse().ok()).collect();
+            let [x, y, z] = coords[..] else {
+                return "usage: block <session> <x> <y> <z>".to_string();
+            };
+            let position = Position { x, y, z };
+            match world.block_at(position) {
+                Some(id) => match world.block_entity_at(position) {
+                    Some(entity) => format!("{}: {} with block entity {} {}", position, id, entity.kind,
+                                            entity.data.as_ref().map(|data| data.to_string()).unwrap_or_default()),
+                    None => format!("{}: {}", position, id),
+                },
+                None => format!("{}: not loaded", position),
+            }
+        }
+        "entities" => {
+            let Some(session) = sessions.find(target) else {
+                return format!("no session {:?}", target);
+            };
+            let entities = session.entities();
+            let mut list: Vec<&Entity> = entities.iter().collect();
+            list.sort_by_key(|entity| entity.id);
+            let mut out = format!("{} entities, own id {}", list.len(), entities.own_id.map(|id| id.to_string()).unwrap_or_else(|| "unknown".to_string()));
+            for entity in list {
+                let kind = match entity.kind {
+                    EntityKind::Entity(id) => format!("type {}", id),
+                    EntityKind::Player => "player".to_string(),
+                    EntityKind::ExperienceOrb { count } => format!("xp orb {}", count),
+                };
+                out.push_str(&format!("\n{:<8} {:<12} {:>10.2} {:>8.2} {:>10.2} {} metadata, {} equipment",
+                                      entity.id, kind, entity.x, entity.y, entity.z, entity.metadata.len(), entity.equipment.len()));
+            }
+            out
+        }
+        "movement" => {
+            let Some(session) = sessions.find(target) else {
+                return format!("no session {:?}", target);
+            };
+            let movement = session.movement();
+            let mut out = movement.summary();
+            let corrections: Vec<_> = movement.corrections().collect();
+            for correction in corrections.iter().skip(corrections.len().saturating_sub(RECENT_CORRECTIONS)) {
+                let (x, y, z) = correction.to;
+                out.push_str(&format!("\n{:?} {:.2} blocks to {:.2},{:.2},{:.2} teleport {} undone {} ignored {}{}",
+                                      correction.cause, correction.distance, x, y, z, correction.teleport_id,
+                                      correction.moves_undone, correction.moves_ignored,
+                                      if correction.confirmed { "" } else { " unconfirmed" }));
+            }
+            out
+        }
+        "path" => {
+            let Some(session) = sessions.find(target) else {
+                return format!("no session {:?}", target);
+            };
+            if rest.is_empty() {
+                return "usage: path <session> <file>".to_string();
+            }
+            let (contents, points) = {
+                let movement = session.movement();
+                let contents = if rest.ends_with(".json") {
+                    serde_json::to_string(&movement.path().collect::<Vec<_>>()).unwrap_or_default()
+                } else {
+                    movement.path_csv()
+                };
+                (contents, movement.path().count())
+            };
+            match fs::write(rest, contents) {
+                Ok(()) => format!("wrote {} points to {}", points, rest),
+                Err(e) => format!("path failed: {}", e),
+            }
+        }
+        "export" => {
+            let Some(session) = sessions.find(target) else {
+                return format!("no session {:?}", target);
+            };
+            let dir = if rest.is_empty() { exporter.default_dir(&session) } else { Path::new(rest).to_path_buf() };
+            match exporter.export(&session, &dir) {
+                Ok(summary) => {
+                    info!("Console: exported {}", summary);
+                    format!("exported {}", summary)
+                }
+                Err(e) => format!("export failed: {}", e),
+            }
+        }
+        "log" if args.is_empty() => logging::filter(),
+        "log" => match logging::set_filter(args) {
+            Ok(()) => format!("log filter set to {}", args),
+            Err(e) => format!("invalid filter: {}", e),
+        },
+        _ => format!("unknown command {:?}, try help", command),
+    }
+}
+
+/// A text component in color when stdout is a terminal, as plain text otherwise.
+fn render(text: &Component) -> String {
+    if io::stdout().is_terminal() { text.to_ansi() } else { text.to_plain() }
+}
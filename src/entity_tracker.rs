@@ -0,0 +1,188 @@
+use crate::metadata::{MetadataValue, Slot};
+use crate::packets::server::{SetEntityMetadataPacket, SetEquipmentPacket, SpawnEntityPacket, SpawnExperienceOrbPacket, SpawnPlayerPacket,
+                             TeleportEntityPacket, UpdateEntityPacket};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+use uuid::Uuid;
+
+/// Velocities are sent in 1/8000 block per tick.
+const VELOCITY_UNIT: f64 = 8000.0;
+/// Relative moves are sent in 1/4096 block.
+const MOVE_UNIT: f64 = 4096.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntityKind {
+    /// Id in the `minecraft:entity_type` registry.
+    Entity(i32),
+    Player,
+    ExperienceOrb { count: i16 },
+}
+
+/// What the client knows about one entity. Angles are in degrees.
+#[derive(Clone, Debug)]
+pub struct Entity {
+    pub id: i32,
+    /// Experience orbs have none.
+    pub uuid: Option<Uuid>,
+    pub kind: EntityKind,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub head_yaw: f32,
+    /// In blocks per tick.
+    pub velocity: [f64; 3],
+    pub on_ground: bool,
+    /// Spawn Entity's type-specific data.
+    pub data: i32,
+    pub metadata: BTreeMap<u8, MetadataValue>,
+    /// Items by `metadata::EQUIPMENT_SLOTS` index; empty slots are left out.
+    pub equipment: BTreeMap<u8, Slot>,
+    pub spawned: Instant,
+    pub updated: Instant,
+}
+
+impl Entity {
+    fn new(id: i32, uuid: Option<Uuid>, kind: EntityKind, (x, y, z): (f64, f64, f64)) -> Entity {
+        let now = Instant::now();
+        Entity {
+            id, uuid, kind, x, y, z,
+            yaw: 0.0,
+            pitch: 0.0,
+            head_yaw: 0.0,
+            velocity: [0.0; 3],
+            on_ground: false,
+            data: 0,
+            metadata: BTreeMap::new(),
+            equipment: BTreeMap::new(),
+            spawned: now,
+            updated: now,
+        }
+    }
+}
+
+fn velocity(raw: [i16; 3]) -> [f64; 3] {
+    raw.map(|axis| axis as f64 / VELOCITY_UNIT)
+}
+
+/// The entities a session's client has been told about, by entity id. Packets for
+/// entities spawned before the session was inspected are ignored; the update methods
+/// return false for them.
+#[derive(Default)]
+pub struct Entities {
+    /// The player's own entity, from Login. The client is never sent a spawn for it.
+    pub own_id: Option<i32>,
+    entities: HashMap<i32, Entity>,
+}
+
+impl Entities {
+    pub fn new() -> Entities {
+        Entities::default()
+    }
+
+    pub fn login(&mut self, own_id: i32) {
+        self.own_id = Some(own_id);
+        self.entities.clear();
+    }
+
+    /// Forgets every entity, as the client does when it changes dimension.
+    pub fn clear(&mut self) {
+        self.entities.clear();
+    }
+
+    pub fn get(&self, id: i32) -> Option<&Entity> {
+        self.entities.get(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Entity> {
+        self.entities.values()
+    }
+
+    pub fn spawn_entity(&mut self, spawn: &SpawnEntityPacket) {
+        let mut entity = Entity::new(spawn.entity_id, Some(spawn.uuid), EntityKind::Entity(spawn.kind), (spawn.x, spawn.y, spawn.z));
+        entity.yaw = spawn.yaw;
+        entity.pitch = spawn.pitch;
+        entity.head_yaw = spawn.head_yaw;
+        entity.data = spawn.data;
+        entity.velocity = velocity(spawn.velocity);
+        self.entities.insert(entity.id, entity);
+    }
+
+    pub fn spawn_player(&mut self, spawn: &SpawnPlayerPacket) {
+        let mut entity = Entity::new(spawn.entity_id, Some(spawn.uuid), EntityKind::Player, (spawn.x, spawn.y, spawn.z));
+        entity.yaw = spawn.yaw;
+        entity.pitch = spawn.pitch;
+        entity.head_yaw = spawn.yaw;
+        self.entities.insert(entity.id, entity);
+    }
+
+    pub fn spawn_experience_orb(&mut self, spawn: &SpawnExperienceOrbPacket) {
+        let entity = Entity::new(spawn.entity_id, None, EntityKind::ExperienceOrb { count: spawn.count }, (spawn.x, spawn.y, spawn.z));
+        self.entities.insert(entity.id, entity);
+    }
+
+    /// Returns how many of `ids` were known.
+    pub fn remove(&mut self, ids: &[i32]) -> usize {
+        ids.iter().filter(|id| self.entities.remove(id).is_some()).count()
+    }
+
+    fn update(&mut self, id: i32, apply: impl FnOnce(&mut Entity)) -> bool {
+        match self.entities.get_mut(&id) {
+            Some(entity) => {
+                apply(entity);
+                entity.updated = Instant::now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Applies one of the relative move and rotation packets.
+    pub fn move_entity(&mut self, update: &UpdateEntityPacket) -> bool {
+        self.update(update.entity_id, |entity| {
+            if let Some([dx, dy, dz]) = update.delta {
+                entity.x += dx as f64 / MOVE_UNIT;
+                entity.y += dy as f64 / MOVE_UNIT;
+                entity.z += dz as f64 / MOVE_UNIT;
+            }
+            if let Some((yaw, pitch)) = update.rotation {
+                entity.yaw = yaw;
+                entity.pitch = pitch;
+            }
+            entity.on_ground = update.on_ground;
+        })
+    }
+
+    pub fn teleport(&mut self, teleport: &TeleportEntityPacket) -> bool {
+        self.update(teleport.entity_id, |entity| {
+            (entity.x, entity.y, entity.z) = (teleport.x, teleport.y, teleport.z);
+            entity.yaw = teleport.yaw;
+            entity.pitch = teleport.pitch;
+            entity.on_ground = teleport.on_ground;
+        })
+    }
+
+    pub fn set_head_yaw(&mut self, id: i32, head_yaw: f32) -> bool {
+        self.update(id, |entity| entity.head_yaw = head_yaw)
+    }
+
+    pub fn set_velocity(&mut self, id: i32, raw: [i16; 3]) -> bool {
+        self.update(id, |entity| entity.velocity = velocity(raw))
+    }
+
+    pub fn set_metadata(&mut self, packet: SetEntityMetadataPacket) -> bool {
+        self.update(packet.entity_id, |entity| entity.metadata.extend(packet.metadata))
+    }
+
+    pub fn set_equipment(&mut self, packet: SetEquipmentPacket) -> bool {
+        self.update(packet.entity_id, |entity| {
+            for (slot, item) in packet.equipment {
+                match item {
+                    Some(item) => entity.equipment.insert(slot, item),
+                    None => entity.equipment.remove(&slot),
+                };
+            }
+        })
+    }
+}
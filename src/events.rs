@@ -0,0 +1,214 @@
+use crate::packets::names::packet_name;
+use crate::utils::{Direction, RawPacket, State};
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use crate::metrics::METRICS;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many events `recent` keeps, across all sessions.
+const RECENT_EVENTS: usize = 1000;
+/// How many events a subscriber may fall behind by before new ones are dropped for it.
+const SUBSCRIBER_BUFFER: usize = 1024;
+
+/// One forwarded packet, as shown to API and inspector clients.
+#[derive(Debug, Serialize)]
+pub struct PacketEvent {
+    pub session: u64,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub direction: String,
+    pub state: String,
+    pub id: i32,
+    pub name: &'static str,
+    /// Bytes on the wire, and of id + data before compression.
+    pub wire_size: usize,
+    pub size: usize,
+    /// The parsed fields, for packets the proxy understands.
+    pub fields: Option<String>,
+    /// Why the packet could not be parsed, for packets the proxy understands.
+    pub error: Option<String>,
+}
+
+impl PacketEvent {
+    pub fn new(session: u64, direction: Direction, state: State, packet: &RawPacket, fields: Option<String>, error: Option<String>) -> PacketEvent {
+        PacketEvent {
+            session,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_millis() as u64),
+            direction: direction.to_string(),
+            state: format!("{:?}", state),
+            id: packet.id,
+            name: packet_name(state, direction, packet.id),
+            wire_size: packet.wire_len,
+            size: packet.uncompressed_len,
+            fields,
+            error,
+        }
+    }
+}
+
+/// Fans packet events out to subscribers and keeps the latest ones around.
+pub struct Events {
+    /// Set once something asked for `recent`; until then no history is kept.
+    keep_recent: AtomicBool,
+    recent: Mutex<VecDeque<Arc<PacketEvent>>>,
+    /// Length of `subscribers`, read without taking the lock.
+    subscriber_count: AtomicUsize,
+    subscribers: Mutex<Vec<SyncSender<Arc<PacketEvent>>>>,
+}
+
+pub static EVENTS: LazyLock<Events> = LazyLock::new(Events::new);
+
+impl Events {
+    fn new() -> Events {
+        Events {
+            keep_recent: AtomicBool::new(false),
+            recent: Mutex::new(VecDeque::with_capacity(RECENT_EVENTS)),
+            subscriber_count: AtomicUsize::new(0),
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Starts keeping the latest events for `recent`, from now on.
+    pub fn keep_recent(&self) {
+        self.keep_recent.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether published events go anywhere. When not, frames skip building them.
+    pub fn listening(&self) -> bool {
+        self.keep_recent.load(Ordering::Relaxed) || self.subscriber_count.load(Ordering::Relaxed) > 0
+    }
+
+    pub fn publish(&self, event: PacketEvent) {
+        let event = Arc::new(event);
+        if self.keep_recent.load(Ordering::Relaxed) {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() == RECENT_EVENTS {
+                recent.pop_front();
+            }
+            recent.push_back(event.clone());
+        }
+        if self.subscriber_count.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let mut subscribers = self.subscribers.lock().unwrap();
+        // A subscriber that cannot keep up misses events; one that went away is dropped.
+        subscribers.retain(|subscriber| match subscriber.try_send(event.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                METRICS.events_dropped.inc();
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        });
+        self.subscriber_count.store(subscribers.len(), Ordering::Relaxed);
+    }
+
+    /// Up to `limit` of the latest events, oldest first, optionally for one session.
+    pub fn recent(&self, session: Option<u64>, limit: usize) -> Vec<Arc<PacketEvent>> {
+        let recent = self.recent.lock().unwrap();
+        let mut events: Vec<_> = recent.iter().rev()
+            .filter(|event| session.is_none_or(|session| event.session == session))
+            .take(limit)
+            .cloned()
+            .collect();
+        events.reverse();
+        events
+    }
+
+    /// Every event published from now on, until the receiver is dropped. Events are
+    /// dropped rather than queued once the receiver is `SUBSCRIBER_BUFFER` behind.
+    pub fn subscribe(&self) -> Receiver<Arc<PacketEvent>> {
+        let (tx, rx) = mpsc::sync_channel(SUBSCRIBER_BUFFER);
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.push(tx);
+        self.subscriber_count.store(subscribers.len(), Ordering::Relaxed);
+        rx
+    }
+}
+
+thread_local! {
+    /// Whether the frame being handled on this thread is logged and its fields kept; off for
+    /// Play frames of sessions not being inspected.
+    static INSPECTING: Cell<bool> = const { Cell::new(true) };
+    /// Fields parsed from the frame being handled on this thread; each direction runs on its own thread.
+    static FIELDS: RefCell<Option<String>> = const { RefCell::new(None) };
+    /// Why the frame being handled on this thread failed to parse.
+    static ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+pub fn set_inspecting(inspecting: bool) {
+    INSPECTING.with(|current| current.set(inspecting));
+}
+
+/// Read by `decode!`, which only logs and notes fields of frames being inspected.
+pub fn inspecting() -> bool {
+    INSPECTING.with(Cell::get)
+}
+
+/// Called by `decode!` with the parsed fields of the current frame.
+pub fn note_fields(fields: String) {
+    FIELDS.with(|current| *current.borrow_mut() = Some(fields));
+}
+
+pub fn take_fields() -> Option<String> {
+    FIELDS.with(|current| current.borrow_mut().take())
+}
+
+/// Called by `decode!` when the current frame fails to parse.
+pub fn note_error(error: String) {
+    ERROR.with(|current| *current.borrow_mut() = Some(error));
+}
+
+pub fn take_error() -> Option<String> {
+    ERROR.with(|current| current.borrow_mut().take())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+
+    fn event(session: u64) -> PacketEvent {
+        let packet = RawPacket { id: 0, data: Cow::Borrowed(&[]), wire_len: 2, uncompressed_len: 1 };
+        PacketEvent::new(session, Direction::Serverbound, State::Play, &packet, None, None)
+    }
+
+    #[test]
+    fn nothing_is_kept_without_listeners() {
+        let events = Events::new();
+        assert!(!events.listening());
+        events.publish(event(1));
+        assert!(events.recent(None, 10).is_empty());
+        events.keep_recent();
+        assert!(events.listening());
+        events.publish(event(2));
+        let recent = events.recent(None, 10);
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].session, 2);
+    }
+
+    #[test]
+    fn slow_subscribers_miss_events() {
+        let events = Events::new();
+        let receiver = events.subscribe();
+        assert!(events.listening());
+        let dropped = METRICS.events_dropped.get();
+        for _ in 0..SUBSCRIBER_BUFFER + 5 {
+            events.publish(event(1));
+        }
+        assert!(METRICS.events_dropped.get() >= dropped + 5);
+        assert_eq!(receiver.try_iter().count(), SUBSCRIBER_BUFFER);
+    }
+
+    #[test]
+    fn gone_subscribers_are_dropped() {
+        let events = Events::new();
+        drop(events.subscribe());
+        events.publish(event(1));
+        assert!(!events.listening());
+    }
+}
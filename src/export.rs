@@ -0,0 +1,112 @@
+use crate::anvil::{self, ChunkConverter, Level, Reports, Unknown};
+use crate::session::Session;
+use crate::utils::Position;
+use crate::world::World;
+use serde::Deserialize;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Exported worlds open in creative mode, for looking around.
+const CREATIVE: i32 = 1;
+
+/// Directory worlds are exported to from the console when `dir` is not set.
+const DEFAULT_DIR: &str = "worlds";
+
+/// Saving what a player saw as a singleplayer world.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExportConfig {
+    /// Reports directory of the vanilla data generator for the server's version; block
+    /// and block entity names come from it. Exports fail without it.
+    pub reports: Option<String>,
+    /// When set, every session's world is exported to `<dir>/<player>-<session id>`
+    /// when the session ends.
+    pub dir: Option<String>,
+}
+
+/// Writes session worlds as Anvil worlds.
+pub struct Exporter {
+    config: ExportConfig,
+    reports: Option<Reports>,
+}
+
+impl Exporter {
+    pub fn new(config: ExportConfig) -> Arc<Exporter> {
+        let reports = config.reports.as_ref().and_then(|dir| match Reports::load(Path::new(dir)) {
+            Ok(reports) => Some(reports),
+            Err(e) => {
+                warn!("Cannot load data generator reports, worlds will not be exported reason: {}", e);
+                None
+            }
+        });
+        Arc::new(Exporter { config, reports })
+    }
+
+    /// Where `export` writes the session's world unless told otherwise.
+    pub fn default_dir(&self, session: &Session) -> PathBuf {
+        let name: String = session.player().map(|(name, _)| name).unwrap_or_default().chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .collect();
+        let name = if name.is_empty() { "session".to_string() } else { name };
+        Path::new(self.config.dir.as_deref().unwrap_or(DEFAULT_DIR)).join(format!("{}-{}", name, session.id))
+    }
+
+    /// Exports the world of a session that ended, when `dir` is set and it loaded any chunks.
+    pub fn session_ended(&self, session: &Session) {
+        if self.config.dir.is_none() || session.world().chunks().next().is_none() {
+            return;
+        }
+        match self.export(session, &self.default_dir(session)) {
+            Ok(summary) => info!("Exported world: {}", summary),
+            Err(e) => warn!("Failed to export world reason: {}", e),
+        }
+    }
+
+    /// Writes the chunks the session's client has loaded to a world in `dir`, replacing
+    /// region files that are already there. Returns what was written.
+    pub fn export(&self, session: &Session, dir: &Path) -> io::Result<String> {
+        let reports = self.reports.as_ref()
+            .ok_or_else(|| io::Error::new(ErrorKind::Unsupported, "no data generator reports, set export.reports"))?;
+        // Converted under the lock, written after it so the session is not held up.
+        let (dimension, chunks, spawn, unknown) = {
+            let world = session.world();
+            let mut converter = ChunkConverter { reports, biomes: &world.biomes, min_y: world.min_y, unknown: Unknown::default() };
+            let chunks: Vec<_> = world.chunks().map(|chunk| ((chunk.x, chunk.z), converter.convert(chunk))).collect();
+            (world.dimension.clone(), chunks, spawn(&world, reports), converter.unknown)
+        };
+        if chunks.is_empty() {
+            return Err(io::Error::new(ErrorKind::NotFound, "no chunks loaded"));
+        }
+        let count = chunks.len();
+        let regions = anvil::write_regions(&anvil::dimension_dir(dir, &dimension).join("region"), chunks)?;
+        let name = dir.file_name().map_or_else(|| "Proxy export".to_string(), |name| name.to_string_lossy().into_owned());
+        fs::write(dir.join("level.dat"), anvil::level_dat(&Level { name, spawn, game_type: CREATIVE }))?;
+        let mut summary = format!("{} chunks of {} in {} region files to {}", count, dimension, regions, dir.display());
+        if unknown.block_states + unknown.biomes + unknown.block_entities > 0 {
+            summary.push_str(&format!(" (unknown ids: {} block states written as air, {} biomes as plains, {} block entities left out)",
+                                      unknown.block_states, unknown.biomes, unknown.block_entities));
+        }
+        Ok(summary)
+    }
+}
+
+/// On top of the highest block in the middle of the chunk nearest the centre of the loaded area.
+fn spawn(world: &World, reports: &Reports) -> Position {
+    let loaded: Vec<(i32, i32)> = world.chunks().map(|chunk| (chunk.x, chunk.z)).collect();
+    let count = loaded.len().max(1) as i64;
+    let centre_x = loaded.iter().map(|(x, _)| *x as i64).sum::<i64>() / count;
+    let centre_z = loaded.iter().map(|(_, z)| *z as i64).sum::<i64>() / count;
+    let (chunk_x, chunk_z) = loaded.iter()
+        .min_by_key(|(x, z)| (*x as i64 - centre_x).pow(2) + (*z as i64 - centre_z).pow(2))
+        .copied()
+        .unwrap_or_default();
+    let (x, z) = (chunk_x * 16 + 8, chunk_z * 16 + 8);
+    let is_air = |id: i32| matches!(reports.block_name(id), Some("minecraft:air" | "minecraft:cave_air" | "minecraft:void_air"));
+    let y = (world.min_y..world.min_y + world.height).rev()
+        .find(|y| world.block_at(Position { x, y: *y, z }).is_some_and(|id| !is_air(id)))
+        .map_or(world.min_y + world.height / 2, |y| y + 1);
+    Position { x, y, z }
+}
@@ -0,0 +1,147 @@
+use crate::utils::FieldError;
+use std::fmt::Write;
+use std::io::Error;
+
+const BYTES_PER_ROW: usize = 16;
+/// Rows printed from the start of a packet before the dump is cut short.
+const MAX_ROWS: usize = 32;
+/// Rows kept on each side of a mark that falls past the cut.
+const CONTEXT_ROWS: usize = 2;
+/// Width of the `00000000  ` offset column.
+const OFFSET_WIDTH: usize = 10;
+
+/// A byte offset to point at in a dump, with a note printed next to the pointer.
+pub struct Mark {
+    pub offset: usize,
+    pub note: String,
+}
+
+/// Dumps `bytes` as hex + ASCII, sixteen bytes per row. With a mark, the row holding
+/// the marked offset is followed by a `^^` pointer under that byte and the note.
+pub fn dump(bytes: &[u8], mark: Option<Mark>) -> String {
+    let data_rows = bytes.len().div_ceil(BYTES_PER_ROW);
+    // A mark may sit one past the last byte when a read ran off the end.
+    let mark_row = mark.as_ref().map(|m| m.offset / BYTES_PER_ROW);
+    let rows = data_rows.max(mark_row.map_or(0, |r| r + 1)).max(1);
+
+    let mut out = String::new();
+    let _ = write!(out, "{} bytes", bytes.len());
+    let mut printed = 0;
+    for r in 0..rows.min(MAX_ROWS) {
+        write_row(&mut out, bytes, r, mark.as_ref());
+        printed = r + 1;
+    }
+    if let Some(row) = mark_row.filter(|row| *row >= MAX_ROWS) {
+        let from = (row - CONTEXT_ROWS).max(MAX_ROWS);
+        if from > printed {
+            out.push_str("\n...");
+        }
+        for r in from..(row + CONTEXT_ROWS + 1).min(rows) {
+            write_row(&mut out, bytes, r, mark.as_ref());
+            printed = r + 1;
+        }
+    }
+    let shown = (printed * BYTES_PER_ROW).min(bytes.len());
+    if shown < bytes.len() {
+        let _ = write!(out, "\n... ({} more bytes)", bytes.len() - shown);
+    }
+    out
+}
+
+/// Dumps `bytes` and, when `e` came from `read_field`, marks where the failed read
+/// started and which field it was reading.
+pub fn dump_error(bytes: &[u8], e: &Error) -> String {
+    let mark = FieldError::of(e).map(|fe| Mark {
+        offset: fe.offset as usize,
+        note: format!("offset {:#06x}: reading {} ({})", fe.offset, fe.field, fe.source),
+    });
+    dump(bytes, mark)
+}
+
+fn write_row(out: &mut String, bytes: &[u8], row: usize, mark: Option<&Mark>) {
+    let start = row * BYTES_PER_ROW;
+    let chunk = &bytes[start.min(bytes.len())..(start + BYTES_PER_ROW).min(bytes.len())];
+
+    let _ = write!(out, "\n{:08x}  ", start);
+    for i in 0..BYTES_PER_ROW {
+        match chunk.get(i) {
+            Some(b) => { let _ = write!(out, "{:02x} ", b); }
+            None => out.push_str("   "),
+        }
+        if i == BYTES_PER_ROW / 2 - 1 {
+            out.push(' ');
+        }
+    }
+    out.push_str(" |");
+    out.extend(chunk.iter().map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' }));
+    out.push('|');
+
+    if let Some(m) = mark.filter(|m| m.offset / BYTES_PER_ROW == row) {
+        let col = m.offset % BYTES_PER_ROW;
+        let pad = OFFSET_WIDTH + col * 3 + usize::from(col >= BYTES_PER_ROW / 2);
+        let _ = write!(out, "\n{:pad$}^^ {}", "", m.note, pad = pad);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{read_field, read_var_int};
+    use std::io::Cursor;
+
+    /// Bytes whose value is their offset, so every one shows up as a distinct hex pair.
+    fn counting(len: usize) -> Vec<u8> {
+        (0..len).map(|offset| offset as u8).collect()
+    }
+
+    /// The dump of a failed VarInt read at `offset`.
+    fn failed_read(bytes: &[u8], offset: usize) -> String {
+        let mut cr = Cursor::new(bytes);
+        cr.set_position(offset as u64);
+        let e = read_field(&mut cr, "length", |cr| read_var_int(cr)).unwrap_err();
+        dump_error(bytes, &e)
+    }
+
+    /// The column of the `^^` pointer and the row line above it.
+    fn pointer(dump: &str) -> (usize, &str) {
+        let lines: Vec<&str> = dump.lines().collect();
+        let at = lines.iter().position(|line| line.contains("^^")).unwrap();
+        (lines[at].find("^^").unwrap(), lines[at - 1])
+    }
+
+    #[test]
+    fn the_pointer_sits_under_the_marked_byte() {
+        // All bytes have their top bit set, so the VarInt runs on and off the end.
+        let bytes: Vec<u8> = counting(48).iter().map(|byte| byte | 0x80).collect();
+        for offset in [0, 7, 8, 15, 16, 35, 47] {
+            let dump = failed_read(&bytes, offset);
+            let (column, row) = pointer(&dump);
+            assert!(row.starts_with(&format!("{:08x}", offset / 16 * 16)), "{}", dump);
+            assert_eq!(&row[column..column + 2], format!("{:02x}", bytes[offset]), "{}", dump);
+        }
+        assert!(failed_read(&bytes, 35).ends_with("^^ offset 0x0023: reading length (Number too big)"));
+    }
+
+    #[test]
+    fn a_read_off_the_end_points_past_the_last_byte() {
+        let bytes = counting(16);
+        let dump = failed_read(&bytes, 16);
+        let (column, row) = pointer(&dump);
+        assert!(row.starts_with("00000010 "));
+        assert_eq!(column, OFFSET_WIDTH);
+    }
+
+    #[test]
+    fn marks_past_the_cut_are_shown_with_their_rows() {
+        let bytes = counting(MAX_ROWS * 16 + 200);
+        let offset = MAX_ROWS * 16 + 100;
+        let dump = dump(&bytes, Some(Mark { offset, note: "here".to_string() }));
+        let (column, row) = pointer(&dump);
+        assert_eq!(&row[column..column + 2], format!("{:02x}", bytes[offset]));
+        assert!(dump.contains(&format!("\n...\n{:08x}", offset / 16 * 16 - 2 * 16)), "{}", dump);
+        // Two rows after the mark's, up to 0x290.
+        assert!(dump.ends_with("\n... (56 more bytes)"), "{}", dump);
+        // Without a mark only the first rows are shown.
+        assert_eq!(dump_error(&bytes, &Error::other("no field")).lines().count(), 1 + MAX_ROWS + 1);
+    }
+}
@@ -0,0 +1,272 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, ErrorKind, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, Span};
+use crate::utils::Direction;
+
+/// Simulated network conditions for one direction. The default adds nothing.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkImpairment {
+    /// Delay added to every frame.
+    pub latency_ms: u64,
+    /// Each frame's delay varies by up to this much either way.
+    pub jitter_ms: u64,
+    /// Cap in bytes per second; unlimited when unset.
+    pub bandwidth: Option<u64>,
+    /// Fraction of frames (0 to 1) that are "lost". TCP never drops data, so a lost
+    /// frame arrives `retransmit_ms` late and holds up everything behind it.
+    pub loss: f64,
+    pub retransmit_ms: u64,
+    /// Fraction of frames (0 to 1) swapped with the frame that follows. Only frames sent
+    /// in Play are swapped, as the login depends on its packets' order.
+    pub reorder: f64,
+}
+
+impl Default for LinkImpairment {
+    fn default() -> Self {
+        LinkImpairment {
+            latency_ms: 0,
+            jitter_ms: 0,
+            bandwidth: None,
+            loss: 0.0,
+            retransmit_ms: 200,
+            reorder: 0.0,
+        }
+    }
+}
+
+impl fmt::Display for LinkImpairment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "latency {}ms jitter {}ms", self.latency_ms, self.jitter_ms)?;
+        match self.bandwidth {
+            Some(bandwidth) => write!(f, " bandwidth {}B/s", bandwidth)?,
+            None => write!(f, " bandwidth unlimited")?,
+        }
+        write!(f, " loss {}% (+{}ms) reorder {}%", self.loss * 100.0, self.retransmit_ms, self.reorder * 100.0)
+    }
+}
+
+/// Impairment for both directions of a session.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Impairment {
+    pub serverbound: LinkImpairment,
+    pub clientbound: LinkImpairment,
+}
+
+impl Impairment {
+    pub fn direction(&self, direction: Direction) -> &LinkImpairment {
+        match direction {
+            Direction::Serverbound => &self.serverbound,
+            Direction::Clientbound => &self.clientbound,
+        }
+    }
+}
+
+impl fmt::Display for Impairment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "serverbound: {}, clientbound: {}", self.serverbound, self.clientbound)
+    }
+}
+
+struct Unit {
+    bytes: Vec<u8>,
+    due: Instant,
+    bandwidth: Option<u64>,
+    /// A whole frame that may change places with another.
+    frame: bool,
+    /// Write the next unit first if it arrives before this one is due and is a frame too.
+    swap: bool,
+    /// Shut the connection down once this unit is written.
+    close: bool,
+    /// Write everything after this unit here instead, closing the current connection.
+    redirect: Option<TcpStream>,
+}
+
+/// Queues frames the proxy makes up itself on a link, between forwarded frames.
+#[derive(Clone)]
+pub struct Injector {
+    tx: Sender<Unit>,
+}
+
+impl Injector {
+    /// Queues `frame` to go out without impairment, after what is already queued.
+    pub fn send(&self, frame: Vec<u8>) -> io::Result<()> {
+        self.push(frame, false)
+    }
+
+    /// Queues `frame`, then closes the connection once it is written.
+    pub fn send_and_close(&self, frame: Vec<u8>) -> io::Result<()> {
+        self.push(frame, true)
+    }
+
+    /// Writes what follows what is already queued to `output`, and closes the connection
+    /// written to so far once its queue is through.
+    pub fn redirect(&self, output: TcpStream) -> io::Result<()> {
+        self.tx.send(Unit { bytes: Vec::new(), due: Instant::now(), bandwidth: None, frame: false, swap: false, close: false, redirect: Some(output) })
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "writer stopped"))
+    }
+
+    fn push(&self, bytes: Vec<u8>, close: bool) -> io::Result<()> {
+        self.tx.send(Unit { bytes, due: Instant::now(), bandwidth: None, frame: false, swap: false, close, redirect: None })
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "writer stopped"))
+    }
+}
+
+/// Write side of one direction. Units are handed to a writer thread that holds each
+/// one until it is due, so delays never block reading.
+pub struct Link {
+    tx: Sender<Unit>,
+    last_due: Instant,
+}
+
+impl Link {
+    pub fn spawn(output: TcpStream, span: Span) -> Link {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _entered = span.enter();
+            write_loop(output, rx);
+        });
+        Link { tx, last_due: Instant::now() }
+    }
+
+    pub fn injector(&self) -> Injector {
+        Injector { tx: self.tx.clone() }
+    }
+
+    /// Queues `bytes` for writing under `settings`. `frame` is false for bytes that must
+    /// keep their place, passthrough bytes that are not a whole frame and frames before
+    /// Play; those are never reordered. Units leave in order
+    /// unless swapped, so jitter shows up as bursts like on a real TCP stream.
+    pub fn send(&mut self, bytes: Vec<u8>, frame: bool, settings: &LinkImpairment) -> io::Result<()> {
+        let mut rng = rand::rng();
+        let jitter = settings.jitter_ms as i64;
+        let mut delay = settings.latency_ms as i64 + if jitter > 0 { rng.random_range(-jitter..=jitter) } else { 0 };
+        if settings.loss > 0.0 && rng.random::<f64>() < settings.loss {
+            delay += settings.retransmit_ms as i64;
+        }
+        let due = (Instant::now() + Duration::from_millis(delay.max(0) as u64)).max(self.last_due);
+        self.last_due = due;
+        let swap = frame && settings.reorder > 0.0 && rng.random::<f64>() < settings.reorder;
+        self.tx.send(Unit { bytes, due, bandwidth: settings.bandwidth, frame, swap, close: false, redirect: None })
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "writer stopped"))
+    }
+}
+
+fn write_loop(mut output: TcpStream, rx: Receiver<Unit>) {
+    let mut next_free = Instant::now();
+    while let Ok(unit) = rx.recv() {
+        let mut units = vec![unit];
+        if units[0].swap {
+            match rx.recv_timeout(units[0].due.saturating_duration_since(Instant::now())) {
+                // Injected frames, passthrough bytes and redirects keep their place.
+                Ok(next) if !next.frame => units.push(next),
+                Ok(next) => units.insert(0, next),
+                Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => (),
+            }
+        }
+        for unit in units {
+            let start = unit.due.max(next_free);
+            thread::sleep(start.saturating_duration_since(Instant::now()));
+            if let Err(e) = output.write_all(&unit.bytes) {
+                debug!("Write failed reason: {}", e);
+                return;
+            }
+            if unit.close {
+                let _ = output.shutdown(Shutdown::Both);
+                return;
+            }
+            if let Some(redirect) = unit.redirect {
+                let _ = output.shutdown(Shutdown::Both);
+                output = redirect;
+            }
+            if let Some(bandwidth) = unit.bandwidth.filter(|bandwidth| *bandwidth > 0) {
+                next_free = start.max(Instant::now()) + Duration::from_secs_f64(unit.bytes.len() as f64 / bandwidth as f64);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    /// A link writing to a local connection, and the connection's other end.
+    fn link() -> (Link, TcpStream) {
+        let (output, input) = pair();
+        (Link::spawn(output, Span::none()), input)
+    }
+
+    fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let output = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        (output, listener.accept().unwrap().0)
+    }
+
+    fn read(input: &mut TcpStream, len: usize) -> Vec<u8> {
+        let mut bytes = vec![0; len];
+        input.read_exact(&mut bytes).unwrap();
+        bytes
+    }
+
+    const REORDER: LinkImpairment = LinkImpairment { latency_ms: 50, jitter_ms: 0, bandwidth: None, loss: 0.0, retransmit_ms: 0, reorder: 1.0 };
+
+    #[test]
+    fn only_frames_are_swapped() {
+        let (mut link, mut input) = link();
+        link.send(b"a".to_vec(), true, &REORDER).unwrap();
+        link.send(b"b".to_vec(), true, &REORDER).unwrap();
+        assert_eq!(read(&mut input, 2), b"ba");
+
+        // Passthrough bytes and frames before Play keep their place, on either side of a frame.
+        link.send(b"c".to_vec(), false, &REORDER).unwrap();
+        link.send(b"d".to_vec(), true, &REORDER).unwrap();
+        link.send(b"e".to_vec(), false, &REORDER).unwrap();
+        link.send(b"f".to_vec(), true, &REORDER).unwrap();
+        link.injector().send(b"g".to_vec()).unwrap();
+        assert_eq!(read(&mut input, 5), b"cdefg");
+    }
+
+    #[test]
+    fn nothing_is_swapped_across_a_redirect() {
+        let (mut link, mut old) = link();
+        let (output, mut new) = pair();
+        link.send(b"a".to_vec(), true, &REORDER).unwrap();
+        link.injector().redirect(output).unwrap();
+        link.send(b"b".to_vec(), true, &LinkImpairment::default()).unwrap();
+        assert_eq!(read(&mut new, 1), b"b");
+        let mut rest = Vec::new();
+        old.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"a");
+    }
+
+    #[test]
+    fn bandwidth_paces_the_writes() {
+        let (mut link, mut input) = link();
+        let settings = LinkImpairment { bandwidth: Some(2000), ..LinkImpairment::default() };
+        let start = Instant::now();
+        for _ in 0..3 {
+            link.send(vec![0; 100], true, &settings).unwrap();
+        }
+        read(&mut input, 100);
+        assert!(start.elapsed() < Duration::from_millis(50));
+        // Each 100 bytes take 50ms of the link: the third goes out after the first two.
+        read(&mut input, 200);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        // Injected frames are not held back by the cap, only behind it.
+        let injected = Instant::now();
+        link.injector().send(vec![1; 100]).unwrap();
+        link.injector().send(vec![2; 100]).unwrap();
+        read(&mut input, 200);
+        assert!(injected.elapsed() < Duration::from_millis(100));
+    }
+}
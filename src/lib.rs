@@ -0,0 +1,9 @@
+//! Protocol parsing, shared by the proxy binary and the fuzz targets in `fuzz/`.
+
+pub mod anvil;
+pub mod chunk;
+pub mod metadata;
+pub mod nbt;
+pub mod packets;
+pub mod text;
+pub mod utils;
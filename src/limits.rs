@@ -0,0 +1,176 @@
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Anti-flood limits. A value of 0 turns that limit off.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// New connections allowed per IP within `connection_window_secs`.
+    pub connections_per_ip: usize,
+    pub connection_window_secs: u64,
+    /// Connections open at the same time per IP.
+    pub concurrent_per_ip: usize,
+    /// Time a client gets to send its Handshake and Login Start.
+    pub handshake_timeout_secs: u64,
+    /// Time from connecting to reaching Play; status pings are held to it too.
+    pub login_timeout_secs: u64,
+    /// Packets per second a client may send.
+    pub packets_per_second: u32,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            connections_per_ip: 10,
+            connection_window_secs: 10,
+            concurrent_per_ip: 5,
+            handshake_timeout_secs: 5,
+            login_timeout_secs: 30,
+            packets_per_second: 500,
+        }
+    }
+}
+
+impl LimitsConfig {
+    pub fn handshake_timeout(&self) -> Option<Duration> {
+        (self.handshake_timeout_secs > 0).then(|| Duration::from_secs(self.handshake_timeout_secs))
+    }
+
+    pub fn login_timeout(&self) -> Option<Duration> {
+        (self.login_timeout_secs > 0).then(|| Duration::from_secs(self.login_timeout_secs))
+    }
+}
+
+#[derive(Default)]
+struct IpState {
+    recent: VecDeque<Instant>,
+    active: usize,
+}
+
+/// Per-IP connection rate and concurrency, checked when a connection is accepted.
+pub struct ConnectionLimiter {
+    config: LimitsConfig,
+    ips: Mutex<HashMap<IpAddr, IpState>>,
+}
+
+/// Held for as long as a connection is open; frees its concurrency slot on drop.
+pub struct Permit {
+    limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut ips = self.limiter.ips.lock().unwrap();
+        if let Some(state) = ips.get_mut(&self.ip) {
+            state.active -= 1;
+        }
+    }
+}
+
+impl ConnectionLimiter {
+    pub fn new(config: LimitsConfig) -> Arc<ConnectionLimiter> {
+        Arc::new(ConnectionLimiter { config, ips: Mutex::new(HashMap::new()) })
+    }
+
+    /// Admits a new connection from `ip`, or names the limit it hit.
+    pub fn admit(self: &Arc<Self>, ip: IpAddr) -> Result<Permit, &'static str> {
+        let now = Instant::now();
+        let window = Duration::from_secs(self.config.connection_window_secs);
+        let mut ips = self.ips.lock().unwrap();
+        // Forget idle addresses so scans from many IPs do not pile up.
+        ips.retain(|_, state| {
+            while state.recent.front().is_some_and(|connected| now - *connected > window) {
+                state.recent.pop_front();
+            }
+            state.active > 0 || !state.recent.is_empty()
+        });
+        let state = ips.entry(ip).or_default();
+        if self.config.concurrent_per_ip > 0 && state.active >= self.config.concurrent_per_ip {
+            return Err("too_many_connections");
+        }
+        if self.config.connections_per_ip > 0 && state.recent.len() >= self.config.connections_per_ip {
+            return Err("rate_limited");
+        }
+        state.recent.push_back(now);
+        state.active += 1;
+        Ok(Permit { limiter: self.clone(), ip })
+    }
+}
+
+/// Counts packets in one-second windows.
+pub struct PacketRate {
+    limit: u32,
+    window_start: Instant,
+    count: u32,
+}
+
+impl PacketRate {
+    pub fn new(limit: u32) -> PacketRate {
+        PacketRate { limit, window_start: Instant::now(), count: 0 }
+    }
+
+    /// Counts one packet; false once this second's count is over the limit.
+    pub fn admit(&mut self) -> bool {
+        if self.limit == 0 {
+            return true;
+        }
+        if self.window_start.elapsed() >= Duration::from_secs(1) {
+            self.window_start = Instant::now();
+            self.count = 0;
+        }
+        self.count += 1;
+        self.count <= self.limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn limiter_with(connections_per_ip: usize, connection_window_secs: u64, concurrent_per_ip: usize) -> Arc<ConnectionLimiter> {
+        ConnectionLimiter::new(LimitsConfig { connections_per_ip, connection_window_secs, concurrent_per_ip, ..LimitsConfig::default() })
+    }
+
+    #[test]
+    fn concurrent_connections_are_capped_per_ip() {
+        let limiter = limiter_with(0, 10, 2);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let first = limiter.admit(ip).unwrap();
+        let _second = limiter.admit(ip).unwrap();
+        assert_eq!(limiter.admit(ip).err(), Some("too_many_connections"));
+        assert!(limiter.admit("10.0.0.2".parse().unwrap()).is_ok());
+        drop(first);
+        assert!(limiter.admit(ip).is_ok());
+    }
+
+    #[test]
+    fn new_connections_are_rate_limited_within_the_window() {
+        let limiter = limiter_with(2, 10, 0);
+        let ip: IpAddr = "::1".parse().unwrap();
+        drop(limiter.admit(ip).unwrap());
+        drop(limiter.admit(ip).unwrap());
+        assert_eq!(limiter.admit(ip).err(), Some("rate_limited"));
+
+        let limiter = limiter_with(1, 0, 0);
+        drop(limiter.admit(ip).unwrap());
+        thread::sleep(Duration::from_millis(2));
+        assert!(limiter.admit(ip).is_ok());
+    }
+
+    #[test]
+    fn packet_rate_resets_every_second() {
+        let mut rate = PacketRate::new(3);
+        assert!((0..3).all(|_| rate.admit()));
+        assert!(!rate.admit());
+        rate.window_start -= Duration::from_secs(1);
+        assert!(rate.admit());
+
+        let mut unlimited = PacketRate::new(0);
+        assert!((0..10_000).all(|_| unlimited.admit()));
+    }
+}
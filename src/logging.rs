@@ -0,0 +1,64 @@
+use std::sync::OnceLock;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+/// Filter used when `RUST_LOG` is not set. Parsed packets are logged at debug
+/// level so they stay quiet unless asked for.
+const DEFAULT_FILTER: &str = "info";
+
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// Installs the global subscriber. The filter is read from `RUST_LOG` and uses the
+/// usual `EnvFilter` syntax; packet events use `<State>/<direction>/<Packet>` targets
+/// so e.g. `RUST_LOG="info,Login=debug,Play/serverbound/SetPlayerPosition=off"` works.
+pub fn init() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let (filter, handle) = reload::Layer::new(filter);
+    tracing_subscriber::registry().with(filter).with(fmt::layer()).init();
+    let _ = FILTER.set(handle);
+}
+
+/// Replaces the active filter with `directives`, in `RUST_LOG` syntax.
+pub fn set_filter(directives: &str) -> Result<(), String> {
+    let filter = EnvFilter::try_new(directives).map_err(|e| e.to_string())?;
+    let handle = FILTER.get().ok_or("logging is not initialised")?;
+    handle.reload(filter).map_err(|e| e.to_string())
+}
+
+/// The active filter, in `RUST_LOG` syntax.
+pub fn filter() -> String {
+    FILTER.get()
+        .and_then(|handle| handle.with_current(|filter| filter.to_string()).ok())
+        .unwrap_or_default()
+}
+
+/// Parses `$raw` as `$packet` and logs the outcome under `$target`.
+/// Successes go out at debug and are attached to the frame's packet event, failures
+/// at warn with an annotated hex dump at trace. Successes of frames not being inspected
+/// are neither logged nor attached, and none are formatted unless something reads them.
+/// Evaluates to `Option<$packet>`.
+macro_rules! decode {
+    ($target:literal, $packet:ident, $raw:expr) => {
+        match <$packet as $crate::packets::Packet>::parse($raw) {
+            Ok(parsed) => {
+                if $crate::events::inspecting()
+                    && ($crate::events::EVENTS.listening() || tracing::enabled!(target: $target, tracing::Level::DEBUG)) {
+                    let fields = $crate::packets::Packet::make_string(&parsed);
+                    tracing::debug!(target: $target, "{}", fields);
+                    $crate::events::note_fields(fields);
+                }
+                Some(parsed)
+            }
+            Err(e) => {
+                $crate::metrics::METRICS.parse_errors.with_label_values(&[stringify!($packet)]).inc();
+                tracing::warn!(target: $target, "Failed to parse {} reason: {}", stringify!($packet), e);
+                tracing::trace!(target: $target, "{} body: {}", stringify!($packet), $crate::hexdump::dump_error($raw, &e));
+                $crate::events::note_error(e.to_string());
+                None
+            }
+        }
+    };
+}
+
+pub(crate) use decode;
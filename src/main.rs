@@ -1,12 +1,55 @@
+mod access;
+mod api;
+mod chat;
+mod commands;
+mod config;
+mod console;
+mod entity_tracker;
+mod events;
+mod export;
+mod hexdump;
+mod impair;
+mod limits;
+mod logging;
+mod metrics;
+mod movement;
 mod proxy;
-mod utils;
-mod packets;
+mod sanity;
+mod session;
+mod stats;
+mod switch;
+mod world;
 
+use config::Config;
+use rust_minecraft_proxy::{anvil, chunk, metadata, nbt, packets, text, utils};
 use proxy::Proxy;
+use std::path::Path;
+use tracing::{info, warn};
 
 fn main() {
     std::env::set_var("RUST_BACKTRACE", "1");
-    let proxy = Proxy::new();
+    logging::init();
+    let path = Config::path(std::env::args().nth(1));
+    let config = Config::load(path.as_deref());
+    utils::set_strict(config.strict);
+    if let Some(lang) = &config.lang {
+        match text::load_language(Path::new(lang)) {
+            Ok(count) => info!("Loaded {} translations from {}", count, lang),
+            Err(e) => warn!("Cannot load translations {} reason: {}", lang, e),
+        }
+    }
+    if let Some(addr) = &config.metrics {
+        metrics::serve(addr);
+    }
+    let api = config.api.clone();
+    let proxy = Proxy::new(config);
+    if let Some(path) = path {
+        proxy.watch_config(path);
+    }
+    if let Some(addr) = &api {
+        api::serve(addr, proxy.sessions(), proxy.routing());
+    }
+    console::spawn(proxy.sessions(), proxy.exporter());
 
     proxy.run();
 }
@@ -0,0 +1,213 @@
+//! Entity metadata and item slots, as sent in Set Entity Metadata and Set Equipment.
+
+use crate::nbt::{self, Tag};
+use crate::packets::{MAX_CHAT_LEN, MAX_IDENTIFIER_LEN};
+use crate::utils::{read_field, read_string, read_var_int, Position, ProtocolError};
+use byteorder::{BigEndian, ReadBytesExt};
+use std::fmt;
+use std::io::{self, Cursor};
+use uuid::Uuid;
+
+/// Ends the list of metadata entries.
+const METADATA_END: u8 = 0xff;
+/// Set on an equipment slot byte when another slot follows.
+const MORE_EQUIPMENT: u8 = 0x80;
+/// Longest String in metadata, in characters.
+const MAX_METADATA_STRING_LEN: usize = 32767;
+
+/// Equipment slots by the index Set Equipment uses.
+pub const EQUIPMENT_SLOTS: [&str; 6] = ["main_hand", "off_hand", "boots", "leggings", "chestplate", "helmet"];
+
+/// A stack of items; an empty slot is `None` wherever one is read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Slot {
+    pub item: i32,
+    pub count: i8,
+    pub nbt: Option<Tag>,
+}
+
+impl Slot {
+    pub fn read(cr: &mut Cursor<&[u8]>) -> io::Result<Option<Slot>> {
+        if read_field(cr, "present", |cr| cr.read_u8())? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(Slot {
+            item: read_field(cr, "item_id", |cr| read_var_int(cr))?,
+            count: read_field(cr, "item_count", |cr| cr.read_i8())?,
+            nbt: read_field(cr, "nbt", |cr| nbt::read_named(cr))?.map(|(_, tag)| tag),
+        }))
+    }
+}
+
+impl fmt::Display for Slot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.count, self.item)?;
+        match &self.nbt {
+            Some(nbt) => write!(f, " {}", nbt),
+            None => Ok(()),
+        }
+    }
+}
+
+/// One metadata value, by its type in protocol 760.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetadataValue {
+    Byte(i8),
+    VarInt(i32),
+    Float(f32),
+    String(String),
+    /// A JSON text component.
+    Chat(String),
+    OptChat(Option<String>),
+    Slot(Option<Slot>),
+    Boolean(bool),
+    Rotation(f32, f32, f32),
+    Position(Position),
+    OptPosition(Option<Position>),
+    Direction(i32),
+    OptUuid(Option<Uuid>),
+    /// A block state id, 0 for none.
+    BlockState(i32),
+    Nbt(Option<Tag>),
+    /// Particle type id; its parameters are read but not kept.
+    Particle(i32),
+    /// Villager type, profession and level.
+    VillagerData(i32, i32, i32),
+    OptVarInt(Option<i32>),
+    Pose(i32),
+    CatVariant(i32),
+    FrogVariant(i32),
+    /// Dimension and position.
+    OptGlobalPos(Option<(String, Position)>),
+    PaintingVariant(i32),
+}
+
+impl MetadataValue {
+    pub fn read(cr: &mut Cursor<&[u8]>) -> io::Result<MetadataValue> {
+        let kind = read_field(cr, "type", |cr| read_var_int(cr))?;
+        read_field(cr, "value", |cr| {
+            let present = |cr: &mut Cursor<&[u8]>| cr.read_u8().map(|present| present != 0);
+            Ok(match kind {
+                0 => MetadataValue::Byte(cr.read_i8()?),
+                1 => MetadataValue::VarInt(read_var_int(cr)?),
+                2 => MetadataValue::Float(cr.read_f32::<BigEndian>()?),
+                3 => MetadataValue::String(read_string(cr, MAX_METADATA_STRING_LEN)?),
+                4 => MetadataValue::Chat(read_string(cr, MAX_CHAT_LEN)?),
+                5 => MetadataValue::OptChat(if present(cr)? { Some(read_string(cr, MAX_CHAT_LEN)?) } else { None }),
+                6 => MetadataValue::Slot(Slot::read(cr)?),
+                7 => MetadataValue::Boolean(present(cr)?),
+                8 => MetadataValue::Rotation(cr.read_f32::<BigEndian>()?, cr.read_f32::<BigEndian>()?, cr.read_f32::<BigEndian>()?),
+                9 => MetadataValue::Position(Position::read(cr)?),
+                10 => MetadataValue::OptPosition(if present(cr)? { Some(Position::read(cr)?) } else { None }),
+                11 => MetadataValue::Direction(read_var_int(cr)?),
+                12 => MetadataValue::OptUuid(if present(cr)? { Some(Uuid::from_u128(cr.read_u128::<BigEndian>()?)) } else { None }),
+                13 => MetadataValue::BlockState(read_var_int(cr)?),
+                14 => MetadataValue::Nbt(nbt::read_named(cr)?.map(|(_, tag)| tag)),
+                15 => MetadataValue::Particle(read_particle(cr)?),
+                16 => MetadataValue::VillagerData(read_var_int(cr)?, read_var_int(cr)?, read_var_int(cr)?),
+                // 0 is none, anything else the value plus one.
+                17 => MetadataValue::OptVarInt(Some(read_var_int(cr)?).filter(|value| *value != 0).map(|value| value - 1)),
+                18 => MetadataValue::Pose(read_var_int(cr)?),
+                19 => MetadataValue::CatVariant(read_var_int(cr)?),
+                20 => MetadataValue::FrogVariant(read_var_int(cr)?),
+                21 => MetadataValue::OptGlobalPos(if present(cr)? {
+                    Some((read_string(cr, MAX_IDENTIFIER_LEN)?, Position::read(cr)?))
+                } else {
+                    None
+                }),
+                22 => MetadataValue::PaintingVariant(read_var_int(cr)?),
+                _ => return Err(ProtocolError::BadMetadata(format!("unknown type {}", kind)).into_error()),
+            })
+        })
+    }
+}
+
+/// Reads a particle and its parameters, which depend on the particle type in 1.19.2.
+fn read_particle(cr: &mut Cursor<&[u8]>) -> io::Result<i32> {
+    let id = read_var_int(cr)?;
+    let floats = |cr: &mut Cursor<&[u8]>, count: usize| (0..count).try_for_each(|_| cr.read_f32::<BigEndian>().map(drop));
+    match id {
+        // block, block_marker, falling_dust: a block state
+        2 | 3 | 25 => drop(read_var_int(cr)?),
+        // dust: colour and scale
+        14 => floats(cr, 4)?,
+        // dust_color_transition: two colours and scale
+        15 => floats(cr, 7)?,
+        // sculk_charge: roll
+        30 => floats(cr, 1)?,
+        // item
+        39 => drop(Slot::read(cr)?),
+        // vibration: where it goes, then how long it takes
+        40 => {
+            match read_string(cr, MAX_IDENTIFIER_LEN)?.as_str() {
+                "minecraft:block" => drop(Position::read(cr)?),
+                "minecraft:entity" => {
+                    read_var_int(cr)?;
+                    floats(cr, 1)?;
+                }
+                other => return Err(ProtocolError::BadMetadata(format!("unknown vibration source {}", other)).into_error()),
+            }
+            read_var_int(cr)?;
+        }
+        // shriek: delay
+        92 => drop(read_var_int(cr)?),
+        _ => (),
+    }
+    Ok(id)
+}
+
+impl fmt::Display for MetadataValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let optional = |f: &mut fmt::Formatter<'_>, value: Option<&dyn fmt::Display>| match value {
+            Some(value) => write!(f, "{}", value),
+            None => write!(f, "none"),
+        };
+        match self {
+            MetadataValue::Byte(value) => write!(f, "{}b", value),
+            MetadataValue::VarInt(value) | MetadataValue::Direction(value) | MetadataValue::Pose(value)
+            | MetadataValue::CatVariant(value) | MetadataValue::FrogVariant(value) | MetadataValue::PaintingVariant(value) => write!(f, "{}", value),
+            MetadataValue::BlockState(value) => write!(f, "block {}", value),
+            MetadataValue::Particle(value) => write!(f, "particle {}", value),
+            MetadataValue::Float(value) => write!(f, "{}f", value),
+            MetadataValue::String(value) | MetadataValue::Chat(value) => write!(f, "{:?}", value),
+            MetadataValue::OptChat(value) => optional(f, value.as_ref().map(|value| value as &dyn fmt::Display)),
+            MetadataValue::Slot(value) => optional(f, value.as_ref().map(|value| value as &dyn fmt::Display)),
+            MetadataValue::Boolean(value) => write!(f, "{}", value),
+            MetadataValue::Rotation(x, y, z) => write!(f, "{},{},{}", x, y, z),
+            MetadataValue::Position(value) => write!(f, "{}", value),
+            MetadataValue::OptPosition(value) => optional(f, value.as_ref().map(|value| value as &dyn fmt::Display)),
+            MetadataValue::OptUuid(value) => optional(f, value.as_ref().map(|value| value as &dyn fmt::Display)),
+            MetadataValue::Nbt(value) => optional(f, value.as_ref().map(|value| value as &dyn fmt::Display)),
+            MetadataValue::VillagerData(kind, profession, level) => write!(f, "villager {}/{}/{}", kind, profession, level),
+            MetadataValue::OptVarInt(value) => optional(f, value.as_ref().map(|value| value as &dyn fmt::Display)),
+            MetadataValue::OptGlobalPos(value) => match value {
+                Some((dimension, position)) => write!(f, "{} {}", dimension, position),
+                None => write!(f, "none"),
+            },
+        }
+    }
+}
+
+/// Reads metadata entries, `(index, value)`, up to the end marker.
+pub fn read_metadata(cr: &mut Cursor<&[u8]>) -> io::Result<Vec<(u8, MetadataValue)>> {
+    let mut entries = Vec::new();
+    loop {
+        let index = read_field(cr, "index", |cr| cr.read_u8())?;
+        if index == METADATA_END {
+            return Ok(entries);
+        }
+        entries.push((index, MetadataValue::read(cr)?));
+    }
+}
+
+/// Reads Set Equipment's `(slot, item)` pairs; the top bit of a slot says whether another follows.
+pub fn read_equipment(cr: &mut Cursor<&[u8]>) -> io::Result<Vec<(u8, Option<Slot>)>> {
+    let mut equipment = Vec::new();
+    loop {
+        let slot = read_field(cr, "slot", |cr| cr.read_u8())?;
+        equipment.push((slot & !MORE_EQUIPMENT, Slot::read(cr)?));
+        if slot & MORE_EQUIPMENT == 0 {
+            return Ok(equipment);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use prometheus::{Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use std::sync::LazyLock;
+use std::thread;
+use tiny_http::{Header, Response, Server};
+use tracing::{info, warn};
+
+/// Process-wide counters exported on `/metrics`.
+pub struct Metrics {
+    registry: Registry,
+    pub active_sessions: IntGauge,
+    pub connections_accepted: IntCounter,
+    /// Labelled by `reason`.
+    pub connections_rejected: IntCounterVec,
+    /// Labelled by `direction`, `state` and `packet`.
+    pub packets: IntCounterVec,
+    /// Wire bytes, labelled like `packets`.
+    pub bytes: IntCounterVec,
+    /// Labelled by `packet`.
+    pub parse_errors: IntCounterVec,
+    /// Seconds from the login handshake to Login Success.
+    pub login_latency: Histogram,
+    /// Keep Alive round trips between proxy and client.
+    pub client_rtt: Histogram,
+    /// Server-reported player latency minus the client round trip.
+    pub upstream_latency: Histogram,
+    pub upstream_connect_failures: IntCounter,
+    /// Sessions cut off by an anti-flood limit or movement check, labelled by `reason`.
+    pub limit_disconnects: IntCounterVec,
+    /// Failed movement checks, labelled by `check`.
+    pub movement_flags: IntCounterVec,
+    /// Chat messages and commands stopped or changed by the chat filter, labelled by `action`.
+    pub chat_filtered: IntCounterVec,
+    /// `/proxy` commands answered by the proxy, labelled by `command`.
+    pub commands: IntCounterVec,
+    /// Moves to another server, labelled by `result`: `switched`, `failed` or `reconnect`.
+    pub server_switches: IntCounterVec,
+    /// Packet events a slow API subscriber missed.
+    pub events_dropped: IntCounter,
+}
+
+const LATENCY_BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.15, 0.25, 0.5, 1.0, 2.5];
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+impl Metrics {
+    fn new() -> Metrics {
+        let registry = Registry::new();
+        let metrics = Metrics {
+            active_sessions: IntGauge::new("proxy_active_sessions", "Sessions currently proxied").unwrap(),
+            connections_accepted: IntCounter::new("proxy_connections_accepted_total", "Client connections accepted").unwrap(),
+            connections_rejected: IntCounterVec::new(
+                Opts::new("proxy_connections_rejected_total", "Client connections dropped before being proxied"),
+                &["reason"]).unwrap(),
+            packets: IntCounterVec::new(
+                Opts::new("proxy_packets_total", "Packets forwarded"),
+                &["direction", "state", "packet"]).unwrap(),
+            bytes: IntCounterVec::new(
+                Opts::new("proxy_bytes_total", "Bytes forwarded, as sent on the wire"),
+                &["direction", "state", "packet"]).unwrap(),
+            parse_errors: IntCounterVec::new(
+                Opts::new("proxy_parse_errors_total", "Packets that failed to parse"),
+                &["packet"]).unwrap(),
+            login_latency: Histogram::with_opts(
+                HistogramOpts::new("proxy_login_latency_seconds", "Time from the login handshake to Login Success")
+                    .buckets(vec![0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0])).unwrap(),
+            client_rtt: Histogram::with_opts(
+                HistogramOpts::new("proxy_client_rtt_seconds", "Keep Alive round trip between proxy and client")
+                    .buckets(LATENCY_BUCKETS.to_vec())).unwrap(),
+            upstream_latency: Histogram::with_opts(
+                HistogramOpts::new("proxy_upstream_latency_seconds", "Estimated proxy to server share of the player's latency")
+                    .buckets(LATENCY_BUCKETS.to_vec())).unwrap(),
+            upstream_connect_failures: IntCounter::new("proxy_upstream_connect_failures_total", "Failed connections to the upstream server").unwrap(),
+            limit_disconnects: IntCounterVec::new(
+                Opts::new("proxy_limit_disconnects_total", "Sessions disconnected by an anti-flood limit, strict validation or a movement check"),
+                &["reason"]).unwrap(),
+            movement_flags: IntCounterVec::new(
+                Opts::new("proxy_movement_flags_total", "Moves that failed a movement check"),
+                &["check"]).unwrap(),
+            chat_filtered: IntCounterVec::new(
+                Opts::new("proxy_chat_filtered_total", "Chat messages and commands blocked or rewritten by the chat filter"),
+                &["action"]).unwrap(),
+            commands: IntCounterVec::new(
+                Opts::new("proxy_commands_total", "Proxy commands run by players"),
+                &["command"]).unwrap(),
+            server_switches: IntCounterVec::new(
+                Opts::new("proxy_server_switches_total", "Player moves to another server, by how they ended"),
+                &["result"]).unwrap(),
+            events_dropped: IntCounter::new("proxy_events_dropped_total", "Packet events not delivered to a stream subscriber that fell behind").unwrap(),
+            registry,
+        };
+        metrics.registry.register(Box::new(metrics.active_sessions.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.connections_accepted.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.connections_rejected.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.packets.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.bytes.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.parse_errors.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.login_latency.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.client_rtt.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.upstream_latency.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.upstream_connect_failures.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.limit_disconnects.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.movement_flags.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.chat_filtered.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.commands.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.server_switches.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.events_dropped.clone())).unwrap();
+        metrics
+    }
+
+    /// Renders every metric in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+}
+
+/// Serves `GET /metrics` on `addr` from a background thread.
+pub fn serve(addr: &str) {
+    let server = match Server::http(addr) {
+        Ok(server) => server,
+        Err(e) => {
+            warn!("Cannot bind metrics endpoint to {} reason: {}", addr, e);
+            return;
+        }
+    };
+    info!("Serving metrics on http://{}/metrics", addr);
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = if request.url() == "/metrics" {
+                let content_type = Header::from_bytes("Content-Type", TextEncoder::new().format_type()).unwrap();
+                Response::from_string(METRICS.render()).with_header(content_type)
+            } else {
+                Response::from_string("not found").with_status_code(404)
+            };
+            let _ = request.respond(response);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    fn get(addr: &str, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, addr).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn scrape() {
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        METRICS.commands.with_label_values(&["scrape"]).inc();
+        serve(&addr);
+
+        let response = get(&addr, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4"));
+        for metric in ["proxy_active_sessions 0", "proxy_events_dropped_total ", "proxy_commands_total{command=\"scrape\"} 1"] {
+            assert!(response.contains(metric), "{} missing from {}", metric, response);
+        }
+        assert!(get(&addr, "/").starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,357 @@
+use crate::packets::server::SynchronizePlayerPositionPacket;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Path points kept per session; the oldest are dropped first. At 20 moves a second
+/// this is over an hour.
+const MAX_PATH_POINTS: usize = 100_000;
+/// Corrections kept per session.
+const MAX_CORRECTIONS: usize = 1000;
+/// A correction that moves the player less than this changed nothing.
+const IN_PLACE_DISTANCE: f64 = 0.01;
+/// A correction landing this close to where the client was recently sent it back there.
+const REVERT_DISTANCE: f64 = 0.5;
+/// How far back, in milliseconds, a correction is compared with the client's path.
+const REVERT_WINDOW: u64 = 10_000;
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_millis() as u64)
+}
+
+fn distance((ax, ay, az): (f64, f64, f64), (bx, by, bz): (f64, f64, f64)) -> f64 {
+    ((ax - bx).powi(2) + (ay - by).powi(2) + (az - bz).powi(2)).sqrt()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// A Set Player Position/Rotation packet.
+    Client,
+    /// Synchronize Player Position.
+    Server,
+}
+
+/// One position of the player, in the order they happened.
+#[derive(Clone, Debug, Serialize)]
+pub struct PathPoint {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub on_ground: bool,
+    pub source: Source,
+}
+
+impl PathPoint {
+    fn position(&self) -> (f64, f64, f64) {
+        (self.x, self.y, self.z)
+    }
+}
+
+/// Why the server moved the player, as far as can be told from the outside.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cause {
+    /// The first position after Login.
+    Spawn,
+    /// The first position after Respawn: death or a dimension change.
+    Respawn,
+    /// The position did not change, only rotation or nothing at all.
+    InPlace,
+    /// Back to where the client was moments ago: the server rejected its movement
+    /// ("moved too quickly", "moved wrongly"). This is rubber-banding.
+    Reverted,
+    /// Somewhere the client has not been recently: a command, plugin or portal.
+    Teleport,
+}
+
+/// One Synchronize Player Position.
+#[derive(Clone, Debug, Serialize)]
+pub struct Correction {
+    pub timestamp: u64,
+    pub teleport_id: i32,
+    /// Where the client last said it was, if it had.
+    pub from: Option<(f64, f64, f64)>,
+    pub to: (f64, f64, f64),
+    pub distance: f64,
+    pub cause: Cause,
+    /// For reverts, client moves since the position it was sent back to.
+    pub moves_undone: usize,
+    /// Moves the client sent before confirming, which the server ignores.
+    pub moves_ignored: usize,
+    /// False until Confirm Teleportation, and for good when a later correction came first.
+    pub confirmed: bool,
+}
+
+/// The player's own position, merged from what the client reports and where the server
+/// puts it. Coordinates are only known after the first Synchronize Player Position.
+#[derive(Default)]
+pub struct Movement {
+    position: Option<(f64, f64, f64)>,
+    yaw: f32,
+    pitch: f32,
+    on_ground: bool,
+    /// Blocks moved by the client; server moves do not count.
+    distance: f64,
+    path: VecDeque<PathPoint>,
+    corrections: VecDeque<Correction>,
+    /// Set between Respawn and the next Synchronize Player Position.
+    respawned: bool,
+    /// From Player Abilities: creative and spectator players, or ones a plugin lets fly.
+    may_fly: bool,
+}
+
+impl Movement {
+    pub fn new() -> Movement {
+        Movement::default()
+    }
+
+    /// Forgets where the player was; its first position is a spawn again.
+    pub fn login(&mut self) {
+        self.position = None;
+        self.respawned = false;
+    }
+
+    pub fn respawn(&mut self) {
+        self.respawned = true;
+    }
+
+    pub fn position(&self) -> Option<(f64, f64, f64)> {
+        self.position
+    }
+
+    pub fn rotation(&self) -> (f32, f32) {
+        (self.yaw, self.pitch)
+    }
+
+    pub fn on_ground(&self) -> bool {
+        self.on_ground
+    }
+
+    pub fn may_fly(&self) -> bool {
+        self.may_fly
+    }
+
+    pub fn set_may_fly(&mut self, may_fly: bool) {
+        self.may_fly = may_fly;
+    }
+
+    /// True between Synchronize Player Position and its confirmation.
+    pub fn awaiting_confirmation(&self) -> bool {
+        self.corrections.back().is_some_and(|correction| !correction.confirmed)
+    }
+
+    pub fn distance(&self) -> f64 {
+        self.distance
+    }
+
+    pub fn path(&self) -> impl Iterator<Item = &PathPoint> {
+        self.path.iter()
+    }
+
+    pub fn corrections(&self) -> impl Iterator<Item = &Correction> {
+        self.corrections.iter()
+    }
+
+    pub fn last_correction(&self) -> Option<&Correction> {
+        self.corrections.back()
+    }
+
+    /// The correction the client has not confirmed yet.
+    fn pending(&mut self) -> Option<&mut Correction> {
+        self.corrections.back_mut().filter(|correction| !correction.confirmed)
+    }
+
+    fn record(&mut self, source: Source) {
+        let Some((x, y, z)) = self.position else { return };
+        if self.path.len() == MAX_PATH_POINTS {
+            self.path.pop_front();
+        }
+        self.path.push_back(PathPoint { timestamp: now(), x, y, z, yaw: self.yaw, pitch: self.pitch, on_ground: self.on_ground, source });
+    }
+
+    /// Applies Synchronize Player Position and returns what it did.
+    pub fn synchronize(&mut self, sync: &SynchronizePlayerPositionPacket) -> &Correction {
+        let timestamp = now();
+        let from = self.position;
+        let (fx, fy, fz) = from.unwrap_or_default();
+        let (x, y, z, yaw, pitch) = sync.apply((fx, fy, fz, self.yaw, self.pitch));
+        let to = (x, y, z);
+        let moved = from.map_or(0.0, |from| distance(from, to));
+        let recent: Vec<&PathPoint> = self.path.iter().rev()
+            .take_while(|point| point.source == Source::Client && timestamp.saturating_sub(point.timestamp) <= REVERT_WINDOW)
+            .collect();
+        let (cause, moves_undone) = match from {
+            None => (Cause::Spawn, 0),
+            Some(_) if self.respawned => (Cause::Respawn, 0),
+            Some(_) if moved < IN_PLACE_DISTANCE => (Cause::InPlace, 0),
+            Some(_) => match recent.iter().position(|point| distance(point.position(), to) < REVERT_DISTANCE) {
+                Some(undone) => (Cause::Reverted, undone),
+                None => (Cause::Teleport, 0),
+            },
+        };
+        self.position = Some(to);
+        (self.yaw, self.pitch) = (yaw, pitch);
+        self.respawned = false;
+        self.record(Source::Server);
+        if self.corrections.len() == MAX_CORRECTIONS {
+            self.corrections.pop_front();
+        }
+        self.corrections.push_back(Correction {
+            timestamp, teleport_id: sync.teleport_id, from, to, distance: moved, cause, moves_undone, moves_ignored: 0, confirmed: false,
+        });
+        self.corrections.back().unwrap()
+    }
+
+    /// Applies Confirm Teleportation. Returns the correction it confirms, if it was the latest.
+    pub fn confirm(&mut self, teleport_id: i32) -> Option<&Correction> {
+        let pending = self.pending().filter(|pending| pending.teleport_id == teleport_id)?;
+        pending.confirmed = true;
+        Some(pending)
+    }
+
+    /// Applies one of the Set Player Position/Rotation packets. Moves sent before the
+    /// latest correction is confirmed are counted against it and otherwise ignored, like
+    /// the server does. Returns false for those.
+    pub fn client_moved(&mut self, position: Option<(f64, f64, f64)>, rotation: Option<(f32, f32)>, on_ground: bool) -> bool {
+        if let Some(pending) = self.pending() {
+            pending.moves_ignored += 1;
+            return false;
+        }
+        if let (Some(from), Some(to)) = (self.position, position) {
+            let step = distance(from, to);
+            if step.is_finite() {
+                self.distance += step;
+            }
+        }
+        if position.is_some() {
+            self.position = position;
+        }
+        if let Some((yaw, pitch)) = rotation {
+            (self.yaw, self.pitch) = (yaw, pitch);
+        }
+        self.on_ground = on_ground;
+        self.record(Source::Client);
+        true
+    }
+
+    /// The path as CSV with a header line.
+    pub fn path_csv(&self) -> String {
+        let mut csv = String::from("timestamp,x,y,z,yaw,pitch,on_ground,source\n");
+        for point in &self.path {
+            let source = match point.source {
+                Source::Client => "client",
+                Source::Server => "server",
+            };
+            let _ = writeln!(csv, "{},{},{},{},{},{},{},{}", point.timestamp, point.x, point.y, point.z, point.yaw, point.pitch, point.on_ground, source);
+        }
+        csv
+    }
+
+    pub fn summary(&self) -> String {
+        let position = self.position.map_or_else(|| "unknown".to_string(), |(x, y, z)| format!("{:.2},{:.2},{:.2}", x, y, z));
+        let count = |cause: Cause| self.corrections.iter().filter(|correction| correction.cause == cause).count();
+        format!("position:{} yaw:{:.1} pitch:{:.1} on_ground:{} distance:{:.2} path:{} corrections:{} (reverted {}, teleports {})",
+                position, self.yaw, self.pitch, self.on_ground, self.distance, self.path.len(), self.corrections.len(),
+                count(Cause::Reverted), count(Cause::Teleport))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::server::{RELATIVE_PITCH, RELATIVE_X, RELATIVE_Y, RELATIVE_YAW, RELATIVE_Z};
+
+    fn sync(teleport_id: i32, (x, y, z): (f64, f64, f64), flags: u8) -> SynchronizePlayerPositionPacket {
+        SynchronizePlayerPositionPacket { x, y, z, yaw: 0.0, pitch: 0.0, flags, teleport_id, dismount_vehicle: false }
+    }
+
+    /// A player spawned at the origin who walked `steps` blocks along x.
+    fn walked(steps: u32) -> Movement {
+        let mut movement = Movement::new();
+        assert_eq!(movement.synchronize(&sync(1, (0.0, 64.0, 0.0), 0)).cause, Cause::Spawn);
+        assert!(movement.confirm(1).is_some());
+        for step in 1..=steps {
+            assert!(movement.client_moved(Some((step as f64, 64.0, 0.0)), None, true));
+        }
+        movement
+    }
+
+    #[test]
+    fn corrections_back_to_the_path_are_reverts() {
+        let mut movement = walked(5);
+        let correction = movement.synchronize(&sync(2, (2.2, 64.0, 0.0), 0));
+        assert_eq!(correction.cause, Cause::Reverted);
+        // Sent back to the second move: the three after it are undone.
+        assert_eq!(correction.moves_undone, 3);
+        assert_eq!(correction.from, Some((5.0, 64.0, 0.0)));
+        assert_eq!(correction.distance, 2.8);
+        assert_eq!(movement.position(), Some((2.2, 64.0, 0.0)));
+        assert_eq!(movement.distance(), 5.0);
+    }
+
+    #[test]
+    fn corrections_elsewhere_are_teleports() {
+        let mut movement = walked(5);
+        let correction = movement.synchronize(&sync(2, (100.0, 70.0, -20.0), 0));
+        assert_eq!((correction.cause, correction.moves_undone), (Cause::Teleport, 0));
+
+        // A respawn's first position is never a teleport, however far.
+        movement.confirm(2);
+        movement.respawn();
+        assert_eq!(movement.synchronize(&sync(3, (0.0, 64.0, 0.0), 0)).cause, Cause::Respawn);
+    }
+
+    #[test]
+    fn relative_corrections_in_place() {
+        let mut movement = walked(3);
+        let correction = movement.synchronize(&sync(2, (0.0, 0.0, 0.0), RELATIVE_X | RELATIVE_Y | RELATIVE_Z | RELATIVE_PITCH));
+        assert_eq!((correction.cause, correction.to, correction.distance), (Cause::InPlace, (3.0, 64.0, 0.0), 0.0));
+
+        // Relative coordinates add to the last position; absolute rotation replaces it.
+        movement.confirm(2);
+        movement.client_moved(None, Some((90.0, 10.0)), true);
+        movement.synchronize(&sync(3, (0.5, 0.0, 0.0), RELATIVE_X | RELATIVE_YAW));
+        assert_eq!(movement.position(), Some((3.5, 0.0, 0.0)));
+        assert_eq!(movement.rotation(), (90.0, 0.0));
+    }
+
+    #[test]
+    fn moves_before_confirmation_are_ignored() {
+        let mut movement = walked(2);
+        movement.synchronize(&sync(2, (0.0, 64.0, 0.0), 0));
+        assert!(movement.awaiting_confirmation());
+        assert!(!movement.client_moved(Some((3.0, 64.0, 0.0)), None, true));
+        assert!(!movement.client_moved(Some((4.0, 64.0, 0.0)), Some((1.0, 1.0)), false));
+        assert_eq!(movement.position(), Some((0.0, 64.0, 0.0)));
+        assert_eq!(movement.distance(), 2.0);
+        assert_eq!(movement.last_correction().unwrap().moves_ignored, 2);
+
+        // Only the latest teleport id confirms, and only once.
+        assert!(movement.confirm(1).is_none());
+        assert!(movement.confirm(2).unwrap().confirmed);
+        assert!(movement.confirm(2).is_none());
+        assert!(!movement.awaiting_confirmation());
+        assert!(movement.client_moved(Some((1.0, 64.0, 0.0)), None, true));
+        assert_eq!(movement.distance(), 3.0);
+    }
+
+    #[test]
+    fn superseded_corrections_stay_unconfirmed() {
+        let mut movement = walked(1);
+        movement.synchronize(&sync(2, (50.0, 64.0, 0.0), 0));
+        movement.client_moved(Some((51.0, 64.0, 0.0)), None, true);
+        movement.synchronize(&sync(3, (60.0, 64.0, 0.0), 0));
+        assert!(movement.confirm(2).is_none());
+        assert!(movement.confirm(3).is_some());
+        let confirmed: Vec<(i32, bool, usize)> = movement.corrections()
+            .map(|correction| (correction.teleport_id, correction.confirmed, correction.moves_ignored))
+            .collect();
+        assert_eq!(confirmed, [(1, true, 0), (2, false, 1), (3, true, 0)]);
+    }
+}
@@ -0,0 +1,446 @@
+//! Named Binary Tag: the format of chunk block entities, item stacks, registry data and
+//! world files.
+
+use crate::utils::{read_bytes, read_nbt_string, write_nbt_string, ProtocolError};
+use byteorder::{BigEndian, ReadBytesExt};
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use std::fmt::{self, Write as _};
+use std::io::{self, Cursor, Read, Write};
+
+/// How deep compounds and lists may nest, as in vanilla; deeper input is rejected rather
+/// than risking the stack.
+pub const MAX_DEPTH: usize = 512;
+
+pub const END: u8 = 0;
+pub const BYTE: u8 = 1;
+pub const SHORT: u8 = 2;
+pub const INT: u8 = 3;
+pub const LONG: u8 = 4;
+pub const FLOAT: u8 = 5;
+pub const DOUBLE: u8 = 6;
+pub const BYTE_ARRAY: u8 = 7;
+pub const STRING: u8 = 8;
+pub const LIST: u8 = 9;
+pub const COMPOUND: u8 = 10;
+pub const INT_ARRAY: u8 = 11;
+pub const LONG_ARRAY: u8 = 12;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(List),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+/// A list keeps its element type so an empty list is written back the way it was read.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct List {
+    pub id: u8,
+    pub items: Vec<Tag>,
+}
+
+/// Entries in the order they were read, which is also the order they are written in.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Compound(pub Vec<(String, Tag)>);
+
+impl Compound {
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        self.0.iter().find(|(key, _)| key == name).map(|(_, tag)| tag)
+    }
+
+    /// Sets `name`, replacing an existing entry in place.
+    pub fn insert(&mut self, name: impl Into<String>, tag: Tag) {
+        let name = name.into();
+        match self.0.iter_mut().find(|(key, _)| *key == name) {
+            Some((_, existing)) => *existing = tag,
+            None => self.0.push((name, tag)),
+        }
+    }
+}
+
+impl Tag {
+    pub fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => BYTE,
+            Tag::Short(_) => SHORT,
+            Tag::Int(_) => INT,
+            Tag::Long(_) => LONG,
+            Tag::Float(_) => FLOAT,
+            Tag::Double(_) => DOUBLE,
+            Tag::ByteArray(_) => BYTE_ARRAY,
+            Tag::String(_) => STRING,
+            Tag::List(_) => LIST,
+            Tag::Compound(_) => COMPOUND,
+            Tag::IntArray(_) => INT_ARRAY,
+            Tag::LongArray(_) => LONG_ARRAY,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&Compound> {
+        match self {
+            Tag::Compound(compound) => Some(compound),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match self {
+            Tag::LongArray(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Reads the payload of a tag whose id has already been read.
+    pub fn read(buff: &mut impl Read, id: u8) -> io::Result<Tag> {
+        read_payload(buff, id, 0)
+    }
+
+    /// Writes the payload only; the id and name are up to the caller.
+    pub fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Tag::Byte(value) => out.push(*value as u8),
+            Tag::Short(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Tag::Int(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Tag::Long(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Tag::Float(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Tag::Double(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Tag::ByteArray(values) => {
+                out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+                out.extend(values.iter().map(|value| *value as u8));
+            }
+            Tag::String(value) => write_nbt_string(out, value),
+            Tag::List(list) => {
+                // A list that holds items cannot claim to hold nothing.
+                let id = match list.items.first() {
+                    Some(first) => first.id(),
+                    None => list.id,
+                };
+                out.push(id);
+                out.extend_from_slice(&(list.items.len() as i32).to_be_bytes());
+                for item in &list.items {
+                    item.write(out);
+                }
+            }
+            Tag::Compound(compound) => {
+                for (name, tag) in &compound.0 {
+                    out.push(tag.id());
+                    write_nbt_string(out, name);
+                    tag.write(out);
+                }
+                out.push(END);
+            }
+            Tag::IntArray(values) => {
+                out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+                values.iter().for_each(|value| out.extend_from_slice(&value.to_be_bytes()));
+            }
+            Tag::LongArray(values) => {
+                out.extend_from_slice(&(values.len() as i32).to_be_bytes());
+                values.iter().for_each(|value| out.extend_from_slice(&value.to_be_bytes()));
+            }
+        }
+    }
+}
+
+fn bad_nbt(reason: String) -> io::Error {
+    ProtocolError::BadNbt(reason).into_error()
+}
+
+fn read_len(buff: &mut impl Read) -> io::Result<usize> {
+    let len = buff.read_i32::<BigEndian>()?;
+    usize::try_from(len).map_err(|_| ProtocolError::NegativeLength(len).into_error())
+}
+
+fn read_payload(buff: &mut impl Read, id: u8, depth: usize) -> io::Result<Tag> {
+    if depth > MAX_DEPTH {
+        return Err(bad_nbt(format!("nested deeper than {}", MAX_DEPTH)));
+    }
+    Ok(match id {
+        BYTE => Tag::Byte(buff.read_i8()?),
+        SHORT => Tag::Short(buff.read_i16::<BigEndian>()?),
+        INT => Tag::Int(buff.read_i32::<BigEndian>()?),
+        LONG => Tag::Long(buff.read_i64::<BigEndian>()?),
+        FLOAT => Tag::Float(buff.read_f32::<BigEndian>()?),
+        DOUBLE => Tag::Double(buff.read_f64::<BigEndian>()?),
+        BYTE_ARRAY => {
+            let len = read_len(buff)?;
+            Tag::ByteArray(read_bytes(buff, len)?.into_iter().map(|value| value as i8).collect())
+        }
+        STRING => Tag::String(read_nbt_string(buff)?),
+        LIST => {
+            let id = buff.read_u8()?;
+            let len = read_len(buff)?;
+            // End items take no bytes, so a long list of them would never run out of input.
+            if id == END && len > 0 {
+                return Err(bad_nbt(format!("list of {} end tags", len)));
+            }
+            let mut items = Vec::new();
+            for _ in 0..len {
+                items.push(read_payload(buff, id, depth + 1)?);
+            }
+            Tag::List(List { id, items })
+        }
+        COMPOUND => {
+            let mut compound = Compound::default();
+            loop {
+                let id = buff.read_u8()?;
+                if id == END {
+                    break;
+                }
+                let name = read_nbt_string(buff)?;
+                let tag = read_payload(buff, id, depth + 1)?;
+                compound.0.push((name, tag));
+            }
+            Tag::Compound(compound)
+        }
+        INT_ARRAY => {
+            let len = read_len(buff)?;
+            let bytes = read_bytes(buff, len * 4)?;
+            Tag::IntArray(bytes.chunks_exact(4).map(|chunk| i32::from_be_bytes(chunk.try_into().unwrap())).collect())
+        }
+        LONG_ARRAY => {
+            let len = read_len(buff)?;
+            let bytes = read_bytes(buff, len * 8)?;
+            Tag::LongArray(bytes.chunks_exact(8).map(|chunk| i64::from_be_bytes(chunk.try_into().unwrap())).collect())
+        }
+        _ => return Err(bad_nbt(format!("unknown tag id {}", id))),
+    })
+}
+
+/// Reads a root tag with a name, as in files and on the network up to 1.20.1. A lone
+/// End tag stands for "no NBT" and gives `None`.
+pub fn read_named(buff: &mut impl Read) -> io::Result<Option<(String, Tag)>> {
+    let id = buff.read_u8()?;
+    if id == END {
+        return Ok(None);
+    }
+    let name = read_nbt_string(buff)?;
+    Ok(Some((name, Tag::read(buff, id)?)))
+}
+
+/// Reads a root tag without a name, as sent on the network since 1.20.2.
+pub fn read_network(buff: &mut impl Read) -> io::Result<Option<Tag>> {
+    let id = buff.read_u8()?;
+    if id == END {
+        return Ok(None);
+    }
+    Tag::read(buff, id).map(Some)
+}
+
+pub fn write_named(out: &mut Vec<u8>, name: &str, tag: &Tag) {
+    out.push(tag.id());
+    write_nbt_string(out, name);
+    tag.write(out);
+}
+
+pub fn write_network(out: &mut Vec<u8>, tag: &Tag) {
+    out.push(tag.id());
+    tag.write(out);
+}
+
+/// How an NBT file is wrapped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zlib,
+}
+
+/// Reads a named-root NBT file, detecting gzip and zlib from the first bytes.
+pub fn read_file(bytes: &[u8]) -> io::Result<(String, Tag, Compression)> {
+    let mut data = Vec::new();
+    let compression = match bytes {
+        [0x1f, 0x8b, ..] => {
+            GzDecoder::new(bytes).read_to_end(&mut data)?;
+            Compression::Gzip
+        }
+        // CMF byte of a zlib stream with a 32K window; no tag id starts with it.
+        [0x78, ..] => {
+            ZlibDecoder::new(bytes).read_to_end(&mut data)?;
+            Compression::Zlib
+        }
+        _ => {
+            data.extend_from_slice(bytes);
+            Compression::None
+        }
+    };
+    let (name, tag) = read_named(&mut Cursor::new(&data[..]))?.ok_or_else(|| bad_nbt("empty file".to_string()))?;
+    Ok((name, tag, compression))
+}
+
+pub fn write_file(name: &str, tag: &Tag, compression: Compression) -> Vec<u8> {
+    let mut data = Vec::new();
+    write_named(&mut data, name, tag);
+    match compression {
+        Compression::None => data,
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&data).and_then(|_| encoder.finish()).expect("writing to a Vec cannot fail")
+        }
+        Compression::Zlib => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&data).and_then(|_| encoder.finish()).expect("writing to a Vec cannot fail")
+        }
+    }
+}
+
+/// Writes `value` as an SNBT string, quoted when it is not a plain word.
+fn write_snbt_string(f: &mut fmt::Formatter<'_>, value: &str, bare_allowed: bool) -> fmt::Result {
+    let bare = bare_allowed
+        && !value.is_empty()
+        && value.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+'));
+    if bare {
+        return f.write_str(value);
+    }
+    let quote = if value.contains('"') && !value.contains('\'') { '\'' } else { '"' };
+    f.write_char(quote)?;
+    for c in value.chars() {
+        if c == quote || c == '\\' {
+            f.write_char('\\')?;
+        }
+        f.write_char(c)?;
+    }
+    f.write_char(quote)
+}
+
+fn write_array<T>(f: &mut fmt::Formatter<'_>, prefix: char, values: &[T], suffix: &str) -> fmt::Result
+where
+    T: fmt::Display,
+{
+    write!(f, "[{};", prefix)?;
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            f.write_char(',')?;
+        }
+        write!(f, "{}{}", value, suffix)?;
+    }
+    f.write_char(']')
+}
+
+/// SNBT, the text form used by commands: `{name:"Steve",Pos:[1.0d,64.0d,-3.5d]}`.
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tag::Byte(value) => write!(f, "{}b", value),
+            Tag::Short(value) => write!(f, "{}s", value),
+            Tag::Int(value) => write!(f, "{}", value),
+            Tag::Long(value) => write!(f, "{}L", value),
+            Tag::Float(value) => write!(f, "{:?}f", value),
+            Tag::Double(value) => write!(f, "{:?}d", value),
+            Tag::ByteArray(values) => write_array(f, 'B', values, "b"),
+            Tag::String(value) => write_snbt_string(f, value, false),
+            Tag::List(list) => {
+                f.write_char('[')?;
+                for (i, item) in list.items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_char(']')
+            }
+            Tag::Compound(compound) => write!(f, "{}", compound),
+            Tag::IntArray(values) => write_array(f, 'I', values, ""),
+            Tag::LongArray(values) => write_array(f, 'L', values, "L"),
+        }
+    }
+}
+
+impl fmt::Display for Compound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('{')?;
+        for (i, (name, tag)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_char(',')?;
+            }
+            write_snbt_string(f, name, true)?;
+            write!(f, ":{}", tag)?;
+        }
+        f.write_char('}')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One of every tag type, with strings that need modified UTF-8.
+    fn sample() -> Tag {
+        let mut inner = Compound::default();
+        inner.insert("name", Tag::String("Steve \u{0}\u{1F600}".to_string()));
+        inner.insert("empty", Tag::List(List { id: INT, items: Vec::new() }));
+        let mut root = Compound::default();
+        root.insert("byte", Tag::Byte(-1));
+        root.insert("short", Tag::Short(i16::MIN));
+        root.insert("int", Tag::Int(123_456));
+        root.insert("long", Tag::Long(i64::MAX));
+        root.insert("float", Tag::Float(0.5));
+        root.insert("double", Tag::Double(-64.25));
+        root.insert("bytes", Tag::ByteArray(vec![1, -2, 3]));
+        root.insert("list", Tag::List(List { id: DOUBLE, items: vec![Tag::Double(1.0), Tag::Double(2.0)] }));
+        root.insert("compound", Tag::Compound(inner));
+        root.insert("ints", Tag::IntArray(vec![i32::MIN, 0, i32::MAX]));
+        root.insert("longs", Tag::LongArray(vec![-1, 1]));
+        Tag::Compound(root)
+    }
+
+    #[test]
+    fn named_and_network_round_trip() {
+        let tag = sample();
+        let mut out = Vec::new();
+        write_named(&mut out, "root", &tag);
+        assert_eq!(read_named(&mut Cursor::new(&out[..])).unwrap(), Some(("root".to_string(), tag.clone())));
+
+        let mut out = Vec::new();
+        write_network(&mut out, &tag);
+        assert_eq!(read_network(&mut Cursor::new(&out[..])).unwrap(), Some(tag));
+        assert_eq!(read_network(&mut Cursor::new(&[END][..])).unwrap(), None);
+    }
+
+    #[test]
+    fn files_round_trip_in_every_compression() {
+        let tag = sample();
+        for compression in [Compression::None, Compression::Gzip, Compression::Zlib] {
+            let file = write_file("Data", &tag, compression);
+            assert_eq!(read_file(&file).unwrap(), ("Data".to_string(), tag.clone(), compression));
+        }
+    }
+
+    /// The payload of `levels` lists, each holding the next; the innermost is empty.
+    fn nested_lists(levels: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        for _ in 1..levels {
+            out.extend_from_slice(&[LIST, 0, 0, 0, 1]);
+        }
+        out.extend_from_slice(&[END, 0, 0, 0, 0]);
+        out
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        assert!(Tag::read(&mut Cursor::new(&nested_lists(MAX_DEPTH + 1)[..]), LIST).is_ok());
+        let error = Tag::read(&mut Cursor::new(&nested_lists(MAX_DEPTH + 2)[..]), LIST).unwrap_err();
+        assert!(error.to_string().contains("nested deeper"), "{}", error);
+    }
+
+    #[test]
+    fn snbt() {
+        let mut compound = Compound::default();
+        compound.insert("name", Tag::String("Steve".to_string()));
+        compound.insert("has space", Tag::String("say \"hi\"".to_string()));
+        compound.insert("Pos", Tag::List(List { id: DOUBLE, items: vec![Tag::Double(1.0), Tag::Double(-3.5)] }));
+        compound.insert("longs", Tag::LongArray(vec![1, 2]));
+        compound.insert("flag", Tag::Byte(1));
+        assert_eq!(Tag::Compound(compound).to_string(),
+                   r#"{name:"Steve","has space":'say "hi"',Pos:[1.0d,-3.5d],longs:[L;1L,2L],flag:1b}"#);
+    }
+}
@@ -4,21 +4,104 @@ pub trait Packet{
     fn make_string(&self) -> String;
 }
 
+// Limits from the protocol spec, enforced in strict mode. String limits are in characters.
+pub const MAX_USERNAME_LEN: usize = 16;
+pub const MAX_PROPERTY_LEN: usize = 32767;
+pub const MAX_CHAT_LEN: usize = 262144;
+pub const MAX_IDENTIFIER_LEN: usize = 32767;
+// Byte limits for the signing key and signatures sent with Login Start and Player Info.
+pub const MAX_PUBLIC_KEY_LEN: usize = 512;
+pub const MAX_SIGNATURE_LEN: usize = 4096;
+/// Characters in a chat message or command typed by a player.
+pub const MAX_MESSAGE_LEN: usize = 256;
+/// Entries in the last seen messages a client acknowledges with its chat.
+pub const MAX_LAST_SEEN: usize = 5;
+/// Characters in the name of a signed command argument.
+pub const MAX_ARGUMENT_NAME_LEN: usize = 16;
+
+/// Packets the proxy writes itself.
+pub trait PacketWrite{
+    /// Packet id followed by the fields, ready for `utils::encode_frame`.
+    fn as_bytes(&self) -> Vec<u8>;
+}
+
+/// Packet names for protocol 760 (1.19.2), indexed by packet id.
+pub mod names{
+    use crate::utils::{Direction, State};
+
+    const HANDSHAKE_SERVERBOUND: [&str; 1] = [
+        "Handshake",
+    ];
+    const STATUS_SERVERBOUND: [&str; 2] = [
+        "StatusRequest", "PingRequest",
+    ];
+    const STATUS_CLIENTBOUND: [&str; 2] = [
+        "StatusResponse", "PingResponse",
+    ];
+    const LOGIN_SERVERBOUND: [&str; 3] = [
+        "LoginStart", "EncryptionResponse", "LoginPluginResponse",
+    ];
+    const LOGIN_CLIENTBOUND: [&str; 5] = [
+        "Disconnect", "EncryptionRequest", "LoginSuccess", "SetCompression", "LoginPluginRequest",
+    ];
+    const PLAY_SERVERBOUND: [&str; 51] = [
+        "ConfirmTeleportation", "QueryBlockEntityTag", "ChangeDifficulty", "MessageAcknowledgment", "ChatCommand", "ChatMessage", "ChatPreview", "ClientCommand",
+        "ClientInformation", "CommandSuggestionsRequest", "ClickContainerButton", "ClickContainer", "CloseContainer", "PluginMessage", "EditBook", "QueryEntityTag",
+        "Interact", "JigsawGenerate", "KeepAlive", "LockDifficulty", "SetPlayerPosition", "SetPlayerPosAndRot", "SetPlayerRotation", "SetPlayerOnGround",
+        "MoveVehicle", "PaddleBoat", "PickItem", "PlaceRecipe", "PlayerAbilities", "PlayerAction", "PlayerCommand", "PlayerInput",
+        "Pong", "ChangeRecipeBookSettings", "SetSeenRecipe", "RenameItem", "ResourcePack", "SeenAdvancements", "SelectTrade", "SetBeaconEffect",
+        "SetHeldItem", "ProgramCommandBlock", "ProgramCommandBlockMinecart", "SetCreativeModeSlot", "ProgramJigsawBlock", "ProgramStructureBlock", "UpdateSign", "SwingArm",
+        "TeleportToEntity", "UseItemOn", "UseItem",
+    ];
+    const PLAY_CLIENTBOUND: [&str; 108] = [
+        "SpawnEntity", "SpawnExperienceOrb", "SpawnPlayer", "EntityAnimation", "AwardStatistics", "AcknowledgeBlockChange", "SetBlockDestroyStage", "BlockEntityData",
+        "BlockAction", "BlockUpdate", "BossBar", "ChangeDifficulty", "ChatPreview", "ClearTitles", "CommandSuggestionsResponse", "Commands",
+        "CloseContainer", "SetContainerContent", "SetContainerProperty", "SetContainerSlot", "SetCooldown", "ChatSuggestions", "PluginMessage", "CustomSoundEffect",
+        "HideMessage", "Disconnect", "EntityEvent", "Explosion", "UnloadChunk", "GameEvent", "OpenHorseScreen", "InitializeWorldBorder",
+        "KeepAlive", "ChunkDataAndUpdateLight", "WorldEvent", "Particle", "UpdateLight", "Login", "MapData", "MerchantOffers",
+        "UpdateEntityPosition", "UpdateEntityPositionAndRotation", "UpdateEntityRotation", "MoveVehicle", "OpenBook", "OpenScreen", "OpenSignEditor", "Ping",
+        "PlaceGhostRecipe", "PlayerAbilities", "MessageHeader", "PlayerChatMessage", "EndCombat", "EnterCombat", "CombatDeath", "PlayerInfo",
+        "LookAt", "SynchronizePlayerPosition", "UpdateRecipeBook", "RemoveEntities", "RemoveEntityEffect", "ResourcePack", "Respawn", "SetHeadRotation",
+        "UpdateSectionBlocks", "SelectAdvancementsTab", "ServerData", "SetActionBarText", "SetBorderCenter", "SetBorderLerpSize", "SetBorderSize", "SetBorderWarningDelay",
+        "SetBorderWarningDistance", "SetCamera", "SetHeldItem", "SetCenterChunk", "SetRenderDistance", "SetDefaultSpawnPosition", "SetDisplayChatPreview", "DisplayObjective",
+        "SetEntityMetadata", "LinkEntities", "SetEntityVelocity", "SetEquipment", "SetExperience", "SetHealth", "UpdateObjectives", "SetPassengers",
+        "UpdateTeams", "UpdateScore", "SetSimulationDistance", "SetSubtitleText", "UpdateTime", "SetTitleText", "SetTitleAnimationTimes", "EntitySoundEffect",
+        "SoundEffect", "StopSound", "SystemChatMessage", "SetTabListHeaderAndFooter", "TagQueryResponse", "PickupItem", "TeleportEntity", "UpdateAdvancements",
+        "UpdateAttributes", "EntityEffect", "UpdateRecipes", "UpdateTags",
+    ];
+
+    /// Returns the name of packet `id`, or `"Unknown"` for ids outside the table.
+    pub fn packet_name(state: State, direction: Direction, id: i32) -> &'static str{
+        let table: &[&'static str] = match (state, direction){
+            (State::Handshake, Direction::Serverbound) => &HANDSHAKE_SERVERBOUND,
+            (State::Status, Direction::Serverbound) => &STATUS_SERVERBOUND,
+            (State::Status, Direction::Clientbound) => &STATUS_CLIENTBOUND,
+            (State::Login, Direction::Serverbound) => &LOGIN_SERVERBOUND,
+            (State::Login, Direction::Clientbound) => &LOGIN_CLIENTBOUND,
+            (State::Play, Direction::Serverbound) => &PLAY_SERVERBOUND,
+            (State::Play, Direction::Clientbound) => &PLAY_CLIENTBOUND,
+            _ => &[]
+        };
+        usize::try_from(id).ok().and_then(|i| table.get(i)).copied().unwrap_or("Unknown")
+    }
+}
+
 pub mod client{
     use std::io::{Cursor, ErrorKind,Error};
     use byteorder::{BigEndian, ReadBytesExt};
-    use super::Packet;
-    use crate::utils::{State, read_string_255, read_var_int};
+    use super::{Packet, PacketWrite};
+    use crate::utils::{State, check_length, read_bytes, read_field, read_string, read_string_255, read_var_int, write_string, write_var_int};
+    use super::{MAX_ARGUMENT_NAME_LEN, MAX_LAST_SEEN, MAX_MESSAGE_LEN, MAX_PUBLIC_KEY_LEN, MAX_SIGNATURE_LEN, MAX_USERNAME_LEN};
 
 
     #[derive(Clone, Copy, Debug)]
-    pub enum StatusPacketId{
+    pub enum HandshakePacketId{
         Handshake = 0x00,
         Unknown
     } 
     
-    impl StatusPacketId{
-        pub fn from_u8(input: &u8) -> StatusPacketId{
+    impl HandshakePacketId{
+        pub fn from_id(input: i32) -> HandshakePacketId{
             match input{
                 0x00 => Self::Handshake,
                 _ => Self::Unknown           
@@ -26,6 +109,24 @@ pub mod client{
         }
 
     }
+
+    #[derive(Clone, Copy, Debug)]
+    pub enum StatusPacketId{
+        StatusRequest = 0x00,
+        PingRequest = 0x01,
+        Unknown
+    }
+
+    impl StatusPacketId{
+        pub fn from_id(input: i32) -> StatusPacketId{
+            match input{
+                0x00 => Self::StatusRequest,
+                0x01 => Self::PingRequest,
+                _ => Self::Unknown
+            }
+        }
+    }
+
     #[derive(Clone, Copy)]
     pub enum LoginPacketId{
         Start   = 0x00,
@@ -33,7 +134,7 @@ pub mod client{
     }
 
     impl LoginPacketId{
-        pub fn from_u8(input: &u8) -> LoginPacketId{
+        pub fn from_id(input: i32) -> LoginPacketId{
             match input{
                 0x00 => Self::Start,
                 _ => Self::Unknonwn
@@ -41,20 +142,28 @@ pub mod client{
         }
     }
     pub enum PlayPacketId{
+        ConfirmTeleportation = 0x00,
+        ChatCommand         = 0x04,
+        ChatMessage         = 0x05,
         KeepAlive           = 0x12,
         SetPlayerPosition   = 0x14,
         SetPLayerPosAndRot  = 0x15,
         SetPlayerRotation   = 0x16,
+        SetPlayerOnGround   = 0x17,
         Unknonwn
     }
 
     impl PlayPacketId{
-        pub fn from_u8(input: &u8) -> PlayPacketId{
+        pub fn from_id(input: i32) -> PlayPacketId{
             match input {
+                0x00 => Self::ConfirmTeleportation,
+                0x04 => Self::ChatCommand,
+                0x05 => Self::ChatMessage,
                 0x12 => Self::KeepAlive,
                 0x14 => Self::SetPlayerPosition,
                 0x15 => Self::SetPLayerPosAndRot,
                 0x16 => Self::SetPlayerRotation,
+                0x17 => Self::SetPlayerOnGround,
                 _ => Self::Unknonwn
             }
         }
@@ -72,45 +181,32 @@ pub mod client{
         fn parse(packet_buff: &[u8]) -> Result<Self, std::io::Error>{
             let mut cr = Cursor::new(packet_buff);
             let packet = HandshakePacket{
-                protocol_version:match read_var_int(&mut cr){
-                        Ok(value) => value,
-                        Err(e) => return Err(Error::new(e.kind(), 
-                                                    format!("Failed to read protocol version reason: {}", e.to_string())))
-                    },
-                server_address:match read_string_255(&mut cr){
-                        Ok(value) => value,
-                        Err(e) => return Err(Error::new(e.kind(),
-                                                        format!("Failed to read server address reason: {}", e.to_string())))
-                    },
-                server_port: match cr.read_u16::<BigEndian>(){
-                    Ok(value) => value,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                            format!("Failed to read server port reason: {}", e.to_string())))
-                },
-                next_state: match cr.read_u8(){
-                    Ok(value) => match State::from_u8(value){
-                        State::Unknown => return Err(Error::new(ErrorKind::InvalidData,
-                                                                format!("Read invalid status vale of: {:#02x}", value))),
-                        _ => State::from_u8(value)
-                    },
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                            format!("failed to read state reason: {}", e.to_string())))      
-                } 
+                protocol_version: read_field(&mut cr, "protocol_version", |cr| read_var_int(cr))?,
+                server_address: read_field(&mut cr, "server_address", |cr| read_string_255(cr))?,
+                server_port: read_field(&mut cr, "server_port", |cr| cr.read_u16::<BigEndian>())?,
+                next_state: read_field(&mut cr, "next_state", |cr| {
+                    let value = cr.read_u8()?;
+                    match State::from_u8(value){
+                        State::Unknown => Err(Error::new(ErrorKind::InvalidData,
+                                                         format!("Read invalid status vale of: {:#02x}", value))),
+                        state => Ok(state)
+                    }
+                })?
             };
             Ok(packet)
         }
 
         fn make_string(&self) -> String{
-            String::from(format!("protocol_version {}, to server: {}:{} with next state {}",
+            format!("protocol_version {}, to server: {}:{} with next state {}",
                                     self.protocol_version,
                                     self.server_address,
                                     self.server_port,
-                                    self.next_state as u8))
+                                    self.next_state as u8)
         }
     }
     #[derive(Debug)]
     pub struct LoginStart{
-        player_name: String,
+        pub player_name: String,
         sig_data: bool, //if false the next 5 fields are not sent
         timestamp: Option<i64>, //true 8 bytes value
         pub_key_len: Option<i32>, //varint
@@ -118,51 +214,27 @@ pub mod client{
         sig_len: Option<i32>, //varint
         sig: Option<Vec<u8>>,
         has_uuid: bool,
-        uuid: Option<uuid::Uuid> //only if has_uuid
+        pub uuid: Option<uuid::Uuid> //only if has_uuid
     }
 
     impl Packet for LoginStart{
         fn parse(buff: &[u8]) -> Result<Self, std::io::Error> {
             let mut cr = Cursor::new(buff);
-            let name = match read_string_255(&mut cr){
-                Ok(value) => value,
-                Err(e) => return Err(Error::new(e.kind(),
-                                                        format!("Failed to parse name reason: {}", e.to_string())))
-            };
-            let sig_data = match cr.read_u8(){
-                Ok(value) => value == 0x01,
-                Err(e) => return Err(Error::new(e.kind(),
-                                                            format!("Failed to parse sig_data reason: {}", e.to_string())))
-            };
+            let name = read_field(&mut cr, "name", |cr| read_string(cr, MAX_USERNAME_LEN))?;
+            let sig_data = read_field(&mut cr, "sig_data", |cr| cr.read_u8())? == 0x01;
 
             let (timestamp, pub_key_len, pub_key, sig_len, sig) = if sig_data{
-                let timestamp = match cr.read_i64::<BigEndian>(){
-                    Ok(value) => value,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                                format!("Failed to parse timestamp reason: {}", e.to_string()))) 
-                };
-                let pub_key_len = match read_var_int (&mut cr){
-                    Ok(value) => value,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                            format!("Failed to parse pub_key_len reason: {}", e.to_string())))
-                };
+                let timestamp = read_field(&mut cr, "timestamp", |cr| cr.read_i64::<BigEndian>())?;
+                let pub_key_len = read_field(&mut cr, "pub_key_len", |cr| read_var_int(cr))?;
 
                 let pub_key = if pub_key_len > 0{
-                    let vec = Vec::<u8>::from(&buff[cr.position() as usize..(cr.position()+ pub_key_len as u64) as usize]);
-                    cr.set_position(cr.position() + pub_key_len as u64);
-                    Some(vec)
+                    Some(read_field(&mut cr, "pub_key", |cr| read_bytes(cr, check_length(pub_key_len, MAX_PUBLIC_KEY_LEN)?))?)
                 }else{
                     None
                 };
-                let sig_len = match read_var_int(&mut cr){
-                    Ok(value) => value,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                            format!("Failed to parse sig_len reason: {}", e.to_string())))  
-                };
+                let sig_len = read_field(&mut cr, "sig_len", |cr| read_var_int(cr))?;
                 let sig = if sig_len > 0{
-                    let vec = Vec::<u8>::from(&buff[cr.position() as usize..(cr.position()+ pub_key_len as u64) as usize]);
-                    cr.set_position(cr.position() + sig_len as u64);
-                    Some(vec)
+                    Some(read_field(&mut cr, "sig", |cr| read_bytes(cr, check_length(sig_len, MAX_SIGNATURE_LEN)?))?)
                 }else{
                     None
                 };
@@ -171,71 +243,59 @@ pub mod client{
                 (None, None, None, None, None)
             };
 
-            let has_uuid= match cr.read_u8(){
-                Ok(value) => value == 0x01,
-                Err(e) => return Err(Error::new(e.kind(),
-                                                        format!("Failed to parse has_uuid reason: {}", e.to_string())))
-            };
+            let has_uuid = read_field(&mut cr, "has_uuid", |cr| cr.read_u8())? == 0x01;
 
             let uuid = if has_uuid{
-                match cr.read_u128::<BigEndian>(){
-                    Ok(value) => Some(uuid::Uuid::from_u128(value)),
-                    Err(e)=> return Err(Error::new(e.kind(), 
-                                                            format!("Failed to parse uuid value reason: {}", e.to_string())))
-                }
+                Some(uuid::Uuid::from_u128(read_field(&mut cr, "uuid", |cr| cr.read_u128::<BigEndian>())?))
             }else{
                 None
             };
 
             Ok(LoginStart {
                 player_name: name, 
-                sig_data: sig_data, 
-                timestamp: timestamp, 
-                pub_key_len: pub_key_len, 
-                pub_key: pub_key, 
-                sig_len: sig_len, 
-                sig: sig, 
-                has_uuid:has_uuid, 
-                uuid:uuid })
+                sig_data, 
+                timestamp, 
+                pub_key_len, 
+                pub_key, 
+                sig_len, 
+                sig, 
+                has_uuid, 
+                uuid })
         }
 
         fn make_string(&self) -> String {
-            format!("{:?}", self)
+            let uuid = match self.uuid{
+                Some(uuid) if self.has_uuid => uuid.to_string(),
+                _ => "none".to_string()
+            };
+            format!("player_name:{} uuid:{} sig_data:{} timestamp:{:?} pub_key_len:{:?} sig_len:{:?} (read {} key bytes, {} sig bytes)",
+                    self.player_name,
+                    uuid,
+                    self.sig_data,
+                    self.timestamp,
+                    self.pub_key_len,
+                    self.sig_len,
+                    self.pub_key.as_ref().map_or(0, Vec::len),
+                    self.sig.as_ref().map_or(0, Vec::len))
         }
     }
 
     #[derive(Debug)]
     pub struct SetPlayerPositionPacket{
-        pos_x: f64,
-        pos_y: f64,
-        pos_z: f64,
-        on_ground: bool
+        pub pos_x: f64,
+        pub pos_y: f64,
+        pub pos_z: f64,
+        pub on_ground: bool
     }
 
     impl Packet for SetPlayerPositionPacket {
         fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
             let mut cr = Cursor::new(buff);
             let packet = SetPlayerPositionPacket{
-                pos_x: match cr.read_f64::<BigEndian>(){
-                    Ok(value) => value,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                    format!("Failed to parse pos_x reason: {}", e.to_string())))
-                },
-                pos_y: match cr.read_f64::<BigEndian>(){
-                    Ok(value) => value,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                    format!("Failed to parse pos_y reason: {}", e.to_string())))
-                },
-                pos_z: match cr.read_f64::<BigEndian>(){
-                    Ok(value) => value,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                    format!("Failed to parse pos_z reason: {}", e.to_string())))
-                },
-                on_ground: match cr.read_u8(){
-                    Ok(value) => value == 0x01,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                    format!("Failed to parse on_ground reason: {}", e.to_string())))
-                }
+                pos_x: read_field(&mut cr, "pos_x", |cr| cr.read_f64::<BigEndian>())?,
+                pos_y: read_field(&mut cr, "pos_y", |cr| cr.read_f64::<BigEndian>())?,
+                pos_z: read_field(&mut cr, "pos_z", |cr| cr.read_f64::<BigEndian>())?,
+                on_ground: read_field(&mut cr, "on_ground", |cr| cr.read_u8())? == 0x01
             };
             Ok(packet)
         }
@@ -246,30 +306,18 @@ pub mod client{
     }
 
     pub struct SetPlayerRotationPacket{
-        yaw: f32,
-        pitch: f32,
-        on_ground: bool
+        pub yaw: f32,
+        pub pitch: f32,
+        pub on_ground: bool
     }
 
     impl Packet for SetPlayerRotationPacket{
         fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
             let mut cr = Cursor::new(buff);
             let packet = SetPlayerRotationPacket{
-                yaw: match cr.read_f32::<BigEndian>(){
-                    Ok(value) => value,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                    format!("Failed to parse yaw reason: {}", e.to_string())))
-                },
-                pitch: match cr.read_f32::<BigEndian>(){
-                    Ok(value) => value,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                    format!("Failed to parse pitch reason: {}", e.to_string())))
-                },
-                on_ground: match cr.read_u8(){
-                    Ok(value) => value == 0x01,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                    format!("Failed to parse on_ground reason: {}", e.to_string())))
-                }
+                yaw: read_field(&mut cr, "yaw", |cr| cr.read_f32::<BigEndian>())?,
+                pitch: read_field(&mut cr, "pitch", |cr| cr.read_f32::<BigEndian>())?,
+                on_ground: read_field(&mut cr, "on_ground", |cr| cr.read_u8())? == 0x01
             };
             Ok(packet)
         }
@@ -280,17 +328,14 @@ pub mod client{
     }
 
     pub struct KeepAlivePacket{
-        id: i64
+        pub id: i64
     }
 
     impl Packet for KeepAlivePacket{
         fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
             let mut cr = Cursor::new(buff);
             Ok(KeepAlivePacket {
-                id: match cr.read_i64::<BigEndian>(){
-                    Ok(value) => value,
-                    Err(e) => return Err(Error::new(e.kind(), format!("Failed to parse id reason: {}", e.to_string())))
-                }
+                id: read_field(&mut cr, "id", |cr| cr.read_i64::<BigEndian>())?
             })
         }
 
@@ -300,48 +345,24 @@ pub mod client{
     }
     
     pub struct SetPlayerPosAndRotPacket{
-        pos_x: f64,
-        pos_y: f64,
-        pos_z: f64,
-        yaw: f32,
-        pitch: f32,
-        on_ground: bool
+        pub pos_x: f64,
+        pub pos_y: f64,
+        pub pos_z: f64,
+        pub yaw: f32,
+        pub pitch: f32,
+        pub on_ground: bool
     }
 
     impl Packet for SetPlayerPosAndRotPacket{
         fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
             let mut cr = Cursor::new(buff);
             let packet = SetPlayerPosAndRotPacket{
-                pos_x: match cr.read_f64::<BigEndian>(){
-                    Ok(value) => value,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                    format!("Failed to parse pos_x reason: {}", e.to_string())))
-                },
-                pos_y: match cr.read_f64::<BigEndian>(){
-                    Ok(value) => value,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                    format!("Failed to parse pos_y reason: {}", e.to_string())))
-                },
-                pos_z: match cr.read_f64::<BigEndian>(){
-                    Ok(value) => value,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                    format!("Failed to parse pos_z reason: {}", e.to_string())))
-                },
-                yaw: match cr.read_f32::<BigEndian>(){
-                    Ok(value) => value,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                    format!("Failed to parse yaw reason: {}", e.to_string())))
-                },
-                pitch: match cr.read_f32::<BigEndian>(){
-                    Ok(value) => value,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                    format!("Failed to parse pitch reason: {}", e.to_string())))
-                },
-                on_ground: match cr.read_u8(){
-                    Ok(value) => value == 0x01,
-                    Err(e) => return Err(Error::new(e.kind(),
-                                                    format!("Failed to parse on_ground reason: {}", e.to_string())))
-                }
+                pos_x: read_field(&mut cr, "pos_x", |cr| cr.read_f64::<BigEndian>())?,
+                pos_y: read_field(&mut cr, "pos_y", |cr| cr.read_f64::<BigEndian>())?,
+                pos_z: read_field(&mut cr, "pos_z", |cr| cr.read_f64::<BigEndian>())?,
+                yaw: read_field(&mut cr, "yaw", |cr| cr.read_f32::<BigEndian>())?,
+                pitch: read_field(&mut cr, "pitch", |cr| cr.read_f32::<BigEndian>())?,
+                on_ground: read_field(&mut cr, "on_ground", |cr| cr.read_u8())? == 0x01
             };
             Ok(packet)
         }
@@ -350,20 +371,1602 @@ pub mod client{
         }
     }
 
+    /// Sent instead of a move when only `on_ground` changed.
+    pub struct SetPlayerOnGroundPacket{
+        pub on_ground: bool
+    }
+
+    impl Packet for SetPlayerOnGroundPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(SetPlayerOnGroundPacket {
+                on_ground: read_field(&mut cr, "on_ground", |cr| cr.read_u8())? == 0x01
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("on_ground:{}", self.on_ground)
+        }
+    }
+
+    /// The client's answer to Synchronize Player Position.
+    pub struct ConfirmTeleportationPacket{
+        pub teleport_id: i32
+    }
+
+    impl Packet for ConfirmTeleportationPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(ConfirmTeleportationPacket {
+                teleport_id: read_field(&mut cr, "teleport_id", |cr| read_var_int(cr))?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("teleport_id:{}", self.teleport_id)
+        }
+    }
+
+    /// A message signature: a VarInt length, then the bytes.
+    fn read_signature(cr: &mut Cursor<&[u8]>) -> Result<Vec<u8>, Error>{
+        let len = read_field(cr, "signature_length", |cr| read_var_int(cr))?;
+        read_field(cr, "signature", |cr| read_bytes(cr, check_length(len, MAX_SIGNATURE_LEN)?))
+    }
+
+    fn write_signature(out: &mut Vec<u8>, signature: &[u8]){
+        write_var_int(out, signature.len() as i32);
+        out.extend_from_slice(signature);
+    }
+
+    /// Messages the client has seen, by sender and signature, sent along with its own
+    /// chat so the server can check the chain.
+    #[derive(Clone, Debug, Default)]
+    pub struct LastSeen{
+        pub seen: Vec<(uuid::Uuid, Vec<u8>)>,
+        pub last_received: Option<(uuid::Uuid, Vec<u8>)>
+    }
+
+    impl LastSeen{
+        fn read(cr: &mut Cursor<&[u8]>) -> Result<LastSeen, Error>{
+            let count = check_length(read_field(cr, "last_seen_count", |cr| read_var_int(cr))?, MAX_LAST_SEEN)?;
+            let mut seen = Vec::new();
+            for _ in 0..count{
+                let sender = uuid::Uuid::from_u128(read_field(cr, "last_seen_sender", |cr| cr.read_u128::<BigEndian>())?);
+                seen.push((sender, read_signature(cr)?));
+            }
+            let last_received = if read_field(cr, "has_last_received", |cr| cr.read_u8())? == 0x01{
+                let sender = uuid::Uuid::from_u128(read_field(cr, "last_received_sender", |cr| cr.read_u128::<BigEndian>())?);
+                Some((sender, read_signature(cr)?))
+            }else{
+                None
+            };
+            Ok(LastSeen { seen, last_received })
+        }
+
+        fn write(&self, out: &mut Vec<u8>){
+            write_var_int(out, self.seen.len() as i32);
+            for (sender, signature) in &self.seen{
+                out.extend_from_slice(&sender.as_u128().to_be_bytes());
+                write_signature(out, signature);
+            }
+            out.push(self.last_received.is_some() as u8);
+            if let Some((sender, signature)) = &self.last_received{
+                out.extend_from_slice(&sender.as_u128().to_be_bytes());
+                write_signature(out, signature);
+            }
+        }
+    }
+
+    /// A chat message typed by the player. An empty `signature` is an unsigned message.
+    pub struct ChatMessagePacket{
+        pub message: String,
+        pub timestamp: i64,
+        pub salt: i64,
+        pub signature: Vec<u8>,
+        pub signed_preview: bool,
+        pub last_seen: LastSeen
+    }
+
+    impl Packet for ChatMessagePacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(ChatMessagePacket {
+                message: read_field(&mut cr, "message", |cr| read_string(cr, MAX_MESSAGE_LEN))?,
+                timestamp: read_field(&mut cr, "timestamp", |cr| cr.read_i64::<BigEndian>())?,
+                salt: read_field(&mut cr, "salt", |cr| cr.read_i64::<BigEndian>())?,
+                signature: read_signature(&mut cr)?,
+                signed_preview: read_field(&mut cr, "signed_preview", |cr| cr.read_u8())? == 0x01,
+                last_seen: LastSeen::read(&mut cr)?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("message:{:?} timestamp:{} signed:{} last_seen:{}", self.message, self.timestamp, !self.signature.is_empty(), self.last_seen.seen.len())
+        }
+    }
+
+    impl PacketWrite for ChatMessagePacket{
+        fn as_bytes(&self) -> Vec<u8> {
+            let mut out = Vec::new();
+            write_var_int(&mut out, PlayPacketId::ChatMessage as i32);
+            write_string(&mut out, &self.message);
+            out.extend_from_slice(&self.timestamp.to_be_bytes());
+            out.extend_from_slice(&self.salt.to_be_bytes());
+            write_signature(&mut out, &self.signature);
+            out.push(self.signed_preview as u8);
+            self.last_seen.write(&mut out);
+            out
+        }
+    }
+
+    /// A command typed by the player, without the leading slash. Arguments that are
+    /// chat messages, like the one of `/msg`, come with their own signatures.
+    pub struct ChatCommandPacket{
+        pub command: String,
+        pub timestamp: i64,
+        pub salt: i64,
+        pub argument_signatures: Vec<(String, Vec<u8>)>,
+        pub signed_preview: bool,
+        pub last_seen: LastSeen
+    }
+
+    impl Packet for ChatCommandPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            let command = read_field(&mut cr, "command", |cr| read_string(cr, MAX_MESSAGE_LEN))?;
+            let timestamp = read_field(&mut cr, "timestamp", |cr| cr.read_i64::<BigEndian>())?;
+            let salt = read_field(&mut cr, "salt", |cr| cr.read_i64::<BigEndian>())?;
+            let count = read_field(&mut cr, "argument_count", |cr| read_var_int(cr))?;
+            let mut argument_signatures = Vec::new();
+            for _ in 0..count{
+                let name = read_field(&mut cr, "argument_name", |cr| read_string(cr, MAX_ARGUMENT_NAME_LEN))?;
+                argument_signatures.push((name, read_signature(&mut cr)?));
+            }
+            Ok(ChatCommandPacket {
+                command, timestamp, salt, argument_signatures,
+                signed_preview: read_field(&mut cr, "signed_preview", |cr| cr.read_u8())? == 0x01,
+                last_seen: LastSeen::read(&mut cr)?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            let arguments: Vec<&str> = self.argument_signatures.iter().map(|(name, _)| name.as_str()).collect();
+            format!("command:{:?} timestamp:{} signed_arguments:{:?} last_seen:{}", self.command, self.timestamp, arguments, self.last_seen.seen.len())
+        }
+    }
+
+    impl PacketWrite for ChatCommandPacket{
+        fn as_bytes(&self) -> Vec<u8> {
+            let mut out = Vec::new();
+            write_var_int(&mut out, PlayPacketId::ChatCommand as i32);
+            write_string(&mut out, &self.command);
+            out.extend_from_slice(&self.timestamp.to_be_bytes());
+            out.extend_from_slice(&self.salt.to_be_bytes());
+            write_var_int(&mut out, self.argument_signatures.len() as i32);
+            for (name, signature) in &self.argument_signatures{
+                write_string(&mut out, name);
+                write_signature(&mut out, signature);
+            }
+            out.push(self.signed_preview as u8);
+            self.last_seen.write(&mut out);
+            out
+        }
+    }
+
 }
 
 pub mod server{
+    use std::io::{Cursor, Error, ErrorKind};
+    use byteorder::{BigEndian, ReadBytesExt};
+    use super::{Packet, PacketWrite};
+    use crate::chunk::{Chunk, LightData};
+    use crate::metadata::{self, MetadataValue, Slot};
+    use crate::nbt::{self, Compound, Tag};
+    use crate::utils::{check_length, read_angle, read_bytes, read_field, read_string, read_var_int, read_var_long, write_string, write_var_int, Position,
+                       MAX_FRAME_LEN};
+    use super::{MAX_IDENTIFIER_LEN, MAX_CHAT_LEN, MAX_LAST_SEEN, MAX_MESSAGE_LEN, MAX_PUBLIC_KEY_LEN, MAX_PROPERTY_LEN, MAX_SIGNATURE_LEN, MAX_USERNAME_LEN};
+
+    pub enum StatusPacketId{
+        StatusResponse = 0x00,
+        PingResponse = 0x01,
+        Unknown
+    }
+
+    impl StatusPacketId{
+        pub fn from_id(input: i32) -> StatusPacketId{
+            match input{
+                0x00 => Self::StatusResponse,
+                0x01 => Self::PingResponse,
+                _ => Self::Unknown
+            }
+        }
+    }
+
     pub enum LoginPacketId{
+        Disconnect = 0x00,
+        EncryptionRequest = 0x01,
         LoginSuccess = 0x02,
+        SetCompression = 0x03,
         Unknonwn
     }
 
     impl LoginPacketId{
-        pub fn from_u8(input: u8) -> LoginPacketId{
+        pub fn from_id(input: i32) -> LoginPacketId{
             match input{
+                0x0 => Self::Disconnect,
+                0x1 => Self::EncryptionRequest,
                 0x2 => Self::LoginSuccess,
+                0x3 => Self::SetCompression,
+                _ => Self::Unknonwn
+            }
+        }
+    }
+
+    pub enum PlayPacketId{
+        SpawnEntity = 0x00,
+        SpawnExperienceOrb = 0x01,
+        SpawnPlayer = 0x02,
+        BlockEntityData = 0x07,
+        BlockUpdate = 0x09,
+        Commands = 0x0f,
+        Disconnect = 0x19,
+        UnloadChunk = 0x1c,
+        KeepAlive = 0x20,
+        ChunkData = 0x21,
+        UpdateLight = 0x24,
+        Login = 0x25,
+        UpdateEntityPosition = 0x28,
+        UpdateEntityPositionAndRotation = 0x29,
+        UpdateEntityRotation = 0x2a,
+        PlayerAbilities = 0x31,
+        PlayerChat = 0x33,
+        PlayerInfo = 0x37,
+        SynchronizePlayerPosition = 0x39,
+        RemoveEntities = 0x3b,
+        Respawn = 0x3e,
+        SetHeadRotation = 0x3f,
+        UpdateSectionBlocks = 0x40,
+        SetActionBarText = 0x43,
+        SetEntityMetadata = 0x50,
+        SetEntityVelocity = 0x52,
+        SetEquipment = 0x53,
+        SetSubtitleText = 0x5b,
+        SetTitleText = 0x5d,
+        SystemChat = 0x62,
+        TeleportEntity = 0x66,
+        Unknonwn
+    }
+
+    impl PlayPacketId{
+        pub fn from_id(input: i32) -> PlayPacketId{
+            match input{
+                0x00 => Self::SpawnEntity,
+                0x01 => Self::SpawnExperienceOrb,
+                0x02 => Self::SpawnPlayer,
+                0x07 => Self::BlockEntityData,
+                0x09 => Self::BlockUpdate,
+                0x0f => Self::Commands,
+                0x19 => Self::Disconnect,
+                0x1c => Self::UnloadChunk,
+                0x20 => Self::KeepAlive,
+                0x21 => Self::ChunkData,
+                0x24 => Self::UpdateLight,
+                0x25 => Self::Login,
+                0x28 => Self::UpdateEntityPosition,
+                0x29 => Self::UpdateEntityPositionAndRotation,
+                0x2a => Self::UpdateEntityRotation,
+                0x31 => Self::PlayerAbilities,
+                0x33 => Self::PlayerChat,
+                0x37 => Self::PlayerInfo,
+                0x39 => Self::SynchronizePlayerPosition,
+                0x3b => Self::RemoveEntities,
+                0x3e => Self::Respawn,
+                0x3f => Self::SetHeadRotation,
+                0x40 => Self::UpdateSectionBlocks,
+                0x43 => Self::SetActionBarText,
+                0x50 => Self::SetEntityMetadata,
+                0x52 => Self::SetEntityVelocity,
+                0x53 => Self::SetEquipment,
+                0x5b => Self::SetSubtitleText,
+                0x5d => Self::SetTitleText,
+                0x62 => Self::SystemChat,
+                0x66 => Self::TeleportEntity,
                 _ => Self::Unknonwn
             }
         }
     }
+
+    /// Status Response: the server list entry as JSON, with the MOTD under `description`.
+    pub struct StatusResponsePacket{
+        pub json: String
+    }
+
+    impl Packet for StatusResponsePacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(StatusResponsePacket {
+                json: read_field(&mut cr, "json", |cr| read_string(cr, MAX_CHAT_LEN))?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("json:{}", self.json)
+        }
+    }
+
+    /// Login Disconnect; `reason` is a JSON text component.
+    pub struct LoginDisconnectPacket{
+        pub reason: String
+    }
+
+    impl Packet for LoginDisconnectPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(LoginDisconnectPacket {
+                reason: read_field(&mut cr, "reason", |cr| read_string(cr, MAX_CHAT_LEN))?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("reason:{}", self.reason)
+        }
+    }
+
+    impl PacketWrite for LoginDisconnectPacket{
+        fn as_bytes(&self) -> Vec<u8> {
+            let mut out = Vec::new();
+            write_var_int(&mut out, LoginPacketId::Disconnect as i32);
+            write_string(&mut out, &self.reason);
+            out
+        }
+    }
+
+    /// Play Disconnect; `reason` is a JSON text component.
+    pub struct DisconnectPacket{
+        pub reason: String
+    }
+
+    impl Packet for DisconnectPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(DisconnectPacket {
+                reason: read_field(&mut cr, "reason", |cr| read_string(cr, MAX_CHAT_LEN))?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("reason:{}", self.reason)
+        }
+    }
+
+    /// Set Title Text, Set Subtitle Text and Set Action Bar Text, which carry nothing but
+    /// a JSON text component.
+    pub struct TitleTextPacket{
+        pub text: String
+    }
+
+    impl Packet for TitleTextPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(TitleTextPacket {
+                text: read_field(&mut cr, "text", |cr| read_string(cr, MAX_CHAT_LEN))?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("text:{}", self.text)
+        }
+    }
+
+    impl PacketWrite for DisconnectPacket{
+        fn as_bytes(&self) -> Vec<u8> {
+            let mut out = Vec::new();
+            write_var_int(&mut out, PlayPacketId::Disconnect as i32);
+            write_string(&mut out, &self.reason);
+            out
+        }
+    }
+
+    /// System Chat Message; `content` is a JSON text component, shown above the hotbar when `overlay` is set.
+    pub struct SystemChatPacket{
+        pub content: String,
+        pub overlay: bool
+    }
+
+    impl PacketWrite for SystemChatPacket{
+        fn as_bytes(&self) -> Vec<u8> {
+            let mut out = Vec::new();
+            write_var_int(&mut out, PlayPacketId::SystemChat as i32);
+            write_string(&mut out, &self.content);
+            out.push(self.overlay as u8);
+            out
+        }
+    }
+
+    impl Packet for SystemChatPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(SystemChatPacket {
+                content: read_field(&mut cr, "content", |cr| read_string(cr, MAX_CHAT_LEN))?,
+                overlay: read_field(&mut cr, "overlay", |cr| cr.read_u8())? != 0
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("content:{} overlay:{}", self.content, self.overlay)
+        }
+    }
+
+    /// One node of the command graph: the root, a literal word or an argument.
+    pub struct CommandNode{
+        /// Node type in the low two bits (0 root, 1 literal, 2 argument), then executable,
+        /// has redirect and has suggestions type.
+        pub flags: u8,
+        /// Indices into the packet's nodes.
+        pub children: Vec<i32>,
+        pub redirect: Option<i32>,
+        /// For literals and arguments.
+        pub name: Option<String>,
+        /// For arguments: the parser id and its properties as sent.
+        pub parser: Option<(i32, Vec<u8>)>,
+        pub suggestions: Option<String>
+    }
+
+    impl CommandNode{
+        pub const LITERAL: u8 = 0x01;
+        pub const EXECUTABLE: u8 = 0x04;
+
+        /// A literal word, runnable on its own when `executable` is set.
+        pub fn literal(name: &str, executable: bool, children: Vec<i32>) -> CommandNode{
+            CommandNode {
+                flags: Self::LITERAL | if executable { Self::EXECUTABLE } else { 0 },
+                children,
+                redirect: None,
+                name: Some(name.to_string()),
+                parser: None,
+                suggestions: None
+            }
+        }
+
+        pub fn kind(&self) -> u8{
+            self.flags & 0x03
+        }
+    }
+
+    /// Commands: the server's command graph, which drives the client's tab completion.
+    pub struct CommandsPacket{
+        pub nodes: Vec<CommandNode>,
+        pub root: i32
+    }
+
+    /// Reads the properties of argument parser `parser`, returning them as sent. Parser
+    /// ids are the `minecraft:command_argument_type` registry of 1.19.2.
+    fn read_parser_properties(cr: &mut Cursor<&[u8]>, parser: i32) -> Result<Vec<u8>, std::io::Error>{
+        let start = cr.position() as usize;
+        match parser{
+            // brigadier:float, double, integer and long: flags, then min and max if set.
+            1..=4 => {
+                let flags = read_field(cr, "parser_flags", |cr| cr.read_u8())?;
+                let width = [4, 8, 4, 8][parser as usize - 1];
+                for bit in [0x01, 0x02]{
+                    if flags & bit != 0{
+                        read_field(cr, "parser_bound", |cr| read_bytes(cr, width))?;
+                    }
+                }
+            },
+            // brigadier:string: single word, quotable phrase or greedy phrase.
+            5 => { read_field(cr, "parser_string_type", |cr| read_var_int(cr))?; },
+            // minecraft:entity and minecraft:score_holder: flags.
+            6 | 29 => { read_field(cr, "parser_flags", |cr| cr.read_u8())?; },
+            // minecraft:resource_or_tag and minecraft:resource: the registry.
+            43 | 44 => { read_field(cr, "parser_registry", |cr| read_string(cr, MAX_IDENTIFIER_LEN))?; },
+            0..=47 => (),
+            _ => return Err(Error::new(ErrorKind::InvalidData, format!("unknown argument parser {}", parser)))
+        }
+        Ok(cr.get_ref()[start..cr.position() as usize].to_vec())
+    }
+
+    impl Packet for CommandsPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            let count = read_field(&mut cr, "count", |cr| read_var_int(cr).and_then(|len| check_length(len, MAX_FRAME_LEN)))?;
+            let mut nodes = Vec::new();
+            for _ in 0..count{
+                let flags = read_field(&mut cr, "flags", |cr| cr.read_u8())?;
+                let children_count = read_field(&mut cr, "children_count", |cr| read_var_int(cr).and_then(|len| check_length(len, MAX_FRAME_LEN)))?;
+                let mut children = Vec::new();
+                for _ in 0..children_count{
+                    children.push(read_field(&mut cr, "children", |cr| read_var_int(cr))?);
+                }
+                let redirect = if flags & 0x08 != 0 { Some(read_field(&mut cr, "redirect", |cr| read_var_int(cr))?) } else { None };
+                let name = if matches!(flags & 0x03, 1 | 2) { Some(read_field(&mut cr, "name", |cr| read_string(cr, MAX_IDENTIFIER_LEN))?) } else { None };
+                let parser = if flags & 0x03 == 2{
+                    let id = read_field(&mut cr, "parser", |cr| read_var_int(cr))?;
+                    Some((id, read_parser_properties(&mut cr, id)?))
+                }else{
+                    None
+                };
+                let suggestions = if flags & 0x10 != 0 { Some(read_field(&mut cr, "suggestions", |cr| read_string(cr, MAX_IDENTIFIER_LEN))?) } else { None };
+                nodes.push(CommandNode { flags, children, redirect, name, parser, suggestions });
+            }
+            let root = read_field(&mut cr, "root", |cr| read_var_int(cr))?;
+            let in_range = |index: i32| usize::try_from(index).is_ok_and(|index| index < nodes.len());
+            let linked = nodes.iter().flat_map(|node| node.children.iter().chain(&node.redirect)).all(|index| in_range(*index));
+            if !in_range(root) || !linked{
+                return Err(Error::new(ErrorKind::InvalidData, "command node index out of range"));
+            }
+            Ok(CommandsPacket { nodes, root })
+        }
+
+        fn make_string(&self) -> String {
+            let top_level = self.nodes[self.root as usize].children.len();
+            format!("nodes:{} root:{} top_level:{}", self.nodes.len(), self.root, top_level)
+        }
+    }
+
+    impl PacketWrite for CommandsPacket{
+        fn as_bytes(&self) -> Vec<u8> {
+            let mut out = Vec::new();
+            write_var_int(&mut out, PlayPacketId::Commands as i32);
+            write_var_int(&mut out, self.nodes.len() as i32);
+            for node in &self.nodes{
+                out.push(node.flags);
+                write_var_int(&mut out, node.children.len() as i32);
+                for child in &node.children{
+                    write_var_int(&mut out, *child);
+                }
+                if let Some(redirect) = node.redirect{
+                    write_var_int(&mut out, redirect);
+                }
+                if let Some(name) = &node.name{
+                    write_string(&mut out, name);
+                }
+                if let Some((id, properties)) = &node.parser{
+                    write_var_int(&mut out, *id);
+                    out.extend_from_slice(properties);
+                }
+                if let Some(suggestions) = &node.suggestions{
+                    write_string(&mut out, suggestions);
+                }
+            }
+            write_var_int(&mut out, self.root);
+            out
+        }
+    }
+
+    /// Player Chat Message: chat from a player, relayed by the server. Text components
+    /// are JSON; the signatures and the other messages the sender had seen are skipped.
+    pub struct PlayerChatPacket{
+        pub sender: uuid::Uuid,
+        /// What the player typed.
+        pub message: String,
+        /// The message as the server styled it, if it did.
+        pub formatted: Option<String>,
+        pub timestamp: i64,
+        /// Shown instead of `message` when set, e.g. by a chat plugin; not covered by the signature.
+        pub unsigned_content: Option<String>,
+        /// 0 shown as sent, 1 hidden, 2 partly hidden by the server's text filter.
+        pub filter_type: i32,
+        /// Id in the `minecraft:chat_type` registry from Login.
+        pub chat_type: i32,
+        /// The sender's display name.
+        pub network_name: String,
+        /// The recipient's name, for private messages.
+        pub target_name: Option<String>
+    }
+
+    fn read_byte_array(cr: &mut Cursor<&[u8]>, field: &'static str) -> Result<Vec<u8>, std::io::Error>{
+        let len = read_field(cr, field, |cr| read_var_int(cr))?;
+        read_field(cr, field, |cr| read_bytes(cr, check_length(len, MAX_SIGNATURE_LEN)?))
+    }
+
+    fn read_optional_chat(cr: &mut Cursor<&[u8]>, field: &'static str) -> Result<Option<String>, std::io::Error>{
+        if read_field(cr, field, |cr| cr.read_u8())? == 0x01{
+            Ok(Some(read_field(cr, field, |cr| read_string(cr, MAX_CHAT_LEN))?))
+        }else{
+            Ok(None)
+        }
+    }
+
+    impl Packet for PlayerChatPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            if read_field(&mut cr, "has_previous_signature", |cr| cr.read_u8())? == 0x01{
+                read_byte_array(&mut cr, "previous_signature")?;
+            }
+            let sender = read_uuid(&mut cr)?;
+            read_byte_array(&mut cr, "header_signature")?;
+            let message = read_field(&mut cr, "plain_message", |cr| read_string(cr, MAX_MESSAGE_LEN))?;
+            let formatted = read_optional_chat(&mut cr, "formatted_message")?;
+            let timestamp = read_field(&mut cr, "timestamp", |cr| cr.read_i64::<BigEndian>())?;
+            read_field(&mut cr, "salt", |cr| cr.read_i64::<BigEndian>())?;
+            let previous = check_length(read_field(&mut cr, "previous_messages", |cr| read_var_int(cr))?, MAX_LAST_SEEN)?;
+            for _ in 0..previous{
+                read_uuid(&mut cr)?;
+                read_byte_array(&mut cr, "previous_message_signature")?;
+            }
+            let unsigned_content = read_optional_chat(&mut cr, "unsigned_content")?;
+            let filter_type = read_field(&mut cr, "filter_type", |cr| read_var_int(cr))?;
+            if filter_type == 2{
+                let longs = check_length(read_field(&mut cr, "filter_type_bits", |cr| read_var_int(cr))?, MAX_FRAME_LEN / 8)?;
+                read_field(&mut cr, "filter_type_bits", |cr| read_bytes(cr, longs * 8))?;
+            }
+            Ok(PlayerChatPacket {
+                sender, message, formatted, timestamp, unsigned_content, filter_type,
+                chat_type: read_field(&mut cr, "chat_type", |cr| read_var_int(cr))?,
+                network_name: read_field(&mut cr, "network_name", |cr| read_string(cr, MAX_CHAT_LEN))?,
+                target_name: read_optional_chat(&mut cr, "network_target_name")?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("sender:{} name:{} message:{:?} formatted:{:?} unsigned_content:{:?} filter_type:{} chat_type:{} target:{:?}",
+                    self.sender, self.network_name, self.message, self.formatted, self.unsigned_content, self.filter_type, self.chat_type, self.target_name)
+        }
+    }
+
+    pub struct SetCompressionPacket{
+        pub threshold: i32
+    }
+
+    impl Packet for SetCompressionPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(SetCompressionPacket {
+                threshold: read_field(&mut cr, "threshold", |cr| read_var_int(cr))?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("threshold:{}", self.threshold)
+        }
+    }
+
+    /// Login Success; the trailing profile properties are not read.
+    pub struct LoginSuccessPacket{
+        pub uuid: uuid::Uuid,
+        pub username: String
+    }
+
+    impl Packet for LoginSuccessPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(LoginSuccessPacket {
+                uuid: uuid::Uuid::from_u128(read_field(&mut cr, "uuid", |cr| cr.read_u128::<BigEndian>())?),
+                username: read_field(&mut cr, "username", |cr| read_string(cr, MAX_USERNAME_LEN))?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("uuid:{} username:{}", self.uuid, self.username)
+        }
+    }
+
+    pub struct KeepAlivePacket{
+        pub id: i64
+    }
+
+    impl Packet for KeepAlivePacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(KeepAlivePacket {
+                id: read_field(&mut cr, "id", |cr| cr.read_i64::<BigEndian>())?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("KEEP_ALIVE ID:{}", self.id)
+        }
+    }
+
+    /// Chunk Data and Update Light: a whole chunk column.
+    pub struct ChunkDataPacket{
+        pub chunk: Chunk
+    }
+
+    impl Packet for ChunkDataPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            Ok(ChunkDataPacket { chunk: Chunk::read(&mut Cursor::new(buff))? })
+        }
+
+        fn make_string(&self) -> String {
+            self.chunk.summary()
+        }
+    }
+
+    /// Update Light: new light for sections of a column that is already loaded.
+    pub struct UpdateLightPacket{
+        pub x: i32,
+        pub z: i32,
+        pub light: LightData
+    }
+
+    impl Packet for UpdateLightPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(UpdateLightPacket {
+                x: read_field(&mut cr, "chunk_x", |cr| read_var_int(cr))?,
+                z: read_field(&mut cr, "chunk_z", |cr| read_var_int(cr))?,
+                light: LightData::read(&mut cr)?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("x:{} z:{} {}", self.x, self.z, self.light.summary())
+        }
+    }
+
+    /// Login (Play): the player's entity, the world they spawn in and the registry codec.
+    pub struct LoginPlayPacket{
+        pub entity_id: i32,
+        pub is_hardcore: bool,
+        pub gamemode: u8,
+        pub previous_gamemode: i8,
+        pub dimension_names: Vec<String>,
+        /// Dimension types, biomes and chat types, keyed by registry name.
+        pub registry_codec: Tag,
+        pub dimension_type: String,
+        pub dimension_name: String,
+        pub hashed_seed: i64,
+        pub max_players: i32,
+        pub view_distance: i32,
+        pub simulation_distance: i32,
+        pub reduced_debug_info: bool,
+        pub enable_respawn_screen: bool,
+        pub is_debug: bool,
+        pub is_flat: bool,
+        /// Dimension and packed block position.
+        pub death_location: Option<(String, i64)>
+    }
+
+    impl LoginPlayPacket{
+        /// Name and element of every entry in one registry of the codec, e.g. `minecraft:dimension_type`.
+        pub fn registry_entries(&self, registry: &str) -> Vec<(&str, &Compound)>{
+            let entries = self.registry_codec.as_compound()
+                .and_then(|codec| codec.get(registry))
+                .and_then(Tag::as_compound)
+                .and_then(|registry| registry.get("value"));
+            let Some(Tag::List(entries)) = entries else { return Vec::new() };
+            entries.items.iter()
+                .filter_map(Tag::as_compound)
+                .filter_map(|entry| match (entry.get("name"), entry.get("element").and_then(Tag::as_compound)){
+                    (Some(Tag::String(name)), Some(element)) => Some((name.as_str(), element)),
+                    _ => None
+                })
+                .collect()
+        }
+
+        /// Name and protocol id of every entry in one registry of the codec.
+        pub fn registry_ids(&self, registry: &str) -> Vec<(&str, i32)>{
+            let entries = self.registry_codec.as_compound()
+                .and_then(|codec| codec.get(registry))
+                .and_then(Tag::as_compound)
+                .and_then(|registry| registry.get("value"));
+            let Some(Tag::List(entries)) = entries else { return Vec::new() };
+            entries.items.iter()
+                .filter_map(Tag::as_compound)
+                .filter_map(|entry| match (entry.get("name"), entry.get("id")){
+                    (Some(Tag::String(name)), Some(Tag::Int(id))) => Some((name.as_str(), *id)),
+                    _ => None
+                })
+                .collect()
+        }
+
+        /// `registry(entries)` for each registry in the codec, e.g. `minecraft:worldgen/biome(64)`.
+        pub fn registry_summary(&self) -> String{
+            let registries = self.registry_codec.as_compound().map_or(&[][..], |codec| &codec.0[..]);
+            registries.iter()
+                .map(|(name, registry)| {
+                    let entries = match registry.as_compound().and_then(|registry| registry.get("value")){
+                        Some(Tag::List(list)) => list.items.len(),
+                        _ => 0
+                    };
+                    format!("{}({})", name, entries)
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+    }
+
+    impl Packet for LoginPlayPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            let entity_id = read_field(&mut cr, "entity_id", |cr| cr.read_i32::<BigEndian>())?;
+            let is_hardcore = read_field(&mut cr, "is_hardcore", |cr| cr.read_u8())? != 0;
+            let gamemode = read_field(&mut cr, "gamemode", |cr| cr.read_u8())?;
+            let previous_gamemode = read_field(&mut cr, "previous_gamemode", |cr| cr.read_i8())?;
+            let count = read_field(&mut cr, "dimension_count", |cr| read_var_int(cr))?;
+            let mut dimension_names = Vec::new();
+            for _ in 0..count{
+                dimension_names.push(read_field(&mut cr, "dimension_names", |cr| read_string(cr, MAX_IDENTIFIER_LEN))?);
+            }
+            let registry_codec = read_field(&mut cr, "registry_codec", |cr| nbt::read_named(cr))?
+                .map_or(Tag::Compound(Compound::default()), |(_, tag)| tag);
+            Ok(LoginPlayPacket {
+                entity_id,
+                is_hardcore,
+                gamemode,
+                previous_gamemode,
+                dimension_names,
+                registry_codec,
+                dimension_type: read_field(&mut cr, "dimension_type", |cr| read_string(cr, MAX_IDENTIFIER_LEN))?,
+                dimension_name: read_field(&mut cr, "dimension_name", |cr| read_string(cr, MAX_IDENTIFIER_LEN))?,
+                hashed_seed: read_field(&mut cr, "hashed_seed", |cr| cr.read_i64::<BigEndian>())?,
+                max_players: read_field(&mut cr, "max_players", |cr| read_var_int(cr))?,
+                view_distance: read_field(&mut cr, "view_distance", |cr| read_var_int(cr))?,
+                simulation_distance: read_field(&mut cr, "simulation_distance", |cr| read_var_int(cr))?,
+                reduced_debug_info: read_field(&mut cr, "reduced_debug_info", |cr| cr.read_u8())? != 0,
+                enable_respawn_screen: read_field(&mut cr, "enable_respawn_screen", |cr| cr.read_u8())? != 0,
+                is_debug: read_field(&mut cr, "is_debug", |cr| cr.read_u8())? != 0,
+                is_flat: read_field(&mut cr, "is_flat", |cr| cr.read_u8())? != 0,
+                death_location: if read_field(&mut cr, "has_death_location", |cr| cr.read_u8())? != 0{
+                    Some((read_field(&mut cr, "death_dimension_name", |cr| read_string(cr, MAX_IDENTIFIER_LEN))?,
+                          read_field(&mut cr, "death_location", |cr| cr.read_i64::<BigEndian>())?))
+                }else{
+                    None
+                }
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("entity_id:{} hardcore:{} gamemode:{} previous_gamemode:{} dimension:{} type:{} dimensions:{:?} seed:{} max_players:{} view_distance:{} simulation_distance:{} flat:{} death_location:{:?} registries:{}",
+                    self.entity_id, self.is_hardcore, self.gamemode, self.previous_gamemode, self.dimension_name, self.dimension_type,
+                    self.dimension_names, self.hashed_seed, self.max_players, self.view_distance, self.simulation_distance, self.is_flat,
+                    self.death_location, self.registry_summary())
+        }
+    }
+
+    /// Respawn: the player moves to another dimension, or back to the same one after dying.
+    pub struct RespawnPacket{
+        pub dimension_type: String,
+        pub dimension_name: String,
+        pub hashed_seed: i64,
+        pub gamemode: u8,
+        pub previous_gamemode: i8,
+        pub is_debug: bool,
+        pub is_flat: bool,
+        pub copy_metadata: bool,
+        pub death_location: Option<(String, i64)>
+    }
+
+    impl Packet for RespawnPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(RespawnPacket {
+                dimension_type: read_field(&mut cr, "dimension_type", |cr| read_string(cr, MAX_IDENTIFIER_LEN))?,
+                dimension_name: read_field(&mut cr, "dimension_name", |cr| read_string(cr, MAX_IDENTIFIER_LEN))?,
+                hashed_seed: read_field(&mut cr, "hashed_seed", |cr| cr.read_i64::<BigEndian>())?,
+                gamemode: read_field(&mut cr, "gamemode", |cr| cr.read_u8())?,
+                previous_gamemode: read_field(&mut cr, "previous_gamemode", |cr| cr.read_i8())?,
+                is_debug: read_field(&mut cr, "is_debug", |cr| cr.read_u8())? != 0,
+                is_flat: read_field(&mut cr, "is_flat", |cr| cr.read_u8())? != 0,
+                copy_metadata: read_field(&mut cr, "copy_metadata", |cr| cr.read_u8())? != 0,
+                death_location: if read_field(&mut cr, "has_death_location", |cr| cr.read_u8())? != 0{
+                    Some((read_field(&mut cr, "death_dimension_name", |cr| read_string(cr, MAX_IDENTIFIER_LEN))?,
+                          read_field(&mut cr, "death_location", |cr| cr.read_i64::<BigEndian>())?))
+                }else{
+                    None
+                }
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("dimension:{} type:{} seed:{} gamemode:{} previous_gamemode:{} flat:{} copy_metadata:{} death_location:{:?}",
+                    self.dimension_name, self.dimension_type, self.hashed_seed, self.gamemode, self.previous_gamemode,
+                    self.is_flat, self.copy_metadata, self.death_location)
+        }
+    }
+
+    impl RespawnPacket{
+        /// A respawn into the world `login` puts the player in.
+        pub fn from_login(login: &LoginPlayPacket) -> RespawnPacket{
+            RespawnPacket {
+                dimension_type: login.dimension_type.clone(),
+                dimension_name: login.dimension_name.clone(),
+                hashed_seed: login.hashed_seed,
+                gamemode: login.gamemode,
+                previous_gamemode: login.previous_gamemode,
+                is_debug: login.is_debug,
+                is_flat: login.is_flat,
+                copy_metadata: false,
+                death_location: login.death_location.clone()
+            }
+        }
+    }
+
+    impl PacketWrite for RespawnPacket{
+        fn as_bytes(&self) -> Vec<u8> {
+            let mut out = Vec::new();
+            write_var_int(&mut out, PlayPacketId::Respawn as i32);
+            write_string(&mut out, &self.dimension_type);
+            write_string(&mut out, &self.dimension_name);
+            out.extend_from_slice(&self.hashed_seed.to_be_bytes());
+            out.push(self.gamemode);
+            out.push(self.previous_gamemode as u8);
+            out.push(self.is_debug as u8);
+            out.push(self.is_flat as u8);
+            out.push(self.copy_metadata as u8);
+            match &self.death_location{
+                Some((dimension, location)) => {
+                    out.push(1);
+                    write_string(&mut out, dimension);
+                    out.extend_from_slice(&location.to_be_bytes());
+                }
+                None => out.push(0)
+            }
+            out
+        }
+    }
+
+    pub struct UnloadChunkPacket{
+        pub x: i32,
+        pub z: i32
+    }
+
+    impl Packet for UnloadChunkPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(UnloadChunkPacket {
+                x: read_field(&mut cr, "chunk_x", |cr| cr.read_i32::<BigEndian>())?,
+                z: read_field(&mut cr, "chunk_z", |cr| cr.read_i32::<BigEndian>())?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("x:{} z:{}", self.x, self.z)
+        }
+    }
+
+    pub struct BlockUpdatePacket{
+        pub position: Position,
+        pub block_id: i32
+    }
+
+    impl Packet for BlockUpdatePacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(BlockUpdatePacket {
+                position: read_field(&mut cr, "location", |cr| Position::read(cr))?,
+                block_id: read_field(&mut cr, "block_id", |cr| read_var_int(cr))?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("position:{} block_id:{}", self.position, self.block_id)
+        }
+    }
+
+    /// Update Section Blocks: several block changes within one section.
+    pub struct UpdateSectionBlocksPacket{
+        /// Section coordinates, in units of 16 blocks.
+        pub section_x: i32,
+        pub section_y: i32,
+        pub section_z: i32,
+        pub suppress_light_updates: bool,
+        /// Block state id and position within the section (x, y, z, each 0 to 15).
+        pub blocks: Vec<(i32, u8, u8, u8)>
+    }
+
+    impl Packet for UpdateSectionBlocksPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            let section = read_field(&mut cr, "chunk_section_position", |cr| cr.read_i64::<BigEndian>())?;
+            let suppress_light_updates = read_field(&mut cr, "suppress_light_updates", |cr| cr.read_u8())? != 0;
+            let count = read_field(&mut cr, "blocks_array_size", |cr| read_var_int(cr))?;
+            let mut blocks = Vec::new();
+            for _ in 0..count{
+                let block = read_field(&mut cr, "blocks", |cr| read_var_long(cr))?;
+                blocks.push(((block >> 12) as i32, (block >> 8 & 0xf) as u8, (block & 0xf) as u8, (block >> 4 & 0xf) as u8));
+            }
+            Ok(UpdateSectionBlocksPacket {
+                section_x: (section >> 42) as i32,
+                section_y: (section << 44 >> 44) as i32,
+                section_z: (section << 22 >> 42) as i32,
+                suppress_light_updates,
+                blocks
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("section:{},{},{} blocks:{} suppress_light_updates:{}",
+                    self.section_x, self.section_y, self.section_z, self.blocks.len(), self.suppress_light_updates)
+        }
+    }
+
+    pub struct BlockEntityDataPacket{
+        pub position: Position,
+        pub kind: i32,
+        pub data: Option<Tag>
+    }
+
+    impl Packet for BlockEntityDataPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(BlockEntityDataPacket {
+                position: read_field(&mut cr, "location", |cr| Position::read(cr))?,
+                kind: read_field(&mut cr, "type", |cr| read_var_int(cr))?,
+                data: read_field(&mut cr, "nbt_data", |cr| nbt::read_named(cr))?.map(|(_, tag)| tag)
+            })
+        }
+
+        fn make_string(&self) -> String {
+            match &self.data{
+                Some(data) => format!("position:{} type:{} data:{}", self.position, self.kind, data),
+                None => format!("position:{} type:{} data:none", self.position, self.kind)
+            }
+        }
+    }
+
+    fn read_uuid(cr: &mut Cursor<&[u8]>) -> Result<uuid::Uuid, std::io::Error>{
+        read_field(cr, "uuid", |cr| cr.read_u128::<BigEndian>()).map(uuid::Uuid::from_u128)
+    }
+
+    fn read_xyz(cr: &mut Cursor<&[u8]>) -> Result<(f64, f64, f64), std::io::Error>{
+        Ok((read_field(cr, "x", |cr| cr.read_f64::<BigEndian>())?,
+            read_field(cr, "y", |cr| cr.read_f64::<BigEndian>())?,
+            read_field(cr, "z", |cr| cr.read_f64::<BigEndian>())?))
+    }
+
+    /// Three shorts: a velocity in 1/8000 block per tick, or a move in 1/4096 block.
+    fn read_shorts(cr: &mut Cursor<&[u8]>, names: [&'static str; 3]) -> Result<[i16; 3], std::io::Error>{
+        Ok([read_field(cr, names[0], |cr| cr.read_i16::<BigEndian>())?,
+            read_field(cr, names[1], |cr| cr.read_i16::<BigEndian>())?,
+            read_field(cr, names[2], |cr| cr.read_i16::<BigEndian>())?])
+    }
+
+    fn read_on_ground(cr: &mut Cursor<&[u8]>) -> Result<bool, std::io::Error>{
+        read_field(cr, "on_ground", |cr| cr.read_u8()).map(|on_ground| on_ground != 0)
+    }
+
+    /// Spawn Entity, for every entity but players and experience orbs. Angles are in degrees.
+    pub struct SpawnEntityPacket{
+        pub entity_id: i32,
+        pub uuid: uuid::Uuid,
+        /// Id in the `minecraft:entity_type` registry.
+        pub kind: i32,
+        pub x: f64,
+        pub y: f64,
+        pub z: f64,
+        pub pitch: f32,
+        pub yaw: f32,
+        pub head_yaw: f32,
+        /// Meaning depends on the type, e.g. the block state of a falling block.
+        pub data: i32,
+        pub velocity: [i16; 3]
+    }
+
+    impl Packet for SpawnEntityPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            let entity_id = read_field(&mut cr, "entity_id", |cr| read_var_int(cr))?;
+            let uuid = read_uuid(&mut cr)?;
+            let kind = read_field(&mut cr, "type", |cr| read_var_int(cr))?;
+            let (x, y, z) = read_xyz(&mut cr)?;
+            Ok(SpawnEntityPacket {
+                entity_id, uuid, kind, x, y, z,
+                pitch: read_field(&mut cr, "pitch", |cr| read_angle(cr))?,
+                yaw: read_field(&mut cr, "yaw", |cr| read_angle(cr))?,
+                head_yaw: read_field(&mut cr, "head_yaw", |cr| read_angle(cr))?,
+                data: read_field(&mut cr, "data", |cr| read_var_int(cr))?,
+                velocity: read_shorts(&mut cr, ["velocity_x", "velocity_y", "velocity_z"])?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("entity_id:{} uuid:{} type:{} x:{} y:{} z:{} pitch:{} yaw:{} head_yaw:{} data:{} velocity:{:?}",
+                    self.entity_id, self.uuid, self.kind, self.x, self.y, self.z, self.pitch, self.yaw, self.head_yaw, self.data, self.velocity)
+        }
+    }
+
+    pub struct SpawnExperienceOrbPacket{
+        pub entity_id: i32,
+        pub x: f64,
+        pub y: f64,
+        pub z: f64,
+        pub count: i16
+    }
+
+    impl Packet for SpawnExperienceOrbPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            let entity_id = read_field(&mut cr, "entity_id", |cr| read_var_int(cr))?;
+            let (x, y, z) = read_xyz(&mut cr)?;
+            Ok(SpawnExperienceOrbPacket { entity_id, x, y, z, count: read_field(&mut cr, "count", |cr| cr.read_i16::<BigEndian>())? })
+        }
+
+        fn make_string(&self) -> String {
+            format!("entity_id:{} x:{} y:{} z:{} count:{}", self.entity_id, self.x, self.y, self.z, self.count)
+        }
+    }
+
+    pub struct SpawnPlayerPacket{
+        pub entity_id: i32,
+        pub uuid: uuid::Uuid,
+        pub x: f64,
+        pub y: f64,
+        pub z: f64,
+        pub yaw: f32,
+        pub pitch: f32
+    }
+
+    impl Packet for SpawnPlayerPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            let entity_id = read_field(&mut cr, "entity_id", |cr| read_var_int(cr))?;
+            let uuid = read_uuid(&mut cr)?;
+            let (x, y, z) = read_xyz(&mut cr)?;
+            Ok(SpawnPlayerPacket {
+                entity_id, uuid, x, y, z,
+                yaw: read_field(&mut cr, "yaw", |cr| read_angle(cr))?,
+                pitch: read_field(&mut cr, "pitch", |cr| read_angle(cr))?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("entity_id:{} uuid:{} x:{} y:{} z:{} yaw:{} pitch:{}", self.entity_id, self.uuid, self.x, self.y, self.z, self.yaw, self.pitch)
+        }
+    }
+
+    /// Update Entity Position, Update Entity Position and Rotation and Update Entity
+    /// Rotation, which differ only in what they carry. Moves are in 1/4096 block.
+    pub struct UpdateEntityPacket{
+        pub entity_id: i32,
+        pub delta: Option<[i16; 3]>,
+        /// Yaw and pitch in degrees.
+        pub rotation: Option<(f32, f32)>,
+        pub on_ground: bool
+    }
+
+    impl UpdateEntityPacket{
+        fn read(buff: &[u8], moves: bool, rotates: bool) -> Result<Self, std::io::Error>{
+            let mut cr = Cursor::new(buff);
+            let entity_id = read_field(&mut cr, "entity_id", |cr| read_var_int(cr))?;
+            let delta = if moves { Some(read_shorts(&mut cr, ["delta_x", "delta_y", "delta_z"])?) } else { None };
+            let rotation = if rotates {
+                Some((read_field(&mut cr, "yaw", |cr| read_angle(cr))?, read_field(&mut cr, "pitch", |cr| read_angle(cr))?))
+            } else {
+                None
+            };
+            Ok(UpdateEntityPacket { entity_id, delta, rotation, on_ground: read_on_ground(&mut cr)? })
+        }
+
+        fn describe(&self) -> String{
+            let mut out = format!("entity_id:{}", self.entity_id);
+            if let Some(delta) = self.delta{
+                out.push_str(&format!(" delta:{:?}", delta));
+            }
+            if let Some((yaw, pitch)) = self.rotation{
+                out.push_str(&format!(" yaw:{} pitch:{}", yaw, pitch));
+            }
+            out + &format!(" on_ground:{}", self.on_ground)
+        }
+    }
+
+    pub struct UpdateEntityPositionPacket(pub UpdateEntityPacket);
+    pub struct UpdateEntityPositionAndRotationPacket(pub UpdateEntityPacket);
+    pub struct UpdateEntityRotationPacket(pub UpdateEntityPacket);
+
+    impl Packet for UpdateEntityPositionPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            UpdateEntityPacket::read(buff, true, false).map(Self)
+        }
+
+        fn make_string(&self) -> String {
+            self.0.describe()
+        }
+    }
+
+    impl Packet for UpdateEntityPositionAndRotationPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            UpdateEntityPacket::read(buff, true, true).map(Self)
+        }
+
+        fn make_string(&self) -> String {
+            self.0.describe()
+        }
+    }
+
+    impl Packet for UpdateEntityRotationPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            UpdateEntityPacket::read(buff, false, true).map(Self)
+        }
+
+        fn make_string(&self) -> String {
+            self.0.describe()
+        }
+    }
+
+    pub struct RemoveEntitiesPacket{
+        pub entity_ids: Vec<i32>
+    }
+
+    impl Packet for RemoveEntitiesPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            let count = read_field(&mut cr, "count", |cr| read_var_int(cr).and_then(|len| check_length(len, MAX_FRAME_LEN)))?;
+            let mut entity_ids = Vec::new();
+            for _ in 0..count{
+                entity_ids.push(read_field(&mut cr, "entity_id", |cr| read_var_int(cr))?);
+            }
+            Ok(RemoveEntitiesPacket { entity_ids })
+        }
+
+        fn make_string(&self) -> String {
+            format!("entity_ids:{:?}", self.entity_ids)
+        }
+    }
+
+    pub struct SetHeadRotationPacket{
+        pub entity_id: i32,
+        pub head_yaw: f32
+    }
+
+    impl Packet for SetHeadRotationPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(SetHeadRotationPacket {
+                entity_id: read_field(&mut cr, "entity_id", |cr| read_var_int(cr))?,
+                head_yaw: read_field(&mut cr, "head_yaw", |cr| read_angle(cr))?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("entity_id:{} head_yaw:{}", self.entity_id, self.head_yaw)
+        }
+    }
+
+    pub struct SetEntityMetadataPacket{
+        pub entity_id: i32,
+        /// `(index, value)`; indices not sent keep their previous value.
+        pub metadata: Vec<(u8, MetadataValue)>
+    }
+
+    impl Packet for SetEntityMetadataPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(SetEntityMetadataPacket {
+                entity_id: read_field(&mut cr, "entity_id", |cr| read_var_int(cr))?,
+                metadata: read_field(&mut cr, "metadata", metadata::read_metadata)?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            let metadata: Vec<String> = self.metadata.iter().map(|(index, value)| format!("{}={}", index, value)).collect();
+            format!("entity_id:{} metadata:[{}]", self.entity_id, metadata.join(", "))
+        }
+    }
+
+    pub struct SetEntityVelocityPacket{
+        pub entity_id: i32,
+        pub velocity: [i16; 3]
+    }
+
+    impl Packet for SetEntityVelocityPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(SetEntityVelocityPacket {
+                entity_id: read_field(&mut cr, "entity_id", |cr| read_var_int(cr))?,
+                velocity: read_shorts(&mut cr, ["velocity_x", "velocity_y", "velocity_z"])?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("entity_id:{} velocity:{:?}", self.entity_id, self.velocity)
+        }
+    }
+
+    pub struct SetEquipmentPacket{
+        pub entity_id: i32,
+        /// `(slot, item)`, slots indexing `metadata::EQUIPMENT_SLOTS`.
+        pub equipment: Vec<(u8, Option<Slot>)>
+    }
+
+    impl Packet for SetEquipmentPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(SetEquipmentPacket {
+                entity_id: read_field(&mut cr, "entity_id", |cr| read_var_int(cr))?,
+                equipment: read_field(&mut cr, "equipment", metadata::read_equipment)?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            let equipment: Vec<String> = self.equipment.iter().map(|(slot, item)| {
+                let slot = metadata::EQUIPMENT_SLOTS.get(*slot as usize).map_or_else(|| slot.to_string(), |name| name.to_string());
+                format!("{}={}", slot, item.as_ref().map_or("empty".to_string(), |item| item.to_string()))
+            }).collect();
+            format!("entity_id:{} equipment:[{}]", self.entity_id, equipment.join(", "))
+        }
+    }
+
+    pub struct TeleportEntityPacket{
+        pub entity_id: i32,
+        pub x: f64,
+        pub y: f64,
+        pub z: f64,
+        pub yaw: f32,
+        pub pitch: f32,
+        pub on_ground: bool
+    }
+
+    impl Packet for TeleportEntityPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            let entity_id = read_field(&mut cr, "entity_id", |cr| read_var_int(cr))?;
+            let (x, y, z) = read_xyz(&mut cr)?;
+            Ok(TeleportEntityPacket {
+                entity_id, x, y, z,
+                yaw: read_field(&mut cr, "yaw", |cr| read_angle(cr))?,
+                pitch: read_field(&mut cr, "pitch", |cr| read_angle(cr))?,
+                on_ground: read_on_ground(&mut cr)?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("entity_id:{} x:{} y:{} z:{} yaw:{} pitch:{} on_ground:{}",
+                    self.entity_id, self.x, self.y, self.z, self.yaw, self.pitch, self.on_ground)
+        }
+    }
+
+    /// Player Abilities flags.
+    pub const INVULNERABLE: u8 = 0x01;
+    pub const FLYING: u8 = 0x02;
+    pub const ALLOW_FLYING: u8 = 0x04;
+    pub const CREATIVE_MODE: u8 = 0x08;
+
+    pub struct PlayerAbilitiesPacket{
+        pub flags: u8,
+        pub flying_speed: f32,
+        pub fov_modifier: f32
+    }
+
+    impl PlayerAbilitiesPacket{
+        pub fn allow_flying(&self) -> bool{
+            self.flags & ALLOW_FLYING != 0
+        }
+    }
+
+    impl Packet for PlayerAbilitiesPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            Ok(PlayerAbilitiesPacket {
+                flags: read_field(&mut cr, "flags", |cr| cr.read_u8())?,
+                flying_speed: read_field(&mut cr, "flying_speed", |cr| cr.read_f32::<BigEndian>())?,
+                fov_modifier: read_field(&mut cr, "fov_modifier", |cr| cr.read_f32::<BigEndian>())?
+            })
+        }
+
+        fn make_string(&self) -> String {
+            let flag = |flag: u8, name: &'static str| (self.flags & flag != 0).then_some(name);
+            let flags: Vec<&str> = [flag(INVULNERABLE, "invulnerable"), flag(FLYING, "flying"), flag(ALLOW_FLYING, "allow_flying"), flag(CREATIVE_MODE, "creative")]
+                .into_iter().flatten().collect();
+            format!("flags:[{}] flying_speed:{} fov_modifier:{}", flags.join(","), self.flying_speed, self.fov_modifier)
+        }
+    }
+
+    /// Flags of Synchronize Player Position saying which fields are relative to the current value.
+    pub const RELATIVE_X: u8 = 0x01;
+    pub const RELATIVE_Y: u8 = 0x02;
+    pub const RELATIVE_Z: u8 = 0x04;
+    pub const RELATIVE_YAW: u8 = 0x08;
+    pub const RELATIVE_PITCH: u8 = 0x10;
+
+    /// Moves the player; the client answers with Confirm Teleportation and the server
+    /// ignores its movement until then.
+    pub struct SynchronizePlayerPositionPacket{
+        pub x: f64,
+        pub y: f64,
+        pub z: f64,
+        pub yaw: f32,
+        pub pitch: f32,
+        /// `RELATIVE_*` bits.
+        pub flags: u8,
+        pub teleport_id: i32,
+        pub dismount_vehicle: bool
+    }
+
+    impl SynchronizePlayerPositionPacket{
+        /// Where the player ends up from `(x, y, z, yaw, pitch)`.
+        pub fn apply(&self, (x, y, z, yaw, pitch): (f64, f64, f64, f32, f32)) -> (f64, f64, f64, f32, f32){
+            let relative = |flag: u8| self.flags & flag != 0;
+            (if relative(RELATIVE_X) { x + self.x } else { self.x },
+             if relative(RELATIVE_Y) { y + self.y } else { self.y },
+             if relative(RELATIVE_Z) { z + self.z } else { self.z },
+             if relative(RELATIVE_YAW) { yaw + self.yaw } else { self.yaw },
+             if relative(RELATIVE_PITCH) { pitch + self.pitch } else { self.pitch })
+        }
+    }
+
+    impl Packet for SynchronizePlayerPositionPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            let (x, y, z) = read_xyz(&mut cr)?;
+            Ok(SynchronizePlayerPositionPacket {
+                x, y, z,
+                yaw: read_field(&mut cr, "yaw", |cr| cr.read_f32::<BigEndian>())?,
+                pitch: read_field(&mut cr, "pitch", |cr| cr.read_f32::<BigEndian>())?,
+                flags: read_field(&mut cr, "flags", |cr| cr.read_u8())?,
+                teleport_id: read_field(&mut cr, "teleport_id", |cr| read_var_int(cr))?,
+                dismount_vehicle: read_field(&mut cr, "dismount_vehicle", |cr| cr.read_u8())? != 0
+            })
+        }
+
+        fn make_string(&self) -> String {
+            format!("x:{} y:{} z:{} yaw:{} pitch:{} flags:{:#04x} teleport_id:{} dismount_vehicle:{}",
+                    self.x, self.y, self.z, self.yaw, self.pitch, self.flags, self.teleport_id, self.dismount_vehicle)
+        }
+    }
+
+    /// One player entry of a Player Info packet. Which fields are present depends on the action.
+    #[derive(Debug)]
+    pub struct PlayerInfoEntry{
+        pub uuid: uuid::Uuid,
+        pub name: Option<String>,
+        pub gamemode: Option<i32>,
+        /// Round trip the server measured to this player, in milliseconds.
+        pub latency: Option<i32>,
+        pub display_name: Option<String>
+    }
+
+    pub struct PlayerInfoPacket{
+        /// 0 add player, 1 update gamemode, 2 update latency, 3 update display name, 4 remove player.
+        pub action: i32,
+        pub players: Vec<PlayerInfoEntry>
+    }
+
+    impl PlayerInfoPacket{
+        pub const REMOVE: i32 = 4;
+
+        /// Takes the players with these uuids off the tab list.
+        pub fn remove(uuids: impl IntoIterator<Item = uuid::Uuid>) -> PlayerInfoPacket{
+            let players = uuids.into_iter()
+                .map(|uuid| PlayerInfoEntry { uuid, name: None, gamemode: None, latency: None, display_name: None })
+                .collect();
+            PlayerInfoPacket { action: Self::REMOVE, players }
+        }
+    }
+
+    /// Properties and signature data are not kept, so players added this way have neither.
+    impl PacketWrite for PlayerInfoPacket{
+        fn as_bytes(&self) -> Vec<u8> {
+            let mut out = Vec::new();
+            write_var_int(&mut out, PlayPacketId::PlayerInfo as i32);
+            write_var_int(&mut out, self.action);
+            write_var_int(&mut out, self.players.len() as i32);
+            let write_display_name = |out: &mut Vec<u8>, display_name: &Option<String>| match display_name{
+                Some(display_name) => {
+                    out.push(1);
+                    write_string(out, display_name);
+                }
+                None => out.push(0)
+            };
+            for entry in &self.players{
+                out.extend_from_slice(&entry.uuid.as_u128().to_be_bytes());
+                match self.action{
+                    0 => {
+                        write_string(&mut out, entry.name.as_deref().unwrap_or_default());
+                        write_var_int(&mut out, 0);
+                        write_var_int(&mut out, entry.gamemode.unwrap_or_default());
+                        write_var_int(&mut out, entry.latency.unwrap_or_default());
+                        write_display_name(&mut out, &entry.display_name);
+                        out.push(0);
+                    },
+                    1 => write_var_int(&mut out, entry.gamemode.unwrap_or_default()),
+                    2 => write_var_int(&mut out, entry.latency.unwrap_or_default()),
+                    3 => write_display_name(&mut out, &entry.display_name),
+                    _ => ()
+                }
+            }
+            out
+        }
+    }
+
+    impl Packet for PlayerInfoPacket{
+        fn parse(buff: &[u8]) -> Result<Self, std::io::Error> where Self: Sized {
+            let mut cr = Cursor::new(buff);
+            let action = read_field(&mut cr, "action", |cr| read_var_int(cr))?;
+            let count = read_field(&mut cr, "number_of_players", |cr| read_var_int(cr))?;
+            let mut players = Vec::new();
+            for _ in 0..count{
+                let mut entry = PlayerInfoEntry{
+                    uuid: uuid::Uuid::from_u128(read_field(&mut cr, "uuid", |cr| cr.read_u128::<BigEndian>())?),
+                    name: None,
+                    gamemode: None,
+                    latency: None,
+                    display_name: None
+                };
+                match action{
+                    0 => {
+                        entry.name = Some(read_field(&mut cr, "name", |cr| read_string(cr, MAX_USERNAME_LEN))?);
+                        let properties = read_field(&mut cr, "number_of_properties", |cr| read_var_int(cr))?;
+                        for _ in 0..properties{
+                            read_field(&mut cr, "property_name", |cr| read_string(cr, MAX_PROPERTY_LEN))?;
+                            read_field(&mut cr, "property_value", |cr| read_string(cr, MAX_PROPERTY_LEN))?;
+                            if read_field(&mut cr, "is_signed", |cr| cr.read_u8())? == 0x01{
+                                read_field(&mut cr, "signature", |cr| read_string(cr, MAX_PROPERTY_LEN))?;
+                            }
+                        }
+                        entry.gamemode = Some(read_field(&mut cr, "gamemode", |cr| read_var_int(cr))?);
+                        entry.latency = Some(read_field(&mut cr, "ping", |cr| read_var_int(cr))?);
+                        if read_field(&mut cr, "has_display_name", |cr| cr.read_u8())? == 0x01{
+                            entry.display_name = Some(read_field(&mut cr, "display_name", |cr| read_string(cr, MAX_CHAT_LEN))?);
+                        }
+                        if read_field(&mut cr, "has_sig_data", |cr| cr.read_u8())? == 0x01{
+                            read_field(&mut cr, "timestamp", |cr| cr.read_i64::<BigEndian>())?;
+                            let key_len = read_field(&mut cr, "public_key_length", |cr| read_var_int(cr))?;
+                            read_field(&mut cr, "public_key", |cr| read_bytes(cr, check_length(key_len, MAX_PUBLIC_KEY_LEN)?))?;
+                            let sig_len = read_field(&mut cr, "signature_length", |cr| read_var_int(cr))?;
+                            read_field(&mut cr, "signature", |cr| read_bytes(cr, check_length(sig_len, MAX_SIGNATURE_LEN)?))?;
+                        }
+                    },
+                    1 => entry.gamemode = Some(read_field(&mut cr, "gamemode", |cr| read_var_int(cr))?),
+                    2 => entry.latency = Some(read_field(&mut cr, "ping", |cr| read_var_int(cr))?),
+                    3 => {
+                        let has_display_name = read_field(&mut cr, "has_display_name", |cr| cr.read_u8())? == 0x01;
+                        if has_display_name{
+                            entry.display_name = Some(read_field(&mut cr, "display_name", |cr| read_string(cr, MAX_CHAT_LEN))?);
+                        }
+                    },
+                    _ => ()
+                }
+                players.push(entry);
+            }
+            Ok(PlayerInfoPacket { action, players })
+        }
+
+        fn make_string(&self) -> String {
+            format!("action:{} players:{:?}", self.action, self.players)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::client::LoginStart;
+    use super::Packet;
+    use crate::utils::{write_string, write_var_int, FieldError};
+    use std::io::ErrorKind;
+
+    /// Login Start for Alex with a key and signature of the given lengths, of which only
+    /// `key.len()` and `sig.len()` bytes are present.
+    fn login_start(key_len: i32, key: &[u8], sig_len: Option<(i32, &[u8])>) -> Vec<u8>{
+        let mut out = Vec::new();
+        write_string(&mut out, "Alex");
+        out.push(0x01);
+        out.extend(1_700_000_000_000i64.to_be_bytes());
+        write_var_int(&mut out, key_len);
+        out.extend_from_slice(key);
+        if let Some((sig_len, sig)) = sig_len{
+            write_var_int(&mut out, sig_len);
+            out.extend_from_slice(sig);
+        }
+        out
+    }
+
+    fn failed_field(buff: &[u8]) -> (&'static str, ErrorKind){
+        let e = LoginStart::parse(buff).unwrap_err();
+        (FieldError::of(&e).expect("a field error").field, e.kind())
+    }
+
+    #[test]
+    fn login_start_with_signature_data(){
+        let mut buff = login_start(3, &[1, 2, 3], Some((2, &[4, 5])));
+        buff.push(0x01);
+        buff.extend(7u128.to_be_bytes());
+        let login = LoginStart::parse(&buff).unwrap();
+        assert_eq!(login.player_name, "Alex");
+        assert_eq!(login.uuid, Some(uuid::Uuid::from_u128(7)));
+        assert!(login.make_string().contains("(read 3 key bytes, 2 sig bytes)"));
+    }
+
+    #[test]
+    fn truncated_login_start_is_an_error(){
+        assert_eq!(failed_field(&login_start(512, &[0; 10], None)), ("pub_key", ErrorKind::UnexpectedEof));
+        assert_eq!(failed_field(&login_start(3, &[1, 2, 3], Some((256, &[0; 5])))), ("sig", ErrorKind::UnexpectedEof));
+        assert_eq!(failed_field(&login_start(3, &[1, 2, 3], None)), ("sig_len", ErrorKind::UnexpectedEof));
+        // A huge length is not allocated up front: too long in strict mode, cut short otherwise.
+        assert_eq!(failed_field(&login_start(i32::MAX, &[0; 4], None)).0, "pub_key");
+        assert_eq!(failed_field(&[0xff, 0xff, 0xff, 0xff, 0x0f]), ("name", ErrorKind::InvalidData));
+        assert_eq!(failed_field(&[4, b'A']), ("name", ErrorKind::UnexpectedEof));
+    }
 }
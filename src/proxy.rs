@@ -1,273 +1,1181 @@
-
+use crate::hexdump;
+use crate::access::Access;
+use crate::chat::{Chat, Filtered};
+use crate::commands::Commands;
+use crate::config::{self, Config, Routing};
+use crate::events::{self, PacketEvent, EVENTS};
+use crate::export::Exporter;
+use crate::impair::{Impairment, Link};
+use crate::limits::{ConnectionLimiter, LimitsConfig, PacketRate};
+use crate::logging::decode;
+use crate::metrics::METRICS;
+use crate::movement::Cause;
+use crate::sanity::{Action, Sanity, SanityConfig, Violation};
+use crate::session::{Session, Sessions};
 use crate::utils;
 use std::cell::RefCell;
-use std::io::{Read, Write};
-use std::net::{TcpListener, TcpStream};
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
 use std::thread;
-use utils::{State};
-use crate::packets::{Packet, server};
-use crate::packets::client;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, info_span, trace, warn, Span};
+use uuid::Uuid;
+use utils::{Direction, RawPacket, State};
+use crate::packets::names::packet_name;
+use crate::packets::{Packet, PacketWrite};
+use crate::text::{self, ChatType, Component};
+use crate::packets::server::{self, BlockEntityDataPacket, BlockUpdatePacket, ChunkDataPacket, CommandsPacket, UpdateLightPacket, DisconnectPacket, LoginDisconnectPacket, KeepAlivePacket as ServerKeepAlivePacket, LoginPlayPacket, LoginSuccessPacket, PlayerAbilitiesPacket, PlayerChatPacket, PlayerInfoPacket,
+                             RemoveEntitiesPacket, RespawnPacket, SetCompressionPacket, SetEntityMetadataPacket, SetEntityVelocityPacket,
+                             SetEquipmentPacket, SetHeadRotationPacket, SpawnEntityPacket, SpawnExperienceOrbPacket, SpawnPlayerPacket, StatusResponsePacket,
+                             SynchronizePlayerPositionPacket, SystemChatPacket, TeleportEntityPacket, TitleTextPacket, UnloadChunkPacket, UpdateEntityPositionAndRotationPacket, UpdateEntityPositionPacket,
+                             UpdateEntityRotationPacket, UpdateSectionBlocksPacket};
+use crate::packets::client::{self, ChatCommandPacket, ChatMessagePacket, ConfirmTeleportationPacket, HandshakePacket, KeepAlivePacket, LoginStart, SetPlayerOnGroundPacket,
+                             SetPlayerPosAndRotPacket, SetPlayerPositionPacket, SetPlayerRotationPacket};
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// How often a running session's stats are logged at debug level under the `stats` target.
+const STATS_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often a session is checked against its login timeout.
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What happens to a frame once it has been handled.
+#[derive(Default)]
+enum Verdict {
+    #[default]
+    Forward,
+    /// Not sent on.
+    Drop,
+    /// Sent on with this packet, id first, in place of the original.
+    Replace(Vec<u8>),
+    /// Sent on, followed by these packets, id first.
+    Append(Vec<Vec<u8>>),
+}
 
 trait HandshakeConnection {
-    fn input(&self) -> &TcpStream;
+    /// Reads what `input` has next.
+    fn read(&self, buff: &mut [u8]) -> io::Result<usize>;
     fn output(&self) -> &TcpStream;
-    fn state(&self) -> State;
-    fn set_state(&self, new_state: State);
-    fn log(&self, message: String);
-    
-    fn on_status(&self, buff: Vec<&[u8]>);
-    fn on_login(&self, buff: Vec<&[u8]>);
-    fn on_play(&self, buff: Vec<&[u8]>);
+    /// Compression thresholds of `input` and `output`; they differ once the player has
+    /// moved to a server that compresses differently from the first one.
+    fn compression(&self) -> (Option<usize>, Option<usize>);
+    fn session(&self) -> &Session;
+    fn direction(&self) -> Direction;
+    fn span(&self) -> &Span;
+
+    fn on_handshake(&self, packet: &RawPacket);
+    fn on_status(&self, packet: &RawPacket);
+    fn on_login(&self, packet: &RawPacket);
+    fn on_play(&self, packet: &RawPacket);
+
+    /// What to do with the frame just handled; handlers that stop or change a packet
+    /// leave their verdict here.
+    fn take_verdict(&self) -> Verdict{
+        Verdict::Forward
+    }
+
+    /// Counts one frame read from `input`; false when the sender is flooding.
+    fn admit_packet(&self) -> bool{
+        true
+    }
+
+    /// Held from reading the compression thresholds until a frame is queued on `output`.
+    fn frame_lock(&self) -> Option<MutexGuard<'_, ()>>{
+        None
+    }
+
+    /// Bytes already read from `input` before forwarding started.
+    fn initial(&self) -> Vec<u8>{
+        Vec::new()
+    }
+
+    /// Called once `input` is closed; when another server has taken over, starts reading
+    /// from it and returns the bytes it has already sent.
+    fn switch_input(&self) -> Option<Vec<u8>>{
+        None
+    }
+
+    fn state(&self) -> State{
+        self.session().state()
+    }
+
+    fn set_state(&self, new_state: State){
+        self.session().set_state(new_state);
+    }
 
     fn run(&self) {
+        let _entered = self.span().enter();
         self.handshake();
     }
 
-    fn execute(&self, buff: Vec<&[u8]>){
+    fn execute(&self, packet: &RawPacket){
         let state = self.state();
         match state{
-            State::Status => self.on_status(buff),
-            State::Login => self.on_login(buff),
-            State::Play => self.on_play(buff),
-            State::Unknown =>self.log("Warning!!!! State is Unknonwn. Warning!!!!!".to_string())
+            State::Handshake => self.on_handshake(packet),
+            State::Status => self.on_status(packet),
+            State::Login => self.on_login(packet),
+            State::Play => self.on_play(packet),
+            State::Unknown => warn!("State is Unknown, packet {:#02x} not decoded", packet.id)
+        }
+    }
+
+    /// Decodes and handles one frame and says what to forward. Fails, after disconnecting
+    /// the client, when the client sent a malformed frame and strict validation is on; it
+    /// must not be forwarded then. Malformed frames from the server are only logged.
+    fn on_frame(&self, frame: &[u8]) -> io::Result<Verdict>{
+        let session = self.session();
+        match utils::decode_frame(frame, self.compression().0){
+            Ok(packet) => {
+                let state = self.state();
+                session.record_packet(self.direction(), state, &packet);
+                events::set_inspecting(state != State::Play || session.inspecting());
+                self.execute(&packet);
+                let verdict = self.take_verdict();
+                let error = events::take_error();
+                let fields = events::take_fields();
+                if EVENTS.listening(){
+                    EVENTS.publish(PacketEvent::new(session.id, self.direction(), state, &packet, fields, error.clone()));
+                }
+                match error{
+                    Some(error) if self.rejects_malformed() => Err(self.reject_malformed(error)),
+                    _ => Ok(verdict)
+                }
+            }
+            Err(e) => {
+                METRICS.parse_errors.with_label_values(&["frame"]).inc();
+                warn!("Failed to decode {} byte frame reason: {}", frame.len(), e);
+                if self.rejects_malformed() { Err(self.reject_malformed(e.to_string())) } else { Ok(Verdict::Forward) }
+            }
         }
     }
 
+    /// Strict validation only holds the client to the spec; what the server sends is trusted.
+    fn rejects_malformed(&self) -> bool{
+        utils::strict() && self.direction() == Direction::Serverbound
+    }
+
+    /// Disconnects the client over a malformed packet, returning the error to stop forwarding with.
+    fn reject_malformed(&self, reason: String) -> io::Error{
+        METRICS.limit_disconnects.with_label_values(&["malformed"]).inc();
+        warn!("Disconnecting, malformed packet reason: {}", reason);
+        let _ = self.session().kick("Disconnected by the proxy: malformed packet.");
+        io::Error::new(io::ErrorKind::InvalidData, reason)
+    }
+
+    /// Forwards `input` to `output` one complete frame at a time, decoding every frame
+    /// before it is queued on the session's impaired link. Returns once either side is
+    /// closed, unless another server took over `input`.
     fn handshake(&self) {
         let mut buff = [0; 4096];
-        let mut input = self.input();
-        let mut output = self.output();
+        let mut pending: Vec<u8> = self.initial();
+        let mut link = match self.output().try_clone(){
+            Ok(output) => Link::spawn(output, self.span().clone()),
+            Err(e) => {
+                debug!("Cannot clone output reason: {}", e);
+                return;
+            }
+        };
+        self.session().attach(self.direction(), link.injector());
+        if let Err(e) = self.forward(&mut pending, &mut link){
+            debug!("Stopped forwarding reason: {}", e);
+            return self.discard();
+        }
         loop {
-            {
-                let bytes_read = input.read(&mut buff).unwrap();
-                if bytes_read != 0 {
-                    match utils::tokenize_to_packets(&buff[..bytes_read]){
-                        Ok(tokens) => self.execute(tokens),
-                        Err(_e) => ()
+            let bytes_read = match self.read(&mut buff){
+                Ok(0) => {
+                    debug!("Connection closed");
+                    0
+                }
+                Ok(bytes_read) => bytes_read,
+                Err(e) => {
+                    debug!("Read failed reason: {}", e);
+                    0
+                }
+            };
+            if bytes_read > 0{
+                pending.extend_from_slice(&buff[..bytes_read]);
+            }else{
+                match self.switch_input(){
+                    Some(initial) => pending = initial,
+                    None => return
+                }
+            }
+            if let Err(e) = self.forward(&mut pending, &mut link){
+                debug!("Stopped forwarding reason: {}", e);
+                return self.discard();
+            }
+        }
+    }
+
+    /// Reads and drops `input` until it closes, e.g. while a kick is being delivered.
+    fn discard(&self) {
+        let mut buff = [0; 4096];
+        while let Ok(1..) = self.read(&mut buff){}
+    }
+
+    /// Decodes and queues every complete frame in `pending`, leaving a partial one behind.
+    fn forward(&self, pending: &mut Vec<u8>, link: &mut Link) -> io::Result<()> {
+        let settings = *self.session().impairment().direction(self.direction());
+        if self.session().decoding(){
+            match utils::tokenize_to_packets(pending){
+                Ok((frames, consumed)) => {
+                    for frame in frames{
+                        if !self.admit_packet(){
+                            METRICS.limit_disconnects.with_label_values(&["packet_flood"]).inc();
+                            warn!("Disconnecting, over the packet rate limit");
+                            let _ = self.session().kick("You are sending too many packets.");
+                            return Err(io::Error::other("packet rate limit exceeded"));
+                        }
+                        // Frames before Play are never reordered: swapping Set Compression
+                        // and Login Success, say, would break the login.
+                        let reorder = self.state() == State::Play;
+                        let verdict = self.on_frame(frame)?;
+                        let _frame = self.frame_lock();
+                        // Read after the frame is handled, as handling it may move the player.
+                        let (input_compression, output_compression) = self.compression();
+                        let forwarded = |link: &mut Link| if input_compression == output_compression{
+                            link.send(frame.to_vec(), reorder, &settings)
+                        }else{
+                            let packet = utils::decode_frame(frame, input_compression)?;
+                            let mut body = Vec::with_capacity(packet.uncompressed_len);
+                            utils::write_var_int(&mut body, packet.id);
+                            body.extend_from_slice(&packet.data);
+                            link.send(utils::encode_frame(&body, output_compression), reorder, &settings)
+                        };
+                        match verdict{
+                            Verdict::Forward => forwarded(link)?,
+                            Verdict::Replace(body) => link.send(utils::encode_frame(&body, output_compression), reorder, &settings)?,
+                            Verdict::Append(bodies) => {
+                                forwarded(link)?;
+                                for body in bodies{
+                                    link.send(utils::encode_frame(&body, output_compression), reorder, &settings)?;
+                                }
+                            },
+                            Verdict::Drop => ()
+                        }
                     }
-                    output.write_all(&buff[..bytes_read]).unwrap();
+                    pending.drain(..consumed);
+                }
+                Err(e) if self.rejects_malformed() => return Err(self.reject_malformed(e.to_string())),
+                Err(e) => {
+                    warn!("Failed to split stream into packets reason: {}, forwarding without decoding", e);
+                    self.session().stop_decoding();
                 }
-                
             }
         }
+        if !self.session().decoding() && !pending.is_empty(){
+            link.send(std::mem::take(pending), false, &settings)?;
+        }
+        Ok(())
     }
 }
 
 struct M2P {
     input: TcpStream,
     output: TcpStream,
+    /// Handshake and Login Start, read before the upstream connection was opened.
+    greeting: RefCell<Vec<u8>>,
+    packet_rate: RefCell<PacketRate>,
+    sanity: RefCell<Sanity>,
+    chat: Arc<Chat>,
+    commands: Arc<Commands>,
+    verdict: RefCell<Verdict>,
     login_packet_sent: RefCell<bool>,
-    state: Arc<Mutex<State>> 
+    session: Arc<Session>,
+    span: Span
 }
 
 struct S2P {
-    input: TcpStream,
+    /// Replaced when the player moves to another server.
+    input: RefCell<TcpStream>,
     output: TcpStream,
-    state: Arc<Mutex<State>>
+    /// Threshold of the server `input` reads from.
+    compression: RefCell<Option<usize>>,
+    chat: Arc<Chat>,
+    commands: Arc<Commands>,
+    verdict: RefCell<Verdict>,
+    /// How Player Chat is decorated, by `minecraft:chat_type` id from Login.
+    chat_types: RefCell<HashMap<i32, ChatType>>,
+    /// Uuids of the players on the tab list, taken off it when the player moves servers.
+    players: RefCell<HashSet<Uuid>>,
+    session: Arc<Session>,
+    span: Span
 }
 
 impl HandshakeConnection for M2P {
-    fn input(&self) -> &TcpStream {
-        &self.input
+    fn read(&self, buff: &mut [u8]) -> io::Result<usize> {
+        (&self.input).read(buff)
+    }
+
+    fn compression(&self) -> (Option<usize>, Option<usize>) {
+        (self.session.compression(), self.session.upstream_compression())
+    }
+
+    fn initial(&self) -> Vec<u8> {
+        self.greeting.take()
+    }
+
+    fn frame_lock(&self) -> Option<MutexGuard<'_, ()>> {
+        Some(self.session.serverbound_frame())
+    }
+
+    fn admit_packet(&self) -> bool {
+        self.packet_rate.borrow_mut().admit()
+    }
+
+    fn take_verdict(&self) -> Verdict {
+        self.verdict.take()
     }
 
     fn output(&self) -> &TcpStream {
         &self.output
     }
 
-    fn state(&self) -> State{
-        let g = self.state.lock().unwrap();
-        *g
+    fn session(&self) -> &Session {
+        &self.session
     }
-    
-    fn set_state(&self, new_state: State){
-        let mut g = self.state.lock().unwrap();
-        *g = new_state;
-    }
-    
-    fn log(&self, message: String){
-        println!("[M2P]:{message}");
-    }
-
-    fn on_status(&self, buff: Vec<&[u8]>){
-        for packet in buff{
-            let id = &packet[0];
-            let packet = &packet[1..];
-            match client::StatusPacketId::from_u8(id) {
-                    client::StatusPacketId::Handshake =>{
-                        match client::HandshakePacket::parse(packet){
-                            Ok(parsed_value) => {
-                                self.log(parsed_value.make_string());
-                                self.set_state( State::Login);
-                            },
-                            Err(e) => self.log(format!("Failed to parse HandshakePacket reason: {}", e))
-                        };
+
+    fn direction(&self) -> Direction {
+        Direction::Serverbound
+    }
+
+    fn span(&self) -> &Span {
+        &self.span
+    }
+
+    fn on_handshake(&self, packet: &RawPacket){
+        let id = packet.id;
+        let packet = &packet.data[..];
+        match client::HandshakePacketId::from_id(id) {
+            client::HandshakePacketId::Handshake =>{
+                if let Some(parsed_value) = decode!("Handshake/serverbound/Handshake", HandshakePacket, packet){
+                    self.set_state(parsed_value.next_state);
                 }
-                client::StatusPacketId::Unknown => self.log(format!("Unknown status packet id: {:#02x}", id))
             }
+            client::HandshakePacketId::Unknown => trace!(target: "Handshake/serverbound/Unknown", "Unknown handshake packet id: {:#02x} body: {}", id, hexdump::dump(packet, None))
         }
     }
 
-    fn on_login(&self, buff: Vec<&[u8]>){
-        for packet in buff{
-            let id = &packet[0];
-            let packet = &packet[1..];
-            match client::LoginPacketId::from_u8(id){
-                client::LoginPacketId::Start =>{
-                    if !*self.login_packet_sent.borrow(){
-                        match client::LoginStart::parse(packet){
-                            Ok(parsed_value) => {
-                                    *self.login_packet_sent.borrow_mut() = true;
-                                    self.log(parsed_value.make_string());
-                            },
-                            Err(e) => {self.log(format!("{:?}", packet));self.log(format!("Failed to parse LoginStart packet reason: {}", e))}
-                        }
+    fn on_status(&self, packet: &RawPacket){
+        match client::StatusPacketId::from_id(packet.id) {
+            client::StatusPacketId::StatusRequest => debug!(target: "Status/serverbound/StatusRequest", "Status request"),
+            client::StatusPacketId::PingRequest => debug!(target: "Status/serverbound/PingRequest", "Ping request"),
+            client::StatusPacketId::Unknown => trace!(target: "Status/serverbound/Unknown", "Unknown status packet id: {:#02x} body: {}", packet.id, hexdump::dump(&packet.data, None))
+        }
+    }
+
+    fn on_login(&self, packet: &RawPacket){
+        let id = packet.id;
+        let packet = &packet.data[..];
+        match client::LoginPacketId::from_id(id){
+            client::LoginPacketId::Start =>{
+                if !*self.login_packet_sent.borrow(){
+                    if let Some(parsed_value) = decode!("Login/serverbound/LoginStart", LoginStart, packet){
+                        *self.login_packet_sent.borrow_mut() = true;
+                        self.session.set_login_start(parsed_value.player_name, parsed_value.uuid);
                     }
                 }
-                client::LoginPacketId::Unknonwn => self.log(format!("Unknown login packet id:{:#02x}", id))
             }
+            client::LoginPacketId::Unknonwn => trace!(target: "Login/serverbound/Unknown", "Unknown login packet id: {:#02x} ({}) body: {}", id, packet_name(State::Login, Direction::Serverbound, id), hexdump::dump(packet, None))
         }
     }
 
-    fn on_play(&self, buff: Vec<&[u8]>){
-        for packet in buff{ 
-            let id = &packet[1];
-            let packet = &packet[2..];
-            match client::PlayPacketId::from_u8(id){
-                client::PlayPacketId::SetPlayerPosition =>{
-                    match client::SetPlayerPositionPacket::parse(packet){
-                        Ok(parsed_value) =>{
-                            self.log(parsed_value.make_string());
-                        },
-                        Err(e) => self.log(format!("Failed to Parse SetPlayerPosition reason: {}", e)) 
-                    }
-                },
-                client::PlayPacketId::SetPlayerRotation =>{
-                    match client::SetPlayerRotationPacket::parse(packet){
-                        Ok(parsed_value) =>{
-                            self.log(parsed_value.make_string());
-                        },
-                        Err(e) => self.log(format!("Failed to parse SetPlayerRotationPacket reason: {}", e))
-                    }
-                },
-                client::PlayPacketId::KeepAlive => {
-                    match client::KeepAlivePacket::parse(packet){
-                        Ok(parsed_value) => {
-                            self.log(parsed_value.make_string())
-                        }
-                        Err(e) => self.log(format!("Failed to parse KeepAlive reason: {}" ,e))
+    fn on_play(&self, packet: &RawPacket){
+        let id = packet.id;
+        let packet = &packet.data[..];
+        match client::PlayPacketId::from_id(id){
+            client::PlayPacketId::ConfirmTeleportation =>{
+                if let Some(parsed_value) = decode!("Play/serverbound/ConfirmTeleportation", ConfirmTeleportationPacket, packet){
+                    if let Some(correction) = self.session.movement().confirm(parsed_value.teleport_id){
+                        debug!(target: "Play/serverbound/ConfirmTeleportation", "Teleport {} confirmed, {} moves ignored before",
+                               correction.teleport_id, correction.moves_ignored);
                     }
-                },
-                client::PlayPacketId::SetPLayerPosAndRot => match client::SetPlayerPosAndRotPacket::parse(packet) {
-                    Ok(parsed_value) => {
-                        self.log(parsed_value.make_string());
+                }
+            },
+            client::PlayPacketId::ChatCommand =>{
+                if let Some(parsed_value) = decode!("Play/serverbound/ChatCommand", ChatCommandPacket, packet){
+                    self.chat_command(parsed_value);
+                }
+            },
+            client::PlayPacketId::ChatMessage =>{
+                if let Some(parsed_value) = decode!("Play/serverbound/ChatMessage", ChatMessagePacket, packet){
+                    self.chat_message(parsed_value);
+                }
+            },
+            client::PlayPacketId::SetPlayerPosition =>{
+                if let Some(parsed_value) = decode!("Play/serverbound/SetPlayerPosition", SetPlayerPositionPacket, packet){
+                    let position = (parsed_value.pos_x, parsed_value.pos_y, parsed_value.pos_z);
+                    self.client_moved(Some(position), None, parsed_value.on_ground);
+                }
+            },
+            client::PlayPacketId::SetPlayerRotation =>{
+                if let Some(parsed_value) = decode!("Play/serverbound/SetPlayerRotation", SetPlayerRotationPacket, packet){
+                    self.client_moved(None, Some((parsed_value.yaw, parsed_value.pitch)), parsed_value.on_ground);
+                }
+            },
+            client::PlayPacketId::SetPlayerOnGround =>{
+                if let Some(parsed_value) = decode!("Play/serverbound/SetPlayerOnGround", SetPlayerOnGroundPacket, packet){
+                    self.client_moved(None, None, parsed_value.on_ground);
+                }
+            },
+            client::PlayPacketId::KeepAlive => {
+                if let Some(parsed_value) = decode!("Play/serverbound/KeepAlive", KeepAlivePacket, packet){
+                    let stale = self.session.switch().stale_keep_alives.contains(&parsed_value.id);
+                    if stale{
+                        debug!(target: "Play/serverbound/KeepAlive", "Dropped the answer to Keep Alive {} of the previous server", parsed_value.id);
+                        *self.verdict.borrow_mut() = Verdict::Drop;
+                    }else if let Some((rtt, average)) = self.session.keep_alive_answered(parsed_value.id){
+                        debug!(target: "Play/serverbound/KeepAlive", "Client rtt {:.1}ms (avg {:.1}ms)",
+                               rtt.as_secs_f64() * 1000.0, average.as_secs_f64() * 1000.0);
                     }
-                    Err(e) => self.log(format!("Failed to parse SetPlayerPosAndRot reason: {}", e))
                 }
-                client::PlayPacketId::Unknonwn => self.log(format!("Unknown play packet id: {:#02x}", id))
+            },
+            client::PlayPacketId::SetPLayerPosAndRot => {
+                if let Some(parsed_value) = decode!("Play/serverbound/SetPlayerPosAndRot", SetPlayerPosAndRotPacket, packet){
+                    let position = (parsed_value.pos_x, parsed_value.pos_y, parsed_value.pos_z);
+                    self.client_moved(Some(position), Some((parsed_value.yaw, parsed_value.pitch)), parsed_value.on_ground);
+                }
+            }
+            client::PlayPacketId::Unknonwn => trace!(target: "Play/serverbound/Unknown", "Unknown play packet id: {:#02x} ({}) body: {}", id, packet_name(State::Play, Direction::Serverbound, id), hexdump::dump(packet, None))
+        }
+    }
+}
+
+impl M2P {
+    /// Logs a chat message and runs the filter over it. Rewritten messages lose their
+    /// signature, as it no longer matches.
+    fn chat_message(&self, mut packet: ChatMessagePacket){
+        match self.chat.filter(&packet.message, false){
+            Filtered::Pass => self.chat.log(&self.session, "chat", &packet.message),
+            Filtered::Block { reason } => {
+                self.chat.log(&self.session, "chat blocked", &packet.message);
+                self.filtered("block", &reason, Verdict::Drop);
+            },
+            Filtered::Rewrite { text, reason } => {
+                self.chat.log(&self.session, "chat rewritten", &format!("{} -> {}", packet.message, text));
+                packet.message = text;
+                packet.signature.clear();
+                self.filtered("rewrite", &reason, Verdict::Replace(packet.as_bytes()));
             }
         }
     }
+
+    /// Like `chat_message`, for commands; rewritten ones lose their argument signatures.
+    /// Proxy commands are answered here and never reach the server.
+    fn chat_command(&self, mut packet: ChatCommandPacket){
+        if self.commands.handles(&packet.command){
+            self.chat.log(&self.session, "proxy command", &format!("/{}", packet.command));
+            *self.verdict.borrow_mut() = Verdict::Drop;
+            return self.commands.execute(&self.session, &packet.command);
+        }
+        match self.chat.filter(&packet.command, true){
+            Filtered::Pass => self.chat.log(&self.session, "command", &format!("/{}", packet.command)),
+            Filtered::Block { reason } => {
+                self.chat.log(&self.session, "command blocked", &format!("/{}", packet.command));
+                self.filtered("block", &reason, Verdict::Drop);
+            },
+            Filtered::Rewrite { text, reason } => {
+                self.chat.log(&self.session, "command rewritten", &format!("/{} -> /{}", packet.command, text));
+                packet.command = text;
+                packet.argument_signatures.clear();
+                self.filtered("rewrite", &reason, Verdict::Replace(packet.as_bytes()));
+            }
+        }
+    }
+
+    /// Leaves `verdict` for the filtered packet and tells the player why.
+    fn filtered(&self, action: &str, reason: &str, verdict: Verdict){
+        METRICS.chat_filtered.with_label_values(&[action]).inc();
+        info!(target: "chat", "Chat filter action {}, telling the player: {}", action, reason);
+        *self.verdict.borrow_mut() = verdict;
+        if let Err(e) = self.session.send_chat(reason){
+            debug!(target: "chat", "Cannot tell the player reason: {}", e);
+        }
+    }
+
+    /// Runs the movement checks on a move, then applies it to the session's movement.
+    fn client_moved(&self, position: Option<(f64, f64, f64)>, rotation: Option<(f32, f32)>, on_ground: bool){
+        let violations = {
+            let world = self.session.world();
+            let mut movement = self.session.movement();
+            let violations = self.sanity.borrow_mut().check(&movement, &world, position, rotation, on_ground);
+            movement.client_moved(position, rotation, on_ground);
+            violations
+        };
+        for violation in violations{
+            self.flag(violation);
+        }
+    }
+
+    fn flag(&self, violation: Violation){
+        METRICS.movement_flags.with_label_values(&[violation.check.name()]).inc();
+        match violation.action{
+            Action::Kick => {
+                warn!(target: "sanity", "Disconnecting, {}", violation);
+                METRICS.limit_disconnects.with_label_values(&["movement"]).inc();
+                let _ = self.session.kick(&format!("Disconnected by the proxy: invalid movement ({}).", violation.check.name()));
+            },
+            Action::Log if violation.log => warn!(target: "sanity", "Movement {}", violation),
+            Action::Log | Action::Off => ()
+        }
+    }
+}
+
+impl S2P {
+    /// Puts the client in the new server's world once the player has moved there. The
+    /// server's Login already makes the client start over with a new world and its new
+    /// entity id; a respawn into another dimension and back makes it drop every chunk
+    /// and entity of the old server too, like a dimension change does. The old server's
+    /// players are taken off the tab list.
+    fn switched(&self, target: &str, login: &LoginPlayPacket){
+        let mut elsewhere = RespawnPacket::from_login(login);
+        elsewhere.dimension_name = if login.dimension_name == "minecraft:overworld" { "minecraft:the_nether" } else { "minecraft:overworld" }.to_string();
+        let mut packets = vec![elsewhere.as_bytes(), RespawnPacket::from_login(login).as_bytes()];
+        let players = std::mem::take(&mut *self.players.borrow_mut());
+        if !players.is_empty(){
+            packets.push(PlayerInfoPacket::remove(players).as_bytes());
+        }
+        *self.verdict.borrow_mut() = Verdict::Append(packets);
+        info!(target: "commands", "Moved the player to {} ({})", target, self.session.upstream());
+        METRICS.server_switches.with_label_values(&["switched"]).inc();
+    }
+
+    /// Player Chat as the client shows it, laid out by its chat type.
+    fn decorate(&self, packet: &PlayerChatPacket) -> Component {
+        let parse = |json: &str| Component::from_json(json).unwrap_or_else(|_| Component::text(json));
+        let content = match (&packet.unsigned_content, &packet.formatted) {
+            (Some(json), _) | (None, Some(json)) => parse(json),
+            (None, None) => Component::text(packet.message.as_str()),
+        };
+        let chat_type = self.chat_types.borrow().get(&packet.chat_type).cloned().unwrap_or_default();
+        chat_type.decorate(parse(&packet.network_name), packet.target_name.as_deref().map(parse), content)
+    }
 }
 
 impl HandshakeConnection for S2P {
-    fn input(&self) -> &TcpStream {
-        &self.input
+    fn read(&self, buff: &mut [u8]) -> io::Result<usize> {
+        (&*self.input.borrow()).read(buff)
+    }
+
+    fn compression(&self) -> (Option<usize>, Option<usize>) {
+        (*self.compression.borrow(), self.session.compression())
+    }
+
+    fn switch_input(&self) -> Option<Vec<u8>> {
+        let backend = self.session.switch().ready.take()?;
+        match backend.stream.try_clone(){
+            Ok(server) => self.session.set_server(server),
+            Err(e) => {
+                warn!("Cannot switch to {} reason: {}", backend.address, e);
+                return None;
+            }
+        }
+        debug!("Reading from {} now", backend.address);
+        *self.compression.borrow_mut() = backend.compression;
+        *self.input.borrow_mut() = backend.stream;
+        self.session.set_upstream(&backend.address);
+        let (uuid, name) = backend.profile;
+        self.session.set_profile(uuid, name);
+        Some(backend.pending)
+    }
+
+    fn take_verdict(&self) -> Verdict {
+        self.verdict.take()
     }
 
     fn output(&self) -> &TcpStream {
         &self.output
     }
-    
-    fn state(&self) -> State{
-        let g = self.state.lock().unwrap();
-        *g
+
+    fn session(&self) -> &Session {
+        &self.session
     }
 
-    fn set_state(&self, new_state: State){
-        let mut g = self.state.lock().unwrap();
-        *g = new_state;
+    fn direction(&self) -> Direction {
+        Direction::Clientbound
     }
-    
-    fn log(&self, message: String){
-        println!("[S2P]:{message}");
+
+    fn span(&self) -> &Span {
+        &self.span
     }
 
-    fn on_status(&self, _buff: Vec<&[u8]>){
-        self.log("Server on status".to_string());
+    fn on_handshake(&self, packet: &RawPacket){
+        warn!(target: "Handshake/clientbound/Unknown", "Server sent packet {:#02x} before the handshake finished", packet.id);
     }
 
-    fn on_login(&self, buff: Vec<&[u8]>){
-        for packet in buff{
-            let id = &packet[0];
-            let packet = &packet[1..];
-            match server::LoginPacketId::from_u8(*id){
-                server::LoginPacketId::LoginSuccess => {
-                    self.log("___________________Login Success___________________".to_string());
-                    self.set_state(State::Play)
-                },
-                server::LoginPacketId::Unknonwn => ()//self.log(format!("Unkown packet id: {}", id))
+    fn on_status(&self, packet: &RawPacket){
+        match server::StatusPacketId::from_id(packet.id){
+            server::StatusPacketId::StatusResponse => {
+                if let Some(parsed_value) = decode!("Status/clientbound/StatusResponse", StatusResponsePacket, &packet.data){
+                    let motd = serde_json::from_str::<serde_json::Value>(&parsed_value.json).ok()
+                        .and_then(|status| status.get("description").map(|description| Component::from_value(description).map(|motd| motd.to_plain())));
+                    if let Some(Ok(motd)) = motd{
+                        debug!(target: "Status/clientbound/StatusResponse", "MOTD: {}", motd);
+                    }
+                }
+            },
+            _ => debug!(target: "Status/clientbound", "{}", packet_name(State::Status, Direction::Clientbound, packet.id))
+        }
+    }
+
+    fn on_login(&self, packet: &RawPacket){
+        let id = packet.id;
+        let packet = &packet.data[..];
+        match server::LoginPacketId::from_id(id){
+            server::LoginPacketId::Disconnect => match decode!("Login/clientbound/Disconnect", LoginDisconnectPacket, packet){
+                Some(parsed_value) => info!(target: "Login/clientbound/Disconnect", "Server refused login: {}", text::plain(&parsed_value.reason)),
+                None => info!(target: "Login/clientbound/Disconnect", "Server refused login")
+            },
+            server::LoginPacketId::EncryptionRequest => {
+                warn!(target: "Login/clientbound/EncryptionRequest", "Server requested encryption, forwarding the rest of the session without decoding");
+                self.session.stop_decoding();
+            },
+            server::LoginPacketId::LoginSuccess => {
+                info!(target: "Login/clientbound/LoginSuccess", "Login success, switching to Play");
+                if let Some(parsed_value) = decode!("Login/clientbound/LoginSuccess", LoginSuccessPacket, packet){
+                    self.session.set_profile(parsed_value.uuid, parsed_value.username);
+                }
+                self.set_state(State::Play)
+            },
+            server::LoginPacketId::SetCompression => {
+                if let Some(parsed_value) = decode!("Login/clientbound/SetCompression", SetCompressionPacket, packet){
+                    let threshold = usize::try_from(parsed_value.threshold).ok();
+                    *self.compression.borrow_mut() = threshold;
+                    self.session.set_compression(threshold);
+                }
+            },
+            server::LoginPacketId::Unknonwn => trace!(target: "Login/clientbound/Unknown", "Unknown login packet id: {:#02x} body: {}", id, hexdump::dump(packet, None))
+        }
+    }
+
+    fn on_play(&self, packet: &RawPacket){
+        let data = &packet.data[..];
+        match server::PlayPacketId::from_id(packet.id){
+            server::PlayPacketId::KeepAlive => {
+                if let Some(parsed_value) = decode!("Play/clientbound/KeepAlive", ServerKeepAlivePacket, data){
+                    self.session.keep_alive_sent(parsed_value.id);
+                }
+            },
+            server::PlayPacketId::Commands => {
+                if let Some(mut parsed_value) = decode!("Play/clientbound/Commands", CommandsPacket, data){
+                    self.commands.extend_tree(&mut parsed_value);
+                    *self.verdict.borrow_mut() = Verdict::Replace(parsed_value.as_bytes());
+                }
+            },
+            server::PlayPacketId::Disconnect => match decode!("Play/clientbound/Disconnect", DisconnectPacket, data){
+                Some(parsed_value) => info!(target: "Play/clientbound/Disconnect", "Server disconnected the player: {}", text::plain(&parsed_value.reason)),
+                None => info!(target: "Play/clientbound/Disconnect", "Server disconnected the player")
+            },
+            server::PlayPacketId::ChunkData => {
+                if let Some(parsed_value) = decode!("Play/clientbound/ChunkData", ChunkDataPacket, data){
+                    trace!(target: "Play/clientbound/ChunkData", "{}", parsed_value.chunk.details());
+                    self.session.world().load(parsed_value.chunk);
+                }
+            },
+            server::PlayPacketId::UnloadChunk => {
+                if let Some(parsed_value) = decode!("Play/clientbound/UnloadChunk", UnloadChunkPacket, data){
+                    self.session.world().unload(parsed_value.x, parsed_value.z);
+                }
+            },
+            server::PlayPacketId::BlockUpdate => {
+                if let Some(parsed_value) = decode!("Play/clientbound/BlockUpdate", BlockUpdatePacket, data){
+                    if !self.session.world().set_block(parsed_value.position, parsed_value.block_id){
+                        debug!(target: "Play/clientbound/BlockUpdate", "Block update at {} outside the loaded chunks", parsed_value.position);
+                    }
+                }
+            },
+            server::PlayPacketId::UpdateSectionBlocks => {
+                if let Some(parsed_value) = decode!("Play/clientbound/UpdateSectionBlocks", UpdateSectionBlocksPacket, data){
+                    if !self.session.world().set_section_blocks(&parsed_value){
+                        debug!(target: "Play/clientbound/UpdateSectionBlocks", "Section update at {},{},{} outside the loaded chunks",
+                               parsed_value.section_x, parsed_value.section_y, parsed_value.section_z);
+                    }
+                }
+            },
+            server::PlayPacketId::BlockEntityData => {
+                if let Some(parsed_value) = decode!("Play/clientbound/BlockEntityData", BlockEntityDataPacket, data){
+                    if !self.session.world().set_block_entity(parsed_value.position, parsed_value.kind, parsed_value.data){
+                        debug!(target: "Play/clientbound/BlockEntityData", "Block entity at {} outside the loaded chunks", parsed_value.position);
+                    }
+                }
+            },
+            server::PlayPacketId::UpdateLight => {
+                decode!("Play/clientbound/UpdateLight", UpdateLightPacket, data);
+            },
+            server::PlayPacketId::Login => {
+                if let Some(parsed_value) = decode!("Play/clientbound/Login", LoginPlayPacket, data){
+                    trace!(target: "Play/clientbound/Login", "registry_codec:{}", parsed_value.registry_codec);
+                    self.session.world().login(&parsed_value);
+                    self.session.entities().login(parsed_value.entity_id);
+                    self.session.movement().login();
+                    let chat_types = parsed_value.registry_entries("minecraft:chat_type").into_iter()
+                        .filter_map(|(name, element)| Some((name, ChatType::from_element(element)?)))
+                        .collect::<HashMap<_, _>>();
+                    *self.chat_types.borrow_mut() = parsed_value.registry_ids("minecraft:chat_type").into_iter()
+                        .filter_map(|(name, id)| Some((id, chat_types.get(name)?.clone())))
+                        .collect();
+                    let target = self.session.switch().target.take();
+                    if let Some(target) = target{
+                        self.switched(&target, &parsed_value);
+                    }
+                }
+            },
+            server::PlayPacketId::PlayerAbilities => {
+                if let Some(parsed_value) = decode!("Play/clientbound/PlayerAbilities", PlayerAbilitiesPacket, data){
+                    self.session.movement().set_may_fly(parsed_value.allow_flying());
+                }
+            },
+            server::PlayPacketId::SynchronizePlayerPosition => {
+                if let Some(parsed_value) = decode!("Play/clientbound/SynchronizePlayerPosition", SynchronizePlayerPositionPacket, data){
+                    let mut movement = self.session.movement();
+                    let correction = movement.synchronize(&parsed_value);
+                    let (x, y, z) = correction.to;
+                    match correction.cause{
+                        Cause::Reverted => info!(target: "Play/clientbound/SynchronizePlayerPosition",
+                                                 "Rubber-banded {:.2} blocks back to {:.2},{:.2},{:.2}, undoing {} moves",
+                                                 correction.distance, x, y, z, correction.moves_undone),
+                        cause => debug!(target: "Play/clientbound/SynchronizePlayerPosition", "Moved {:.2} blocks to {:.2},{:.2},{:.2} ({:?})",
+                                        correction.distance, x, y, z, cause)
+                    }
+                }
+            },
+            server::PlayPacketId::Respawn => {
+                if let Some(parsed_value) = decode!("Play/clientbound/Respawn", RespawnPacket, data){
+                    let mut world = self.session.world();
+                    if world.dimension != parsed_value.dimension_name{
+                        self.session.entities().clear();
+                    }
+                    world.respawn(&parsed_value);
+                    self.session.movement().respawn();
+                }
+            },
+            server::PlayPacketId::SpawnEntity => {
+                if let Some(parsed_value) = decode!("Play/clientbound/SpawnEntity", SpawnEntityPacket, data){
+                    self.session.entities().spawn_entity(&parsed_value);
+                }
+            },
+            server::PlayPacketId::SpawnExperienceOrb => {
+                if let Some(parsed_value) = decode!("Play/clientbound/SpawnExperienceOrb", SpawnExperienceOrbPacket, data){
+                    self.session.entities().spawn_experience_orb(&parsed_value);
+                }
+            },
+            server::PlayPacketId::SpawnPlayer => {
+                if let Some(parsed_value) = decode!("Play/clientbound/SpawnPlayer", SpawnPlayerPacket, data){
+                    self.session.entities().spawn_player(&parsed_value);
+                }
+            },
+            server::PlayPacketId::RemoveEntities => {
+                if let Some(parsed_value) = decode!("Play/clientbound/RemoveEntities", RemoveEntitiesPacket, data){
+                    self.session.entities().remove(&parsed_value.entity_ids);
+                }
+            },
+            server::PlayPacketId::UpdateEntityPosition => {
+                if let Some(parsed_value) = decode!("Play/clientbound/UpdateEntityPosition", UpdateEntityPositionPacket, data){
+                    if !self.session.entities().move_entity(&parsed_value.0){
+                        debug!(target: "Play/clientbound/UpdateEntityPosition", "Update for unknown entity {}", parsed_value.0.entity_id);
+                    }
+                }
+            },
+            server::PlayPacketId::UpdateEntityPositionAndRotation => {
+                if let Some(parsed_value) = decode!("Play/clientbound/UpdateEntityPositionAndRotation", UpdateEntityPositionAndRotationPacket, data){
+                    if !self.session.entities().move_entity(&parsed_value.0){
+                        debug!(target: "Play/clientbound/UpdateEntityPositionAndRotation", "Update for unknown entity {}", parsed_value.0.entity_id);
+                    }
+                }
+            },
+            server::PlayPacketId::UpdateEntityRotation => {
+                if let Some(parsed_value) = decode!("Play/clientbound/UpdateEntityRotation", UpdateEntityRotationPacket, data){
+                    if !self.session.entities().move_entity(&parsed_value.0){
+                        debug!(target: "Play/clientbound/UpdateEntityRotation", "Update for unknown entity {}", parsed_value.0.entity_id);
+                    }
+                }
+            },
+            server::PlayPacketId::SetHeadRotation => {
+                if let Some(parsed_value) = decode!("Play/clientbound/SetHeadRotation", SetHeadRotationPacket, data){
+                    if !self.session.entities().set_head_yaw(parsed_value.entity_id, parsed_value.head_yaw){
+                        debug!(target: "Play/clientbound/SetHeadRotation", "Update for unknown entity {}", parsed_value.entity_id);
+                    }
+                }
+            },
+            server::PlayPacketId::TeleportEntity => {
+                if let Some(parsed_value) = decode!("Play/clientbound/TeleportEntity", TeleportEntityPacket, data){
+                    if !self.session.entities().teleport(&parsed_value){
+                        debug!(target: "Play/clientbound/TeleportEntity", "Update for unknown entity {}", parsed_value.entity_id);
+                    }
+                }
+            },
+            server::PlayPacketId::SetEntityVelocity => {
+                if let Some(parsed_value) = decode!("Play/clientbound/SetEntityVelocity", SetEntityVelocityPacket, data){
+                    if !self.session.entities().set_velocity(parsed_value.entity_id, parsed_value.velocity){
+                        debug!(target: "Play/clientbound/SetEntityVelocity", "Update for unknown entity {}", parsed_value.entity_id);
+                    }
+                }
+            },
+            server::PlayPacketId::SetEntityMetadata => {
+                if let Some(parsed_value) = decode!("Play/clientbound/SetEntityMetadata", SetEntityMetadataPacket, data){
+                    let entity_id = parsed_value.entity_id;
+                    if !self.session.entities().set_metadata(parsed_value){
+                        debug!(target: "Play/clientbound/SetEntityMetadata", "Update for unknown entity {}", entity_id);
+                    }
+                }
+            },
+            server::PlayPacketId::SetEquipment => {
+                if let Some(parsed_value) = decode!("Play/clientbound/SetEquipment", SetEquipmentPacket, data){
+                    let entity_id = parsed_value.entity_id;
+                    if !self.session.entities().set_equipment(parsed_value){
+                        debug!(target: "Play/clientbound/SetEquipment", "Update for unknown entity {}", entity_id);
+                    }
+                }
+            },
+            server::PlayPacketId::SystemChat => {
+                if let Some(parsed_value) = decode!("Play/clientbound/SystemChat", SystemChatPacket, data){
+                    let content = text::plain(&parsed_value.content);
+                    if parsed_value.overlay{
+                        debug!(target: "Play/clientbound/SystemChat", "Action bar: {}", content);
+                        return;
+                    }
+                    info!(target: "chat", "{}", content);
+                    if self.chat.log_received(){
+                        self.chat.log(&self.session, "received system", &content);
+                    }
+                }
+            },
+            server::PlayPacketId::PlayerChat => {
+                if let Some(parsed_value) = decode!("Play/clientbound/PlayerChat", PlayerChatPacket, data){
+                    let shown = self.decorate(&parsed_value).to_plain();
+                    info!(target: "chat", "{}", shown);
+                    if self.chat.log_received(){
+                        self.chat.log(&self.session, "received chat", &format!("from {}: {}", parsed_value.sender, shown));
+                    }
+                }
+            },
+            server::PlayPacketId::SetTitleText => {
+                if let Some(parsed_value) = decode!("Play/clientbound/SetTitleText", TitleTextPacket, data){
+                    debug!(target: "Play/clientbound/SetTitleText", "Title: {}", text::plain(&parsed_value.text));
+                }
+            },
+            server::PlayPacketId::SetSubtitleText => {
+                if let Some(parsed_value) = decode!("Play/clientbound/SetSubtitleText", TitleTextPacket, data){
+                    debug!(target: "Play/clientbound/SetSubtitleText", "Subtitle: {}", text::plain(&parsed_value.text));
+                }
+            },
+            server::PlayPacketId::SetActionBarText => {
+                if let Some(parsed_value) = decode!("Play/clientbound/SetActionBarText", TitleTextPacket, data){
+                    debug!(target: "Play/clientbound/SetActionBarText", "Action bar: {}", text::plain(&parsed_value.text));
+                }
+            },
+            server::PlayPacketId::PlayerInfo => {
+                if let Some(parsed_value) = decode!("Play/clientbound/PlayerInfo", PlayerInfoPacket, data){
+                    let mut players = self.players.borrow_mut();
+                    match parsed_value.action{
+                        0 => players.extend(parsed_value.players.iter().map(|entry| entry.uuid)),
+                        PlayerInfoPacket::REMOVE => for entry in &parsed_value.players{
+                            players.remove(&entry.uuid);
+                        },
+                        _ => ()
+                    }
+                    drop(players);
+                    let Some((uuid, _)) = self.session.profile() else { return };
+                    let reported = parsed_value.players.iter()
+                        .find(|entry| entry.uuid == uuid)
+                        .and_then(|entry| entry.latency);
+                    if let Some(latency) = reported.and_then(|ms| u64::try_from(ms).ok()).map(Duration::from_millis){
+                        match self.session.server_reported_latency(latency){
+                            Some(upstream) => debug!(target: "Play/clientbound/PlayerInfo", "Server reports {}ms for this player, upstream ~{:.1}ms",
+                                                     latency.as_millis(), upstream.as_secs_f64() * 1000.0),
+                            None => debug!(target: "Play/clientbound/PlayerInfo", "Server reports {}ms for this player", latency.as_millis())
+                        }
+                    }
+                }
+            },
+            server::PlayPacketId::Unknonwn => trace!(target: "Play/clientbound/Unknown", "Unknown play packet id: {:#02x} ({})", packet.id, packet_name(State::Play, Direction::Clientbound, packet.id))
+        }
+    }
+}
+
+/// The start of a connection, read before anything is sent upstream.
+struct Greeting {
+    bytes: Vec<u8>,
+    next_state: State,
+    /// Login Start for logins, `None` if it did not parse.
+    login: Option<LoginStart>
+}
+
+/// Reads the client's Handshake and, for logins, its Login Start, so the player can be
+/// checked before anything reaches the server. The bytes are forwarded as usual afterwards.
+/// Fails with `TimedOut` if that takes longer than `timeout`.
+fn read_greeting(mut mc: &TcpStream, timeout: Option<Duration>) -> io::Result<Greeting> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut bytes = Vec::new();
+    let mut buff = [0; 4096];
+    loop {
+        let found = {
+            let (frames, _) = utils::tokenize_to_packets(&bytes)?;
+            let mut packets = frames.into_iter().map(|frame| utils::decode_frame(frame, None));
+            match packets.next().transpose()?{
+                Some(handshake) => {
+                    let next_state = HandshakePacket::parse(&handshake.data)?.next_state;
+                    match packets.next().transpose()?{
+                        _ if next_state != State::Login => Some((next_state, None)),
+                        // A Login Start the proxy cannot read is still forwarded, unless strict.
+                        Some(login) => match LoginStart::parse(&login.data){
+                            Ok(login) => Some((next_state, Some(login))),
+                            Err(e) if utils::strict() => return Err(e),
+                            Err(_) => Some((next_state, None))
+                        },
+                        None => None
+                    }
+                }
+                None => None
+            }
+        };
+        if let Some((next_state, login)) = found{
+            mc.set_read_timeout(None)?;
+            return Ok(Greeting { bytes, next_state, login });
+        }
+        if let Some(deadline) = deadline{
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero(){
+                return Err(io::Error::new(ErrorKind::TimedOut, "handshake timed out"));
             }
+            mc.set_read_timeout(Some(left))?;
+        }
+        let bytes_read = match mc.read(&mut buff){
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Err(io::Error::new(ErrorKind::TimedOut, "handshake timed out")),
+            result => result?
+        };
+        if bytes_read == 0{
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "closed before logging in"));
         }
+        bytes.extend_from_slice(&buff[..bytes_read]);
     }
+}
+
+/// What every new session is held to.
+struct Guards {
+    access: Arc<Access>,
+    limits: LimitsConfig,
+    sanity: SanityConfig,
+    chat: Arc<Chat>,
+    commands: Arc<Commands>
+}
 
-    fn on_play(&self, buff: Vec<&[u8]>){
-        for packet in buff{
-            let id = &packet[0];
-            let packet = &packet[1..];
-            if *id == 0x1C{
-                self.log("ChunkData".to_string());
+/// Runs one proxied connection until either side closes, then logs its stats. Returns
+/// the session if it got as far as being forwarded.
+fn serve(mc: TcpStream, mc_addr: SocketAddr, upstream: &str, sessions: &Sessions, impairment: Impairment, guards: &Guards) -> Option<Arc<Session>> {
+    let limits = &guards.limits;
+    let session = Arc::new(Session::new(NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed), mc_addr, impairment));
+    let _entered = session.span.clone().entered();
+    info!("Accepted connection");
+    METRICS.connections_accepted.inc();
+
+    let greeting = match read_greeting(&mc, limits.handshake_timeout()){
+        Ok(greeting) => greeting,
+        Err(e) if e.kind() == ErrorKind::TimedOut => {
+            info!("Disconnecting, no handshake within {}s", limits.handshake_timeout_secs);
+            METRICS.connections_rejected.with_label_values(&["handshake_timeout"]).inc();
+            return None;
+        }
+        Err(e) => {
+            debug!("No valid handshake reason: {}", e);
+            METRICS.connections_rejected.with_label_values(&["bad_handshake"]).inc();
+            return None;
+        }
+    };
+    if greeting.next_state == State::Login{
+        let name = greeting.login.as_ref().map(|login| login.player_name.as_str());
+        if let Err(rejection) = guards.access.check(name, mc_addr.ip()){
+            info!("Refused login of {} from {}: {}", name.unwrap_or("?"), mc_addr.ip(), rejection.kind);
+            METRICS.connections_rejected.with_label_values(&[rejection.kind]).inc();
+            let reason = Component::text(rejection.reason).to_json();
+            let frame = utils::encode_frame(&LoginDisconnectPacket { reason }.as_bytes(), None);
+            let _ = (&mc).write_all(&frame);
+            let _ = mc.shutdown(Shutdown::Both);
+            return None;
+        }
+    }
+
+    let next = greeting.login.as_ref().and_then(|login| guards.commands.take_next(&login.player_name));
+    if let Some(next) = &next{
+        info!("Forwarding to {}, picked with /proxy server", next);
+    }
+    let upstream = next.as_deref().unwrap_or(upstream);
+    session.set_upstream(upstream);
+    let server = match TcpStream::connect(upstream){
+        Ok(server) => server,
+        Err(e) => {
+            warn!("Failed to connect to: {} reason: {}", upstream, e);
+            METRICS.upstream_connect_failures.inc();
+            METRICS.connections_rejected.with_label_values(&["upstream_unavailable"]).inc();
+            return None;
+        }
+    };
+    METRICS.active_sessions.inc();
+    sessions.insert(session.clone());
+    let mc_clone = mc.try_clone().unwrap();
+    let server_clone = server.try_clone().unwrap();
+    let mc_handle = mc.try_clone().unwrap();
+    session.set_server(server.try_clone().unwrap());
+    session.set_greeting(greeting.bytes.clone());
+    let m2p = M2P {
+        input: mc,
+        output: server,
+        greeting: RefCell::new(greeting.bytes),
+        packet_rate: RefCell::new(PacketRate::new(limits.packets_per_second)),
+        sanity: RefCell::new(Sanity::new(guards.sanity.clone())),
+        chat: guards.chat.clone(),
+        commands: guards.commands.clone(),
+        verdict: RefCell::new(Verdict::Forward),
+        login_packet_sent: RefCell::new(false),
+        span: info_span!(parent: &session.span, "m2p"),
+        session: session.clone()
+    };
+    let s2p = S2P {
+        input: RefCell::new(server_clone),
+        output: mc_clone,
+        compression: RefCell::new(None),
+        chat: guards.chat.clone(),
+        commands: guards.commands.clone(),
+        verdict: RefCell::new(Verdict::Forward),
+        chat_types: RefCell::new(HashMap::new()),
+        players: RefCell::new(HashSet::new()),
+        span: info_span!(parent: &session.span, "s2p"),
+        session: session.clone()
+    };
+
+    let (done_tx, done_rx) = mpsc::channel();
+    let s2p_done = done_tx.clone();
+    thread::spawn(move || {
+        m2p.run();
+        let _ = done_tx.send(());
+    });
+    thread::spawn(move || {
+        s2p.run();
+        let _ = s2p_done.send(());
+    });
+
+    let mut last_stats = Instant::now();
+    let mut timed_out = false;
+    while let Err(RecvTimeoutError::Timeout) = done_rx.recv_timeout(WATCHDOG_INTERVAL){
+        if let Some(timeout) = limits.login_timeout(){
+            if !timed_out && session.state() != State::Play && session.stats().elapsed() > timeout{
+                timed_out = true;
+                info!("Disconnecting, not logged in within {}s", limits.login_timeout_secs);
+                METRICS.limit_disconnects.with_label_values(&["login_timeout"]).inc();
+                let _ = session.kick("Took too long to log in.");
             }
         }
+        if last_stats.elapsed() >= STATS_INTERVAL{
+            last_stats = Instant::now();
+            debug!(target: "stats", "Session {} ({}) stats\n{}", session.id, session.client_addr, session.stats().report());
+        }
     }
+    // One side is gone; close both so the other direction stops too.
+    let _ = mc_handle.shutdown(Shutdown::Both);
+    session.shutdown_server(Shutdown::Both);
+    let _ = done_rx.recv();
+    sessions.remove(session.id);
+    METRICS.active_sessions.dec();
+    info!(target: "stats", "Session {} ({}) ended\n{}", session.id, session.client_addr, session.stats().report());
+    Some(session)
 }
 
 pub struct Proxy {
-    m2p: Arc<Mutex<M2P>>,
-    s2p: Arc<Mutex<S2P>>,
+    listener: TcpListener,
+    routing: Arc<RwLock<Routing>>,
+    sessions: Arc<Sessions>,
+    guards: Arc<Guards>,
+    limiter: Arc<ConnectionLimiter>,
+    exporter: Arc<Exporter>,
+    /// Applied to new sessions; follows the config file while `watch_config` runs.
+    impairment: Arc<Mutex<Impairment>>
 }
 
 impl Proxy {
-    pub fn new() -> Proxy {
-        let mc_listener = TcpListener::bind("0.0.0.0:25567")
-            .expect("Cannot bind to default address: 0.0.0.0:25565");
-        let mc = mc_listener.accept().unwrap().0;
-        let server =
-            TcpStream::connect("localhost:25566").expect("Failed to connect to: localhost:25566");
-        let mc_clone = mc.try_clone().unwrap();
-        let server_clone = server.try_clone().unwrap();
-        let state = Arc::from(Mutex::from(State::Status));
-        let state_clone = state.clone();
+    pub fn new(config: Config) -> Proxy {
+        let mc_listener = TcpListener::bind(&config.listen)
+            .unwrap_or_else(|e| panic!("Cannot bind to address: {} reason: {}", config.listen, e));
+        info!("Listening on {}, forwarding to {}", config.listen, config.upstream);
         Proxy {
-            m2p: Arc::from(Mutex::from(M2P {
-                input: mc,
-                output: server,
-                state: state,
-                login_packet_sent: RefCell::new(false)   
-            })),
-            s2p: Arc::from(Mutex::from(S2P {
-                input: server_clone,
-                output: mc_clone,
-                state: state_clone
-            }))
+            listener: mc_listener,
+            impairment: Arc::new(Mutex::new(config.impairment)),
+            routing: Arc::new(RwLock::new(Routing { upstream: config.upstream })),
+            sessions: Arc::new(Sessions::default()),
+            guards: Arc::new(Guards { access: Access::load(config.access.clone()), limits: config.limits.clone(), sanity: config.sanity.clone(),
+                                      chat: Chat::new(config.chat.clone()), commands: Commands::new(config.commands, config.servers.clone()) }),
+            limiter: ConnectionLimiter::new(config.limits.clone()),
+            exporter: Exporter::new(config.export.clone())
         }
     }
 
-    pub fn run(&self) {
-        let r1 = self.m2p.clone();
-        let r2 = self.s2p.clone();
+    pub fn sessions(&self) -> Arc<Sessions> {
+        self.sessions.clone()
+    }
 
-        let t1 = thread::spawn(move || {
-            let g = r1.lock().unwrap();
-            g.run();
-        });
-        let t2 = thread::spawn(move || {
-            let g = r2.lock().unwrap();
-            g.run();
+    pub fn routing(&self) -> Arc<RwLock<Routing>> {
+        self.routing.clone()
+    }
+
+    pub fn exporter(&self) -> Arc<Exporter> {
+        self.exporter.clone()
+    }
+
+    /// Reloads `path` whenever it changes and applies a changed `[impairment]` to every
+    /// session, live ones included. Other settings need a restart.
+    pub fn watch_config(&self, path: String) {
+        let sessions = self.sessions.clone();
+        let impairment = self.impairment.clone();
+        config::watch(path.clone(), move || {
+            let new = match Config::read(&path){
+                Ok(config) => config.impairment,
+                Err(e) => {
+                    warn!("Ignoring invalid config {} reason: {}", path, e);
+                    return;
+                }
+            };
+            let mut current = impairment.lock().unwrap();
+            if *current != new{
+                *current = new;
+                for session in sessions.list(){
+                    session.set_impairment(new);
+                }
+                info!("Impairment changed to {}", new);
+            }
         });
+    }
 
-        t1.join().unwrap();
-        t2.join().unwrap();
+    pub fn run(&self) {
+        for stream in self.listener.incoming(){
+            match stream.and_then(|mc| Ok((mc.peer_addr()?, mc))){
+                Ok((mc_addr, mc)) => {
+                    let permit = match self.limiter.admit(mc_addr.ip()){
+                        Ok(permit) => permit,
+                        Err(reason) => {
+                            info!("Refused connection from {}: {}", mc_addr, reason);
+                            METRICS.connections_rejected.with_label_values(&[reason]).inc();
+                            continue;
+                        }
+                    };
+                    let upstream = self.routing.read().unwrap().upstream.clone();
+                    let sessions = self.sessions.clone();
+                    let impairment = *self.impairment.lock().unwrap();
+                    let guards = self.guards.clone();
+                    let exporter = self.exporter.clone();
+                    thread::spawn(move || {
+                        if let Some(session) = serve(mc, mc_addr, &upstream, &sessions, impairment, &guards){
+                            let _entered = session.span.enter();
+                            exporter.session_ended(&session);
+                        }
+                        drop(permit);
+                    });
+                }
+                Err(e) => warn!("Failed to accept connection reason: {}", e)
+            }
+        }
     }
 }
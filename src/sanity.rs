@@ -0,0 +1,357 @@
+use crate::movement::Movement;
+use crate::utils::Position;
+use crate::world::World;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Moves the speed check adds up; the client sends at most one a tick, so about a second.
+const SPEED_MOVES: usize = 20;
+/// Half the width of a player's bounding box.
+const PLAYER_RADIUS: f64 = 0.3;
+/// How far below the feet the ground check looks for a block; fences and walls are 1.5 high.
+const GROUND_DEPTH: f64 = 0.6;
+/// A check failing again within this long is counted but not logged.
+const LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What to do when a check fails.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Off,
+    Log,
+    Kick,
+}
+
+/// Movement checks for catching modified clients. Each has an action and most a limit.
+/// Speed, fly, vertical and ground are skipped while the player may fly.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SanityConfig {
+    /// Horizontal blocks over the last 20 moves, about a second.
+    pub speed: Action,
+    pub max_speed: f64,
+    /// Seconds in the air without coming back down below the height the player left the
+    /// ground at. A jump is over in well under a second.
+    pub fly: Action,
+    pub max_air_secs: f64,
+    /// Blocks climbed in one move; a jump climbs about 0.42 a tick.
+    pub vertical: Action,
+    pub max_climb: f64,
+    /// Pitch beyond ±90 degrees.
+    pub pitch: Action,
+    /// NaN or infinite coordinates or angles.
+    pub invalid: Action,
+    /// Moves in a row claiming to be on the ground with only air below, in the mirrored world.
+    pub ground: Action,
+    pub max_ground_over_air: u32,
+}
+
+impl Default for SanityConfig {
+    fn default() -> Self {
+        SanityConfig {
+            speed: Action::Log,
+            max_speed: 12.0,
+            fly: Action::Log,
+            max_air_secs: 2.0,
+            vertical: Action::Log,
+            max_climb: 1.0,
+            pitch: Action::Kick,
+            invalid: Action::Kick,
+            ground: Action::Log,
+            max_ground_over_air: 5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Check {
+    Speed,
+    Fly,
+    Vertical,
+    Pitch,
+    Invalid,
+    Ground,
+}
+
+impl Check {
+    pub fn name(self) -> &'static str {
+        match self {
+            Check::Speed => "speed",
+            Check::Fly => "fly",
+            Check::Vertical => "vertical",
+            Check::Pitch => "pitch",
+            Check::Invalid => "invalid",
+            Check::Ground => "ground",
+        }
+    }
+}
+
+/// A failed check and what was seen.
+pub struct Violation {
+    pub check: Check,
+    pub action: Action,
+    pub detail: String,
+    /// False when the same check was logged less than a second ago.
+    pub log: bool,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} check failed: {}", self.check.name(), self.detail)
+    }
+}
+
+/// Runs the checks over one client's moves.
+pub struct Sanity {
+    config: SanityConfig,
+    /// Horizontal length of the latest moves.
+    steps: VecDeque<f64>,
+    /// When and at what height the player left the ground.
+    takeoff: Option<(Instant, f64)>,
+    ground_over_air: u32,
+    /// Teleport id of the correction the state was last reset for.
+    correction: Option<i32>,
+    logged: HashMap<Check, Instant>,
+}
+
+impl Sanity {
+    pub fn new(config: SanityConfig) -> Sanity {
+        Sanity { config, steps: VecDeque::with_capacity(SPEED_MOVES), takeoff: None, ground_over_air: 0, correction: None, logged: HashMap::new() }
+    }
+
+    /// Checks a move before `movement` applies it. Moves the server ignores while a
+    /// teleport is unconfirmed are not checked.
+    pub fn check(&mut self, movement: &Movement, world: &World, position: Option<(f64, f64, f64)>, rotation: Option<(f32, f32)>,
+                 on_ground: bool) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        if movement.awaiting_confirmation() {
+            return violations;
+        }
+        // Whatever happened before the server moved the player no longer counts.
+        let correction = movement.last_correction().map(|correction| correction.teleport_id);
+        if correction != self.correction {
+            self.correction = correction;
+            self.steps.clear();
+            self.takeoff = None;
+            self.ground_over_air = 0;
+        }
+        let mut fail = |check: Check, action: Action, detail: String| violations.push(Violation { check, action, detail, log: false });
+
+        let (x, y, z) = position.unwrap_or((0.0, 0.0, 0.0));
+        let (yaw, pitch) = rotation.unwrap_or((0.0, 0.0));
+        if [x, y, z].iter().any(|value| !value.is_finite()) || [yaw, pitch].iter().any(|value| !value.is_finite()) {
+            fail(Check::Invalid, self.config.invalid, format!("position {},{},{} rotation {},{}", x, y, z, yaw, pitch));
+            return self.finish(violations);
+        }
+        if pitch.abs() > 90.0 {
+            fail(Check::Pitch, self.config.pitch, format!("pitch {}", pitch));
+        }
+        if movement.may_fly() {
+            return self.finish(violations);
+        }
+        let previous = movement.position();
+        if let (Some(position), Some((px, py, pz))) = (position, previous) {
+            if self.steps.len() == SPEED_MOVES {
+                self.steps.pop_front();
+            }
+            self.steps.push_back(((x - px).powi(2) + (z - pz).powi(2)).sqrt());
+            let travelled: f64 = self.steps.iter().sum();
+            if travelled > self.config.max_speed {
+                fail(Check::Speed, self.config.speed, format!("{:.2} blocks in {} moves", travelled, self.steps.len()));
+            }
+            if y - py > self.config.max_climb {
+                fail(Check::Vertical, self.config.vertical, format!("climbed {:.2} blocks in one move", y - py));
+            }
+            if on_ground && standing_on_air(world, position) {
+                self.ground_over_air += 1;
+                if self.ground_over_air >= self.config.max_ground_over_air {
+                    fail(Check::Ground, self.config.ground, format!("on the ground over air at {:.2},{:.2},{:.2} for {} moves", x, y, z, self.ground_over_air));
+                }
+            } else {
+                self.ground_over_air = 0;
+            }
+        }
+        if on_ground {
+            self.takeoff = None;
+        } else if let Some(height) = position.or(previous).map(|(_, y, _)| y) {
+            let (since, takeoff_y) = *self.takeoff.get_or_insert((Instant::now(), previous.map_or(height, |(_, y, _)| y)));
+            let airborne = since.elapsed().as_secs_f64();
+            if airborne > self.config.max_air_secs && height >= takeoff_y {
+                fail(Check::Fly, self.config.fly, format!("{:.1}s in the air, {:.2} blocks above take-off", airborne, height - takeoff_y));
+            }
+        }
+        self.finish(violations)
+    }
+
+    /// Drops checks that are off and marks which ones to log.
+    fn finish(&mut self, mut violations: Vec<Violation>) -> Vec<Violation> {
+        violations.retain(|violation| violation.action != Action::Off);
+        let now = Instant::now();
+        for violation in &mut violations {
+            if self.logged.get(&violation.check).is_none_or(|last| now.duration_since(*last) >= LOG_INTERVAL) {
+                self.logged.insert(violation.check, now);
+                violation.log = true;
+            }
+        }
+        violations
+    }
+}
+
+/// True when the blocks under every corner of the player's feet are air. Chunks that are
+/// not loaded count as solid. Cave and void air count as blocks, as in the world mirror.
+fn standing_on_air(world: &World, (x, y, z): (f64, f64, f64)) -> bool {
+    let corners = [(-PLAYER_RADIUS, -PLAYER_RADIUS), (-PLAYER_RADIUS, PLAYER_RADIUS), (PLAYER_RADIUS, -PLAYER_RADIUS), (PLAYER_RADIUS, PLAYER_RADIUS)];
+    let (top, bottom) = ((y - 0.01).floor() as i32, (y - GROUND_DEPTH).floor() as i32);
+    corners.iter().all(|(dx, dz)| {
+        (bottom..=top).all(|by| world.block_at(Position { x: (x + dx).floor() as i32, y: by, z: (z + dz).floor() as i32 }) == Some(0))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{Chunk, LightData};
+    use crate::packets::server::SynchronizePlayerPositionPacket;
+    use std::thread;
+
+    /// A player spawned at `SPAWN`, in a world with nothing loaded.
+    struct Player {
+        movement: Movement,
+        sanity: Sanity,
+        world: World,
+    }
+
+    const SPAWN: (f64, f64, f64) = (0.5, 64.0, 0.5);
+
+    impl Player {
+        fn new(config: SanityConfig) -> Player {
+            let mut player = Player { movement: Movement::new(), sanity: Sanity::new(config), world: World::new() };
+            player.teleport(1, SPAWN);
+            player
+        }
+
+        fn teleport(&mut self, teleport_id: i32, (x, y, z): (f64, f64, f64)) {
+            self.movement.synchronize(&SynchronizePlayerPositionPacket { x, y, z, yaw: 0.0, pitch: 0.0, flags: 0, teleport_id, dismount_vehicle: false });
+            self.movement.confirm(teleport_id);
+        }
+
+        fn moved(&mut self, position: Option<(f64, f64, f64)>, rotation: Option<(f32, f32)>, on_ground: bool) -> Vec<Violation> {
+            let violations = self.sanity.check(&self.movement, &self.world, position, rotation, on_ground);
+            self.movement.client_moved(position, rotation, on_ground);
+            violations
+        }
+
+        fn step(&mut self, position: (f64, f64, f64), on_ground: bool) -> Vec<Check> {
+            self.moved(Some(position), None, on_ground).iter().map(|violation| violation.check).collect()
+        }
+    }
+
+    #[test]
+    fn speed_adds_up_the_latest_moves() {
+        let mut player = Player::new(SanityConfig::default());
+        for step in 1..=12 {
+            assert!(player.step((0.5 + step as f64, 64.0, 0.5), true).is_empty(), "step {}", step);
+        }
+        // Height changes do not count.
+        let violations = player.moved(Some((13.5, 60.0, 0.5)), None, true);
+        assert_eq!(violations.len(), 1);
+        assert_eq!((violations[0].check, violations[0].action, violations[0].log), (Check::Speed, Action::Log, true));
+        assert_eq!(violations[0].detail, "13.00 blocks in 13 moves");
+        // Failing again right away is not logged again.
+        assert!(!player.moved(Some((14.5, 60.0, 0.5)), None, true)[0].log);
+
+        // Creative players are not held to it.
+        player.movement.set_may_fly(true);
+        assert!(player.step((40.5, 60.0, 0.5), true).is_empty());
+    }
+
+    #[test]
+    fn fly_time_counts_from_the_take_off_height() {
+        let mut player = Player::new(SanityConfig { max_air_secs: 0.05, ..SanityConfig::default() });
+        assert!(player.step((0.5, 64.5, 0.5), false).is_empty());
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(player.step((0.5, 65.0, 0.5), false), [Check::Fly]);
+        // Falling below where the player left the ground is no longer flying, however long it takes.
+        assert!(player.step((0.5, 63.9, 0.5), false).is_empty());
+
+        // Landing starts the clock over.
+        assert!(player.step((0.5, 63.0, 0.5), true).is_empty());
+        assert!(player.step((0.5, 63.4, 0.5), false).is_empty());
+        assert!(player.step((0.5, 63.2, 0.5), false).is_empty());
+    }
+
+    #[test]
+    fn climbs_beyond_a_jump_fail() {
+        let mut player = Player::new(SanityConfig::default());
+        assert!(player.step((0.5, 64.42, 0.5), false).is_empty());
+        assert_eq!(player.step((0.5, 65.92, 0.5), false), [Check::Vertical]);
+        // Falling any distance is fine.
+        assert!(player.step((0.5, 50.0, 0.5), true).is_empty());
+    }
+
+    #[test]
+    fn impossible_rotations_and_coordinates() {
+        let mut player = Player::new(SanityConfig::default());
+        let violations = player.moved(None, Some((0.0, 90.5)), true);
+        assert_eq!((violations[0].check, violations[0].action), (Check::Pitch, Action::Kick));
+        assert!(player.moved(None, Some((720.0, -90.0)), true).is_empty());
+
+        // NaN fails only the invalid check, whatever else it would compare as.
+        let violations = player.moved(Some((f64::NAN, 64.0, 0.5)), Some((0.0, 95.0)), true);
+        assert_eq!(violations.iter().map(|violation| violation.check).collect::<Vec<_>>(), [Check::Invalid]);
+        assert_eq!(violations[0].detail, "position NaN,64,0.5 rotation 0,95");
+        assert_eq!(player.step((0.5, f64::INFINITY, 0.5), true), [Check::Invalid]);
+    }
+
+    #[test]
+    fn standing_on_air_in_the_mirrored_world() {
+        let mut player = Player::new(SanityConfig { max_ground_over_air: 3, ..SanityConfig::default() });
+        player.world.load(Chunk { x: 0, z: 0, heightmaps: Default::default(), sections: Vec::new(), block_entities: Vec::new(), light: LightData::default() });
+        // One block of stone at 8,62,8. Its section and those below are filled up with air; those above are not sent.
+        assert!(player.world.set_block(Position { x: 8, y: 62, z: 8 }, 1));
+        assert!(!standing_on_air(&player.world, (8.5, 63.0, 8.5)));
+        // A corner over the block is enough, and so is a block up to 0.6 below the feet.
+        assert!(!standing_on_air(&player.world, (7.8, 63.0, 7.8)));
+        assert!(!standing_on_air(&player.world, (8.5, 63.5, 8.5)));
+        assert!(standing_on_air(&player.world, (8.5, 63.7, 8.5)));
+        assert!(standing_on_air(&player.world, (4.5, 63.0, 4.5)));
+        // Sections that were never sent and unloaded chunks count as solid.
+        assert!(!standing_on_air(&player.world, (4.5, 80.0, 4.5)));
+        assert!(!standing_on_air(&player.world, (-4.5, 63.0, 4.5)));
+
+        player.teleport(2, (4.5, 63.0, 4.5));
+        assert!(player.step((4.6, 63.0, 4.5), true).is_empty());
+        assert!(player.step((4.7, 63.0, 4.5), true).is_empty());
+        assert_eq!(player.step((4.8, 63.0, 4.5), true), [Check::Ground]);
+        // A move in the air starts the count over.
+        assert!(player.step((4.8, 63.0, 4.5), false).is_empty());
+        assert!(player.step((4.8, 63.0, 4.5), true).is_empty());
+    }
+
+    #[test]
+    fn corrections_reset_the_checks() {
+        let mut player = Player::new(SanityConfig::default());
+        for step in 1..=12 {
+            player.step((0.5 + step as f64, 64.0, 0.5), true);
+        }
+        player.teleport(2, (0.5, 64.0, 0.5));
+        // The moves before the correction no longer count towards the speed.
+        for step in 1..=12 {
+            assert!(player.step((0.5 + step as f64, 64.0, 0.5), true).is_empty(), "step {}", step);
+        }
+
+        // Nothing is checked while the server ignores the moves.
+        player.movement.synchronize(&SynchronizePlayerPositionPacket { x: 0.5, y: 64.0, z: 0.5, yaw: 0.0, pitch: 0.0, flags: 0, teleport_id: 3, dismount_vehicle: false });
+        assert!(player.moved(Some((f64::NAN, 0.0, 0.0)), Some((0.0, 180.0)), true).is_empty());
+        player.movement.confirm(3);
+        assert!(player.step((1.5, 64.0, 0.5), true).is_empty());
+    }
+
+    #[test]
+    fn checks_that_are_off_never_fail() {
+        let mut player = Player::new(SanityConfig { pitch: Action::Off, vertical: Action::Off, ..SanityConfig::default() });
+        assert!(player.moved(Some((0.5, 70.0, 0.5)), Some((0.0, 100.0)), false).is_empty());
+    }
+}
@@ -0,0 +1,385 @@
+use crate::entity_tracker::Entities;
+use crate::impair::{Impairment, Injector};
+use crate::metrics::METRICS;
+use crate::movement::Movement;
+use crate::packets::names::packet_name;
+use crate::packets::server::{DisconnectPacket, LoginDisconnectPacket, SystemChatPacket};
+use crate::packets::PacketWrite;
+use crate::stats::SessionStats;
+use crate::switch::Switch;
+use crate::text::Component;
+use crate::utils::{self, Direction, RawPacket, State};
+use crate::world::World;
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tracing::{info_span, Span};
+use uuid::Uuid;
+
+/// State shared by the two directions of one proxied connection.
+pub struct Session {
+    pub id: u64,
+    pub client_addr: SocketAddr,
+    pub span: Span,
+    state: Mutex<State>,
+    compression: Mutex<Option<usize>>,
+    upstream_compression: Mutex<Option<usize>>,
+    decoding: AtomicBool,
+    inspecting: AtomicBool,
+    login_started: Mutex<Option<Instant>>,
+    greeting: Mutex<Vec<u8>>,
+    login_start: Mutex<Option<(String, Option<Uuid>)>>,
+    profile: Mutex<Option<(Uuid, String)>>,
+    upstream: Mutex<String>,
+    server: Mutex<Option<TcpStream>>,
+    switch: Mutex<Switch>,
+    serverbound_frame: Mutex<()>,
+    clientbound: Mutex<Option<Injector>>,
+    serverbound: Mutex<Option<Injector>>,
+    impairment: Mutex<Impairment>,
+    stats: Mutex<SessionStats>,
+    world: Mutex<World>,
+    entities: Mutex<Entities>,
+    movement: Mutex<Movement>,
+}
+
+impl Session {
+    pub fn new(id: u64, client_addr: SocketAddr, impairment: Impairment) -> Session {
+        Session {
+            id,
+            client_addr,
+            span: info_span!("session",
+                             id,
+                             client = %client_addr,
+                             player = tracing::field::Empty,
+                             uuid = tracing::field::Empty),
+            state: Mutex::new(State::Handshake),
+            compression: Mutex::new(None),
+            upstream_compression: Mutex::new(None),
+            decoding: AtomicBool::new(true),
+            inspecting: AtomicBool::new(true),
+            login_started: Mutex::new(None),
+            greeting: Mutex::new(Vec::new()),
+            login_start: Mutex::new(None),
+            profile: Mutex::new(None),
+            upstream: Mutex::new(String::new()),
+            server: Mutex::new(None),
+            switch: Mutex::new(Switch::default()),
+            serverbound_frame: Mutex::new(()),
+            clientbound: Mutex::new(None),
+            serverbound: Mutex::new(None),
+            impairment: Mutex::new(impairment),
+            stats: Mutex::new(SessionStats::new()),
+            world: Mutex::new(World::new()),
+            entities: Mutex::new(Entities::new()),
+            movement: Mutex::new(Movement::new()),
+        }
+    }
+
+    pub fn state(&self) -> State {
+        *self.state.lock().unwrap()
+    }
+
+    pub fn set_state(&self, new_state: State) {
+        *self.state.lock().unwrap() = new_state;
+        self.stats.lock().unwrap().enter_state(new_state);
+        let mut login_started = self.login_started.lock().unwrap();
+        match new_state {
+            State::Login => *login_started = Some(Instant::now()),
+            State::Play => if let Some(started) = login_started.take() {
+                METRICS.login_latency.observe(started.elapsed().as_secs_f64());
+            },
+            _ => (),
+        }
+    }
+
+    /// Compression threshold from Set Compression, `None` until the server sends one.
+    /// This is the client's, which stays the same when the player moves servers.
+    pub fn compression(&self) -> Option<usize> {
+        *self.compression.lock().unwrap()
+    }
+
+    /// Sets the threshold both connections use from now on.
+    pub fn set_compression(&self, threshold: Option<usize>) {
+        *self.compression.lock().unwrap() = threshold;
+        *self.upstream_compression.lock().unwrap() = threshold;
+    }
+
+    /// Threshold frames written to the server use; it differs from the client's after a
+    /// move to a server that compresses differently.
+    pub fn upstream_compression(&self) -> Option<usize> {
+        *self.upstream_compression.lock().unwrap()
+    }
+
+    pub fn set_upstream_compression(&self, threshold: Option<usize>) {
+        *self.upstream_compression.lock().unwrap() = threshold;
+    }
+
+    /// False once the stream can no longer be framed (e.g. it became encrypted);
+    /// both directions then forward bytes untouched.
+    pub fn decoding(&self) -> bool {
+        self.decoding.load(Ordering::Relaxed)
+    }
+
+    pub fn stop_decoding(&self) {
+        self.decoding.store(false, Ordering::Relaxed);
+    }
+
+    /// False while Play packets are handled without being logged or shown as packet events.
+    /// Chat, commands, limits, the world mirror and moves between servers carry on.
+    pub fn inspecting(&self) -> bool {
+        self.inspecting.load(Ordering::Relaxed)
+    }
+
+    pub fn set_inspecting(&self, inspecting: bool) {
+        self.inspecting.store(inspecting, Ordering::Relaxed);
+    }
+
+    pub fn record_packet(&self, direction: Direction, state: State, packet: &RawPacket) {
+        self.stats.lock().unwrap().record(direction, state, packet.id, packet.wire_len, packet.uncompressed_len);
+        let labels = [&direction.to_string(), &format!("{:?}", state), packet_name(state, direction, packet.id)];
+        METRICS.packets.with_label_values(&labels).inc();
+        METRICS.bytes.with_label_values(&labels).inc_by(packet.wire_len as u64);
+    }
+
+    /// Handshake and Login Start as the client sent them, replayed to log in to another server.
+    pub fn greeting(&self) -> Vec<u8> {
+        self.greeting.lock().unwrap().clone()
+    }
+
+    pub fn set_greeting(&self, greeting: Vec<u8>) {
+        *self.greeting.lock().unwrap() = greeting;
+    }
+
+    /// Name and uuid the client sent in Login Start.
+    pub fn set_login_start(&self, name: String, uuid: Option<Uuid>) {
+        self.span.record("player", name.as_str());
+        if let Some(uuid) = uuid {
+            self.span.record("uuid", tracing::field::display(uuid));
+        }
+        *self.login_start.lock().unwrap() = Some((name, uuid));
+    }
+
+    /// Player name and uuid, as assigned by the server once logged in, else as requested.
+    pub fn player(&self) -> Option<(String, Option<Uuid>)> {
+        if let Some((uuid, name)) = self.profile() {
+            return Some((name, Some(uuid)));
+        }
+        self.login_start.lock().unwrap().clone()
+    }
+
+    /// The name the client sent in Login Start, which its next login will use too.
+    pub fn requested_name(&self) -> Option<String> {
+        self.login_start.lock().unwrap().as_ref().map(|(name, _)| name.clone())
+    }
+
+    /// True if `name` is the player's name, as requested or as assigned.
+    pub fn is_player(&self, name: &str) -> bool {
+        let requested = self.login_start.lock().unwrap().as_ref().is_some_and(|(requested, _)| requested.eq_ignore_ascii_case(name));
+        requested || self.profile().is_some_and(|(_, assigned)| assigned.eq_ignore_ascii_case(name))
+    }
+
+    /// Uuid and name the server assigned in Login Success.
+    pub fn profile(&self) -> Option<(Uuid, String)> {
+        self.profile.lock().unwrap().clone()
+    }
+
+    pub fn set_profile(&self, uuid: Uuid, name: String) {
+        *self.profile.lock().unwrap() = Some((uuid, name));
+    }
+
+    /// Address of the server the session is forwarded to.
+    pub fn upstream(&self) -> String {
+        self.upstream.lock().unwrap().clone()
+    }
+
+    pub fn set_upstream(&self, upstream: &str) {
+        *self.upstream.lock().unwrap() = upstream.to_string();
+    }
+
+    /// Keeps a handle on the connection to the server, so it can be shut down from outside.
+    pub fn set_server(&self, server: TcpStream) {
+        *self.server.lock().unwrap() = Some(server);
+    }
+
+    /// Another handle on the connection to the server.
+    pub fn server(&self) -> Result<TcpStream, Error> {
+        self.server.lock().unwrap().as_ref()
+            .ok_or_else(|| Error::new(ErrorKind::NotConnected, "session is not forwarding yet"))?
+            .try_clone()
+    }
+
+    pub fn shutdown_server(&self, how: Shutdown) {
+        if let Some(server) = &*self.server.lock().unwrap() {
+            let _ = server.shutdown(how);
+        }
+    }
+
+    /// The player's move to another server, while one is under way.
+    pub fn switch(&self) -> MutexGuard<'_, Switch> {
+        self.switch.lock().unwrap()
+    }
+
+    /// Held while a serverbound frame is compressed and queued, so a move to another
+    /// server falls between two frames.
+    pub fn serverbound_frame(&self) -> MutexGuard<'_, ()> {
+        self.serverbound_frame.lock().unwrap()
+    }
+
+    pub fn keep_alive_sent(&self, id: i64) {
+        self.stats.lock().unwrap().latency.keep_alive_sent(id);
+    }
+
+    /// Matches the client's Keep Alive answer and returns the round trip and the new average.
+    pub fn keep_alive_answered(&self, id: i64) -> Option<(Duration, Duration)> {
+        let mut stats = self.stats.lock().unwrap();
+        let rtt = stats.latency.keep_alive_answered(id)?;
+        METRICS.client_rtt.observe(rtt.as_secs_f64());
+        Some((rtt, stats.latency.smoothed.unwrap_or(rtt)))
+    }
+
+    /// Stops waiting for answers to the Keep Alives sent so far and returns their ids.
+    pub fn forget_keep_alives(&self) -> Vec<i64> {
+        self.stats.lock().unwrap().latency.forget_pending()
+    }
+
+    /// Records the latency the server reported for this player; returns the upstream estimate.
+    pub fn server_reported_latency(&self, latency: Duration) -> Option<Duration> {
+        let mut stats = self.stats.lock().unwrap();
+        stats.latency.server_reported(latency);
+        let upstream = stats.latency.upstream()?;
+        METRICS.upstream_latency.observe(upstream.as_secs_f64());
+        Some(upstream)
+    }
+
+    /// Simulated network conditions; read for every chunk forwarded, so changes apply at once.
+    pub fn impairment(&self) -> Impairment {
+        *self.impairment.lock().unwrap()
+    }
+
+    pub fn set_impairment(&self, impairment: Impairment) {
+        *self.impairment.lock().unwrap() = impairment;
+    }
+
+    /// Writes what is injected or forwarded in `direction` after what is already queued
+    /// to `output`, and closes the connection written to so far.
+    pub fn redirect(&self, direction: Direction, output: TcpStream) -> Result<(), Error> {
+        let injector = self.injector(direction).lock().unwrap().clone()
+            .ok_or_else(|| Error::new(ErrorKind::NotConnected, "session is not forwarding yet"))?;
+        injector.redirect(output)
+    }
+
+    /// Registers the link frames in `direction` are written to, so packets can be injected.
+    pub fn attach(&self, direction: Direction, injector: Injector) {
+        *self.injector(direction).lock().unwrap() = Some(injector);
+    }
+
+    fn injector(&self, direction: Direction) -> &Mutex<Option<Injector>> {
+        match direction {
+            Direction::Serverbound => &self.serverbound,
+            Direction::Clientbound => &self.clientbound,
+        }
+    }
+
+    /// Writes `packet` in `direction` between two forwarded frames, closing the
+    /// connection afterwards when `close` is set.
+    pub fn inject(&self, direction: Direction, packet: &impl PacketWrite, close: bool) -> Result<(), Error> {
+        if !self.decoding() {
+            return Err(Error::new(ErrorKind::Unsupported, "session is not decoded, cannot inject"));
+        }
+        let injector = self.injector(direction).lock().unwrap().clone()
+            .ok_or_else(|| Error::new(ErrorKind::NotConnected, "session is not forwarding yet"))?;
+        let compression = match direction {
+            Direction::Serverbound => self.upstream_compression(),
+            Direction::Clientbound => self.compression(),
+        };
+        let frame = utils::encode_frame(&packet.as_bytes(), compression);
+        if close { injector.send_and_close(frame) } else { injector.send(frame) }
+    }
+
+    /// Disconnects the player with `message`, which may use legacy `§` codes. Before
+    /// Login, or when packets cannot be injected, the connection is just closed.
+    pub fn kick(&self, message: &str) -> Result<(), Error> {
+        let reason = Component::from_legacy(message).to_json();
+        let sent = match self.state() {
+            State::Login => self.inject(Direction::Clientbound, &LoginDisconnectPacket { reason }, true),
+            State::Play => self.inject(Direction::Clientbound, &DisconnectPacket { reason }, true),
+            _ => Err(Error::new(ErrorKind::Unsupported, "not logging in")),
+        };
+        if sent.is_err() {
+            let injector = self.clientbound.lock().unwrap().clone()
+                .ok_or_else(|| Error::new(ErrorKind::NotConnected, "session is not forwarding yet"))?;
+            injector.send_and_close(Vec::new())?;
+        }
+        Ok(())
+    }
+
+    /// Shows `message`, which may use legacy `§` codes, in the player's chat.
+    pub fn send_chat(&self, message: &str) -> Result<(), Error> {
+        self.send_text(&Component::from_legacy(message))
+    }
+
+    /// Shows a text component in the player's chat.
+    pub fn send_text(&self, text: &Component) -> Result<(), Error> {
+        if self.state() != State::Play {
+            return Err(Error::new(ErrorKind::Unsupported, "player is not in game"));
+        }
+        self.inject(Direction::Clientbound, &SystemChatPacket { content: text.to_json(), overlay: false }, false)
+    }
+
+    /// Snapshot of the traffic counters.
+    pub fn stats(&self) -> SessionStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Mirror of the chunks the client has loaded.
+    pub fn world(&self) -> MutexGuard<'_, World> {
+        self.world.lock().unwrap()
+    }
+
+    /// Entities the client has been told about.
+    pub fn entities(&self) -> MutexGuard<'_, Entities> {
+        self.entities.lock().unwrap()
+    }
+
+    /// The player's own movement.
+    pub fn movement(&self) -> MutexGuard<'_, Movement> {
+        self.movement.lock().unwrap()
+    }
+}
+
+/// Sessions currently being proxied, by id.
+#[derive(Default)]
+pub struct Sessions {
+    sessions: Mutex<BTreeMap<u64, Arc<Session>>>,
+}
+
+impl Sessions {
+    pub fn insert(&self, session: Arc<Session>) {
+        self.sessions.lock().unwrap().insert(session.id, session);
+    }
+
+    pub fn remove(&self, id: u64) {
+        self.sessions.lock().unwrap().remove(&id);
+    }
+
+    pub fn list(&self) -> Vec<Arc<Session>> {
+        self.sessions.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Looks a session up by id or by player name.
+    pub fn find(&self, key: &str) -> Option<Arc<Session>> {
+        let sessions = self.sessions.lock().unwrap();
+        if let Ok(id) = key.parse::<u64>() {
+            if let Some(session) = sessions.get(&id) {
+                return Some(session.clone());
+            }
+        }
+        sessions.values()
+            .find(|session| session.is_player(key))
+            .cloned()
+    }
+}
@@ -0,0 +1,264 @@
+use crate::packets::names::packet_name;
+use crate::utils::{Direction, State};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// Counters for one kind of packet, or for a whole direction.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PacketStats {
+    pub count: u64,
+    /// Bytes on the wire, after compression.
+    pub wire_bytes: u64,
+    /// Bytes of id + data before compression.
+    pub uncompressed_bytes: u64,
+    /// Largest packet seen, in uncompressed bytes.
+    pub largest: usize,
+}
+
+impl PacketStats {
+    fn add(&mut self, wire_len: usize, uncompressed_len: usize) {
+        self.count += 1;
+        self.wire_bytes += wire_len as u64;
+        self.uncompressed_bytes += uncompressed_len as u64;
+        self.largest = self.largest.max(uncompressed_len);
+    }
+
+    fn merge(&mut self, other: &PacketStats) {
+        self.count += other.count;
+        self.wire_bytes += other.wire_bytes;
+        self.uncompressed_bytes += other.uncompressed_bytes;
+        self.largest = self.largest.max(other.largest);
+    }
+}
+
+/// Keep Alive ids forwarded to the client and not answered yet are kept up to this many.
+const MAX_PENDING_KEEP_ALIVES: usize = 16;
+
+/// Keep Alive round trips between the proxy and the client, and the latency the
+/// server reports for the player, from which the proxy↔server share is derived.
+#[derive(Clone, Debug, Default)]
+pub struct LatencyStats {
+    pending: VecDeque<(i64, Instant)>,
+    pub samples: u64,
+    pub last: Option<Duration>,
+    /// Moving average weighted like the server's own (3/4 old, 1/4 new).
+    pub smoothed: Option<Duration>,
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+    /// Latency from the server's Player Info updates; covers server→proxy→client and back.
+    pub server_reported: Option<Duration>,
+}
+
+impl LatencyStats {
+    /// A clientbound Keep Alive with `id` is about to be forwarded to the client.
+    pub fn keep_alive_sent(&mut self, id: i64) {
+        if self.pending.len() == MAX_PENDING_KEEP_ALIVES {
+            self.pending.pop_front();
+        }
+        self.pending.push_back((id, Instant::now()));
+    }
+
+    /// The client answered Keep Alive `id`; returns the proxy↔client round trip.
+    pub fn keep_alive_answered(&mut self, id: i64) -> Option<Duration> {
+        let index = self.pending.iter().position(|(pending, _)| *pending == id)?;
+        let (_, sent) = self.pending.remove(index)?;
+        let rtt = sent.elapsed();
+        self.add_sample(rtt);
+        Some(rtt)
+    }
+
+    fn add_sample(&mut self, rtt: Duration) {
+        self.samples += 1;
+        self.last = Some(rtt);
+        self.smoothed = Some(self.smoothed.map_or(rtt, |old| (old * 3 + rtt) / 4));
+        self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+        self.max = Some(self.max.map_or(rtt, |max| max.max(rtt)));
+    }
+
+    /// Drops every unanswered Keep Alive, returning their ids.
+    pub fn forget_pending(&mut self) -> Vec<i64> {
+        self.pending.drain(..).map(|(id, _)| id).collect()
+    }
+
+    pub fn server_reported(&mut self, latency: Duration) {
+        self.server_reported = Some(latency);
+    }
+
+    /// Estimated proxy↔server share: what the server measures minus the client leg.
+    pub fn upstream(&self) -> Option<Duration> {
+        Some(self.server_reported?.saturating_sub(self.smoothed?))
+    }
+
+    pub fn summary(&self) -> String {
+        let ms = |d: Option<Duration>| d.map_or("-".to_string(), |d| format!("{:.1}ms", d.as_secs_f64() * 1000.0));
+        format!("client rtt last {} avg {} min {} max {} ({} samples), server reported {}, upstream ~{}",
+                ms(self.last), ms(self.smoothed), ms(self.min), ms(self.max), self.samples,
+                ms(self.server_reported), ms(self.upstream()))
+    }
+}
+
+/// Traffic counters for one session, keyed by direction, state and packet id,
+/// plus how long the session spent in each `State`.
+#[derive(Clone, Debug)]
+pub struct SessionStats {
+    started: Instant,
+    state: State,
+    state_since: Instant,
+    state_times: BTreeMap<State, Duration>,
+    packets: BTreeMap<(Direction, State, i32), PacketStats>,
+    pub latency: LatencyStats,
+}
+
+impl Default for SessionStats {
+    fn default() -> Self {
+        SessionStats::new()
+    }
+}
+
+impl SessionStats {
+    pub fn new() -> SessionStats {
+        let now = Instant::now();
+        SessionStats {
+            started: now,
+            state: State::Handshake,
+            state_since: now,
+            state_times: BTreeMap::new(),
+            packets: BTreeMap::new(),
+            latency: LatencyStats::default(),
+        }
+    }
+
+    pub fn record(&mut self, direction: Direction, state: State, id: i32, wire_len: usize, uncompressed_len: usize) {
+        self.packets
+            .entry((direction, state, id))
+            .or_default()
+            .add(wire_len, uncompressed_len);
+    }
+
+    pub fn enter_state(&mut self, state: State) {
+        let now = Instant::now();
+        *self.state_times.entry(self.state).or_default() += now - self.state_since;
+        self.state = state;
+        self.state_since = now;
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Time spent in `state` so far, including the current stay.
+    pub fn time_in(&self, state: State) -> Duration {
+        let past = self.state_times.get(&state).copied().unwrap_or_default();
+        if state == self.state {
+            past + self.state_since.elapsed()
+        } else {
+            past
+        }
+    }
+
+    /// Counters for every packet kind seen, ordered by direction, state and id.
+    pub fn packets(&self) -> impl Iterator<Item = (Direction, State, i32, &PacketStats)> {
+        self.packets.iter().map(|((direction, state, id), stats)| (*direction, *state, *id, stats))
+    }
+
+    pub fn total(&self, direction: Direction) -> PacketStats {
+        let mut total = PacketStats::default();
+        for (_, stats) in self.packets.range((direction, State::Handshake, i32::MIN)..=(direction, State::Unknown, i32::MAX)) {
+            total.merge(stats);
+        }
+        total
+    }
+
+    /// Renders the counters as a plain-text table.
+    pub fn report(&self) -> String {
+        let elapsed = self.elapsed();
+        let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        let mut out = String::new();
+
+        let _ = writeln!(out, "duration {:.2}s", elapsed.as_secs_f64());
+        let _ = writeln!(out, "{:<10} {:>10}", "state", "time");
+        for state in [State::Handshake, State::Status, State::Login, State::Play] {
+            let time = self.time_in(state);
+            if !time.is_zero() {
+                let _ = writeln!(out, "{:<10} {:>9.2}s", format!("{:?}", state), time.as_secs_f64());
+            }
+        }
+        let _ = writeln!(out, "latency    {}", self.latency.summary());
+
+        let _ = writeln!(out);
+        let _ = writeln!(out, "{:<11} {:<9} {:<5} {:<32} {:>8} {:>8} {:>12} {:>12} {:>8}",
+                         "direction", "state", "id", "packet", "count", "pkt/s", "wire B", "data B", "largest");
+        for (direction, state, id, stats) in self.packets() {
+            let _ = writeln!(out, "{:<11} {:<9} {:<#5x} {:<32} {:>8} {:>8.2} {:>12} {:>12} {:>8}",
+                             direction.to_string(), format!("{:?}", state), id, packet_name(state, direction, id),
+                             stats.count, stats.count as f64 / secs,
+                             stats.wire_bytes, stats.uncompressed_bytes, stats.largest);
+        }
+        for direction in [Direction::Serverbound, Direction::Clientbound] {
+            let total = self.total(direction);
+            let _ = writeln!(out, "{:<11} {:<9} {:<5} {:<32} {:>8} {:>8.2} {:>12} {:>12} {:>8}",
+                             direction.to_string(), "total", "", "",
+                             total.count, total.count as f64 / secs,
+                             total.wire_bytes, total.uncompressed_bytes, total.largest);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn keep_alives_are_matched_by_id() {
+        let mut latency = LatencyStats::default();
+        latency.keep_alive_sent(1);
+        latency.keep_alive_sent(2);
+        thread::sleep(ms(20));
+        // Ids the proxy never forwarded, or already matched, are not samples.
+        assert!(latency.keep_alive_answered(3).is_none());
+        assert!(latency.keep_alive_answered(2).unwrap() >= ms(20));
+        assert!(latency.keep_alive_answered(2).is_none());
+        assert_eq!(latency.samples, 1);
+        assert_eq!(latency.forget_pending(), [1]);
+        assert!(latency.keep_alive_answered(1).is_none());
+
+        // Only the latest ids are remembered.
+        for id in 0..=MAX_PENDING_KEEP_ALIVES as i64 {
+            latency.keep_alive_sent(id);
+        }
+        assert!(latency.keep_alive_answered(0).is_none());
+        assert!(latency.keep_alive_answered(1).is_some());
+        assert_eq!(latency.samples, 2);
+    }
+
+    #[test]
+    fn round_trips_are_smoothed_like_the_server() {
+        let mut latency = LatencyStats::default();
+        latency.add_sample(ms(100));
+        assert_eq!((latency.smoothed, latency.min, latency.max), (Some(ms(100)), Some(ms(100)), Some(ms(100))));
+        latency.add_sample(ms(20));
+        assert_eq!(latency.smoothed, Some(ms(80)));
+        latency.add_sample(ms(200));
+        assert_eq!(latency.smoothed, Some(ms(110)));
+        assert_eq!((latency.last, latency.min, latency.max, latency.samples), (Some(ms(200)), Some(ms(20)), Some(ms(200)), 3));
+    }
+
+    #[test]
+    fn upstream_is_what_the_client_leg_leaves() {
+        let mut latency = LatencyStats::default();
+        latency.server_reported(ms(150));
+        assert!(latency.upstream().is_none());
+        latency.add_sample(ms(40));
+        assert_eq!(latency.upstream(), Some(ms(110)));
+        // The server averages over a longer time; a smoothed client leg above it is not negative.
+        latency.add_sample(ms(600));
+        assert_eq!(latency.upstream(), Some(Duration::ZERO));
+    }
+}
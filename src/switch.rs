@@ -0,0 +1,308 @@
+//! Moving a player to another server without a reconnect: the proxy logs in to the new
+//! server itself, then hands the running session over to it.
+
+use crate::packets::names::packet_name;
+use crate::packets::server::{self, LoginDisconnectPacket, LoginSuccessPacket, SetCompressionPacket};
+use crate::packets::Packet;
+use crate::session::Session;
+use crate::text;
+use crate::utils::{self, Direction, State};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::info;
+use uuid::Uuid;
+
+/// How long connecting to and logging in to the new server may take. The player stays
+/// on the old server meanwhile.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A server the player was logged in to, ready to take over in Play.
+pub struct Backend {
+    pub address: String,
+    pub stream: TcpStream,
+    /// Threshold from the server's Set Compression.
+    pub compression: Option<usize>,
+    /// Uuid and name from Login Success.
+    pub profile: (Uuid, String),
+    /// Bytes the server sent after Login Success.
+    pub pending: Vec<u8>,
+}
+
+/// Where a session's move to another server stands.
+#[derive(Default)]
+pub struct Switch {
+    /// Name of the server the proxy is logging in to for the player.
+    pub connecting: Option<String>,
+    /// Name of the server the player is moving to, until its Login reaches the client.
+    pub target: Option<String>,
+    /// Logged in, for the clientbound side to read from once it is done with the old server.
+    pub ready: Option<Backend>,
+    /// Keep Alives the old server sent; the new one would disconnect the player for answering them.
+    pub stale_keep_alives: Vec<i64>,
+}
+
+impl Switch {
+    /// The server the player is moving to, from the start of the login until the new
+    /// server's Login reaches the client.
+    pub fn moving_to(&self) -> Option<&str> {
+        self.connecting.as_deref().or(self.target.as_deref())
+    }
+}
+
+/// Moves the player to server `name` at `address`. The login runs on its own thread,
+/// so the player keeps playing on the old server until the new one is ready. `done`
+/// gets the outcome there; the login fails with `Unsupported` when the server needs one
+/// only the client can do, such as encryption.
+pub fn switch(session: Arc<Session>, name: &str, address: &str, done: impl FnOnce(&Session, io::Result<()>) + Send + 'static) {
+    info!(target: "commands", "Logging in to {} ({}) to move the player there", name, address);
+    session.switch().connecting = Some(name.to_string());
+    let (name, address) = (name.to_string(), address.to_string());
+    thread::spawn(move || {
+        let _entered = session.span.enter();
+        let result = login(&session, &address).and_then(|backend| hand_over(&session, &name, backend));
+        if result.is_err() {
+            *session.switch() = Switch::default();
+        }
+        done(&session, result);
+    });
+}
+
+/// Sends the serverbound side to `backend` between two frames, so the frames after it
+/// go to the new server and are compressed for it.
+fn hand_over(session: &Session, name: &str, backend: Backend) -> io::Result<()> {
+    let output = backend.stream.try_clone()?;
+    let compression = backend.compression;
+    let _between_frames = session.serverbound_frame();
+    {
+        let stale_keep_alives = session.forget_keep_alives();
+        let mut switch = session.switch();
+        switch.connecting = None;
+        switch.target = Some(name.to_string());
+        switch.ready = Some(backend);
+        switch.stale_keep_alives = stale_keep_alives;
+    }
+    // Taken first: once the redirect closes it, the clientbound side may register the new one.
+    let old_server = session.server()?;
+    // Set before the redirect so frames injected after it are compressed for the new server.
+    let old_compression = session.upstream_compression();
+    session.set_upstream_compression(compression);
+    if let Err(e) = session.redirect(Direction::Serverbound, output) {
+        session.set_upstream_compression(old_compression);
+        return Err(e);
+    }
+    // Wakes the clientbound side, which picks the new server up.
+    let _ = old_server.shutdown(Shutdown::Read);
+    Ok(())
+}
+
+/// Connects to `address` and replays the client's login there, up to Login Success.
+fn login(session: &Session, address: &str) -> io::Result<Backend> {
+    let greeting = session.greeting();
+    if greeting.is_empty() {
+        return Err(io::Error::new(ErrorKind::Unsupported, "the client's login was not kept"));
+    }
+    let target = address.to_socket_addrs()?.next()
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "the address does not resolve"))?;
+    let mut stream = TcpStream::connect_timeout(&target, LOGIN_TIMEOUT)?;
+    stream.set_read_timeout(Some(LOGIN_TIMEOUT))?;
+    stream.write_all(&greeting)?;
+
+    let mut compression = None;
+    let mut pending = Vec::new();
+    let mut buff = [0; 4096];
+    loop {
+        let (frames, consumed) = utils::tokenize_to_packets(&pending)?;
+        let mut offset = 0;
+        for frame in frames {
+            offset += frame.len();
+            let packet = utils::decode_frame(frame, compression)?;
+            match server::LoginPacketId::from_id(packet.id) {
+                server::LoginPacketId::Disconnect => {
+                    let reason = LoginDisconnectPacket::parse(&packet.data).map_or(String::new(), |packet| text::plain(&packet.reason));
+                    return Err(io::Error::new(ErrorKind::ConnectionRefused, format!("the server refused the login: {}", reason)));
+                },
+                server::LoginPacketId::EncryptionRequest => return Err(io::Error::new(ErrorKind::Unsupported, "the server is in online mode")),
+                server::LoginPacketId::SetCompression => {
+                    compression = usize::try_from(SetCompressionPacket::parse(&packet.data)?.threshold).ok();
+                },
+                server::LoginPacketId::LoginSuccess => {
+                    let success = LoginSuccessPacket::parse(&packet.data)?;
+                    stream.set_read_timeout(None)?;
+                    return Ok(Backend {
+                        address: address.to_string(),
+                        compression,
+                        profile: (success.uuid, success.username),
+                        pending: pending[offset..].to_vec(),
+                        stream,
+                    });
+                },
+                server::LoginPacketId::Unknonwn => {
+                    let name = packet_name(State::Login, Direction::Clientbound, packet.id);
+                    return Err(io::Error::new(ErrorKind::Unsupported, format!("the server sent {} during login", name)));
+                }
+            }
+        }
+        pending.drain(..consumed);
+        let bytes_read = match stream.read(&mut buff) {
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Err(io::Error::new(ErrorKind::TimedOut, "the login timed out")),
+            result => result?
+        };
+        if bytes_read == 0 {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "the server closed the connection during login"));
+        }
+        pending.extend_from_slice(&buff[..bytes_read]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::impair::{Impairment, Link, LinkImpairment};
+    use crate::packets::PacketWrite;
+    use crate::text::Component;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use tracing::Span;
+
+    const NAME: &str = "lobby";
+
+    fn set_compression(threshold: i32) -> Vec<u8> {
+        let mut body = Vec::new();
+        utils::write_var_int(&mut body, 0x03);
+        utils::write_var_int(&mut body, threshold);
+        utils::encode_frame(&body, None)
+    }
+
+    fn login_success(compression: Option<usize>) -> Vec<u8> {
+        let mut body = vec![0x02];
+        body.extend_from_slice(Uuid::from_u128(42).as_bytes());
+        utils::write_string(&mut body, "Alex");
+        utils::write_var_int(&mut body, 0);
+        utils::encode_frame(&body, compression)
+    }
+
+    fn session() -> Arc<Session> {
+        let session = Arc::new(Session::new(1, "127.0.0.1:1".parse().unwrap(), Impairment::default()));
+        session.set_greeting(b"greeting".to_vec());
+        session.set_compression(Some(64));
+        session
+    }
+
+    /// A server that answers the login with `replies` and hands its end of the connection back.
+    fn server(replies: Vec<u8>) -> (String, thread::JoinHandle<TcpStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut greeting = [0; 8];
+            stream.read_exact(&mut greeting).unwrap();
+            assert_eq!(&greeting, b"greeting");
+            stream.write_all(&replies).unwrap();
+            stream
+        });
+        (address, handle)
+    }
+
+    /// Starts a move and waits for its outcome.
+    fn switch_to(session: &Arc<Session>, address: &str) -> io::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        switch(session.clone(), NAME, address, move |_, result| tx.send(result).unwrap());
+        rx.recv().unwrap()
+    }
+
+    /// Connects the session to a stand-in for its current server and returns the server's end.
+    fn connect(session: &Session) -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        session.set_server(TcpStream::connect(listener.local_addr().unwrap()).unwrap());
+        listener.accept().unwrap().0
+    }
+
+    /// Attaches a serverbound link to the current server.
+    fn attach(session: &Session) -> (Link, TcpStream) {
+        let old = connect(session);
+        let link = Link::spawn(session.server().unwrap(), Span::none());
+        session.attach(Direction::Serverbound, link.injector());
+        (link, old)
+    }
+
+    #[test]
+    fn refused_logins_leave_the_player_in_place() {
+        let session = session();
+        let reason = Component::text("Full").to_json();
+        let (address, refusing) = server(utils::encode_frame(&LoginDisconnectPacket { reason }.as_bytes(), None));
+        let error = switch_to(&session, &address).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ConnectionRefused);
+        assert!(error.to_string().ends_with("Full"));
+        assert!(session.switch().moving_to().is_none());
+        assert_eq!(session.upstream_compression(), Some(64));
+        refusing.join().unwrap();
+
+        // Encryption Request: online mode.
+        let (address, online) = server(vec![0x02, 0x01, 0x00]);
+        assert_eq!(switch_to(&session, &address).unwrap_err().kind(), ErrorKind::Unsupported);
+        online.join().unwrap();
+    }
+
+    #[test]
+    fn failed_hand_overs_restore_the_compression() {
+        // Connected, but with no link to redirect.
+        let session = session();
+        let _old = connect(&session);
+        let mut replies = set_compression(256);
+        replies.extend(login_success(Some(256)));
+        let (address, server) = server(replies);
+        assert_eq!(switch_to(&session, &address).unwrap_err().kind(), ErrorKind::NotConnected);
+        let switch = session.switch();
+        assert!(switch.moving_to().is_none() && switch.ready.is_none());
+        assert_eq!(session.upstream_compression(), Some(64));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn logins_do_not_hold_up_the_player() {
+        let session = session();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let (tx, rx) = mpsc::channel();
+        switch(session.clone(), NAME, &listener.local_addr().unwrap().to_string(), move |_, result| tx.send(result).unwrap());
+        // The server has not even accepted, yet the command is back and the move under way.
+        assert_eq!(session.switch().moving_to(), Some(NAME));
+        // Read the greeting first, or the close resets the connection instead of ending it.
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.read_exact(&mut [0; 8]).unwrap();
+        drop(stream);
+        assert_eq!(rx.recv().unwrap().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert!(session.switch().moving_to().is_none());
+    }
+
+    #[test]
+    fn ready_servers_take_over_between_frames() {
+        let session = session();
+        let (mut link, mut old) = attach(&session);
+        session.keep_alive_sent(7);
+        let mut replies = set_compression(256);
+        replies.extend(login_success(Some(256)));
+        replies.extend(b"play");
+        let (address, server) = server(replies);
+        switch_to(&session, &address).unwrap();
+        assert_eq!(session.upstream_compression(), Some(256));
+        assert_eq!(session.compression(), Some(64));
+        {
+            let switch = session.switch();
+            assert_eq!((switch.connecting.as_deref(), switch.target.as_deref()), (None, Some(NAME)));
+            assert_eq!(switch.stale_keep_alives, [7]);
+            let backend = switch.ready.as_ref().unwrap();
+            assert_eq!(backend.profile, (Uuid::from_u128(42), "Alex".to_string()));
+            assert_eq!((backend.compression, &backend.pending[..]), (Some(256), &b"play"[..]));
+        }
+
+        link.send(b"next".to_vec(), false, &LinkImpairment::default()).unwrap();
+        let mut new = server.join().unwrap();
+        let mut next = [0; 4];
+        new.read_exact(&mut next).unwrap();
+        assert_eq!(&next, b"next");
+        assert_eq!(old.read(&mut next).unwrap(), 0);
+    }
+}
@@ -0,0 +1,762 @@
+//! Text components: the styled text used for chat, disconnect reasons, titles and the
+//! server list MOTD. They come as JSON or NBT and may still carry legacy `§` codes.
+
+use crate::nbt::{Compound, Tag};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+use std::sync::{LazyLock, RwLock};
+
+/// English translations for the keys servers send most: chat, joins, disconnects, deaths
+/// and common command feedback. `load_language` adds the rest from a full `en_us.json`.
+const BUNDLED_LANGUAGE: &str = include_str!("../assets/lang/en_us.json");
+
+/// How deep components may nest; deeper ones are refused rather than overflowing the stack.
+const MAX_DEPTH: usize = 32;
+
+/// Marks a legacy formatting code.
+const SECTION: char = '§';
+
+/// The 16 named colors: JSON name, legacy code, RGB and ANSI foreground code.
+const COLORS: [(&str, char, u32, u8); 16] = [
+    ("black", '0', 0x000000, 30),
+    ("dark_blue", '1', 0x0000aa, 34),
+    ("dark_green", '2', 0x00aa00, 32),
+    ("dark_aqua", '3', 0x00aaaa, 36),
+    ("dark_red", '4', 0xaa0000, 31),
+    ("dark_purple", '5', 0xaa00aa, 35),
+    ("gold", '6', 0xffaa00, 33),
+    ("gray", '7', 0xaaaaaa, 37),
+    ("dark_gray", '8', 0x555555, 90),
+    ("blue", '9', 0x5555ff, 94),
+    ("green", 'a', 0x55ff55, 92),
+    ("aqua", 'b', 0x55ffff, 96),
+    ("red", 'c', 0xff5555, 91),
+    ("light_purple", 'd', 0xff55ff, 95),
+    ("yellow", 'e', 0xffff55, 93),
+    ("white", 'f', 0xffffff, 97),
+];
+
+static LANGUAGE: LazyLock<RwLock<HashMap<String, String>>> =
+    LazyLock::new(|| RwLock::new(serde_json::from_str(BUNDLED_LANGUAGE).expect("bundled language table is valid")));
+
+/// Adds the translations in a Minecraft language file, such as `en_us.json` from the
+/// client's assets, to the table. Returns how many it read.
+pub fn load_language(path: &Path) -> io::Result<usize> {
+    let translations: HashMap<String, String> = serde_json::from_slice(&fs::read(path)?)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let count = translations.len();
+    LANGUAGE.write().unwrap().extend(translations);
+    Ok(count)
+}
+
+/// The English text for a translation key, if the table has it.
+pub fn translate(key: &str) -> Option<String> {
+    LANGUAGE.read().unwrap().get(key).cloned()
+}
+
+/// The plain text of a JSON text component; text that is not one is returned as it is.
+pub fn plain(json: &str) -> String {
+    Component::from_json(json).map_or_else(|_| json.to_string(), |component| component.to_plain())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    /// Index into the 16 named colors.
+    Named(u8),
+    Rgb(u32),
+}
+
+impl Color {
+    /// A color name or `#rrggbb`.
+    pub fn parse(value: &str) -> Option<Color> {
+        if let Some(hex) = value.strip_prefix('#') {
+            return u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6).map(Color::Rgb);
+        }
+        COLORS.iter().position(|(name, ..)| *name == value).map(|index| Color::Named(index as u8))
+    }
+
+    fn from_legacy(code: char) -> Option<Color> {
+        COLORS.iter().position(|(_, legacy, ..)| *legacy == code).map(|index| Color::Named(index as u8))
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Color::Named(index) => COLORS[index as usize].0.to_string(),
+            Color::Rgb(rgb) => format!("#{:06x}", rgb),
+        }
+    }
+
+    pub fn rgb(self) -> u32 {
+        match self {
+            Color::Named(index) => COLORS[index as usize].2,
+            Color::Rgb(rgb) => rgb,
+        }
+    }
+}
+
+/// Formatting of a component. Unset fields are inherited from the parent component.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Style {
+    pub color: Option<Color>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underlined: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub obfuscated: Option<bool>,
+    pub font: Option<String>,
+    /// Text put into the chat box when the component is shift-clicked.
+    pub insertion: Option<String>,
+    /// Kept as sent, for serializing; renderers ignore them.
+    pub click_event: Option<Value>,
+    pub hover_event: Option<Value>,
+}
+
+impl Style {
+    /// This style with the fields it leaves unset taken from `parent`.
+    pub fn inherit(&self, parent: &Style) -> Style {
+        Style {
+            color: self.color.or(parent.color),
+            bold: self.bold.or(parent.bold),
+            italic: self.italic.or(parent.italic),
+            underlined: self.underlined.or(parent.underlined),
+            strikethrough: self.strikethrough.or(parent.strikethrough),
+            obfuscated: self.obfuscated.or(parent.obfuscated),
+            font: self.font.clone().or_else(|| parent.font.clone()),
+            insertion: self.insertion.clone().or_else(|| parent.insertion.clone()),
+            click_event: self.click_event.clone().or_else(|| parent.click_event.clone()),
+            hover_event: self.hover_event.clone().or_else(|| parent.hover_event.clone()),
+        }
+    }
+
+    /// Applies a legacy code. A color also turns off the formatting codes before it, as
+    /// in vanilla; `§r` goes back to `reset`.
+    fn apply_legacy(&mut self, code: &Legacy, reset: &Style) {
+        match code {
+            Legacy::Color(color) => *self = Style {
+                color: Some(*color),
+                bold: reset.bold.and(Some(false)),
+                italic: reset.italic.and(Some(false)),
+                underlined: reset.underlined.and(Some(false)),
+                strikethrough: reset.strikethrough.and(Some(false)),
+                obfuscated: reset.obfuscated.and(Some(false)),
+                ..reset.clone()
+            },
+            Legacy::Format('k') => self.obfuscated = Some(true),
+            Legacy::Format('l') => self.bold = Some(true),
+            Legacy::Format('m') => self.strikethrough = Some(true),
+            Legacy::Format('n') => self.underlined = Some(true),
+            Legacy::Format(_) => self.italic = Some(true),
+            Legacy::Reset => *self = reset.clone(),
+        }
+    }
+
+    fn flags(&self) -> [(bool, &'static str, char, u8); 5] {
+        [(self.bold == Some(true), "bold", 'l', 1),
+         (self.italic == Some(true), "italic", 'o', 3),
+         (self.underlined == Some(true), "underlined", 'n', 4),
+         (self.strikethrough == Some(true), "strikethrough", 'm', 9),
+         (self.obfuscated == Some(true), "obfuscated", 'k', 0)]
+    }
+}
+
+/// What a component shows, before its children.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Content {
+    /// Literal text. Legacy `§` codes in it are honoured when rendering.
+    Text(String),
+    /// A translation key, filled in with `with`; `fallback` is shown for unknown keys.
+    Translate { key: String, with: Vec<Component>, fallback: Option<String> },
+    /// The key bound to a control, such as `key.jump`.
+    Keybind(String),
+    /// A scoreboard value; servers fill in `value` before sending.
+    Score { name: String, objective: String, value: Option<String> },
+    /// An entity selector, normally resolved by the server.
+    Selector(String),
+    /// NBT content, resolved by the server; its fields are kept as sent.
+    Nbt(Map<String, Value>),
+}
+
+/// A text component: content, style and children that inherit the style.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Component {
+    pub content: Content,
+    pub style: Style,
+    pub extra: Vec<Component>,
+}
+
+/// A run of text with the style it is shown in, every field inherited and resolved.
+struct Span {
+    text: String,
+    style: Style,
+}
+
+impl Component {
+    /// Plain, unstyled text.
+    pub fn text(text: impl Into<String>) -> Component {
+        Component { content: Content::Text(text.into()), style: Style::default(), extra: Vec::new() }
+    }
+
+    pub fn translate(key: impl Into<String>, with: Vec<Component>) -> Component {
+        Component { content: Content::Translate { key: key.into(), with, fallback: None }, style: Style::default(), extra: Vec::new() }
+    }
+
+    pub fn with_color(mut self, color: Color) -> Component {
+        self.style.color = Some(color);
+        self
+    }
+
+    /// Parses a JSON text component: an object, a string or an array.
+    pub fn from_json(json: &str) -> io::Result<Component> {
+        let value: Value = serde_json::from_str(json).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Component::from_value(&value)
+    }
+
+    pub fn from_value(value: &Value) -> io::Result<Component> {
+        parse(value, 0)
+    }
+
+    /// Parses an NBT text component, as sent from 1.20.3 on.
+    pub fn from_nbt(tag: &Tag) -> io::Result<Component> {
+        parse(&nbt_to_json(tag), 0)
+    }
+
+    /// Text with legacy `§` codes, as in old server list MOTDs and plugin messages.
+    pub fn from_legacy(text: &str) -> Component {
+        let mut root = Component::text("");
+        let mut style = Style::default();
+        for (run, code) in split_legacy(text) {
+            if !run.is_empty() {
+                root.extra.push(Component { content: Content::Text(run), style: style.clone(), extra: Vec::new() });
+            }
+            if let Some(code) = code {
+                style.apply_legacy(&code, &Style::default());
+            }
+        }
+        match root.extra.len() {
+            0 => root,
+            1 if root.extra[0].style == Style::default() => root.extra.remove(0),
+            _ => root,
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut object = Map::new();
+        match &self.content {
+            Content::Text(text) => {
+                object.insert("text".into(), Value::from(text.as_str()));
+            }
+            Content::Translate { key, with, fallback } => {
+                object.insert("translate".into(), Value::from(key.as_str()));
+                if let Some(fallback) = fallback {
+                    object.insert("fallback".into(), Value::from(fallback.as_str()));
+                }
+                if !with.is_empty() {
+                    object.insert("with".into(), Value::Array(with.iter().map(Component::to_value).collect()));
+                }
+            }
+            Content::Keybind(key) => {
+                object.insert("keybind".into(), Value::from(key.as_str()));
+            }
+            Content::Score { name, objective, value } => {
+                let mut score = Map::new();
+                score.insert("name".into(), Value::from(name.as_str()));
+                score.insert("objective".into(), Value::from(objective.as_str()));
+                if let Some(value) = value {
+                    score.insert("value".into(), Value::from(value.as_str()));
+                }
+                object.insert("score".into(), Value::Object(score));
+            }
+            Content::Selector(selector) => {
+                object.insert("selector".into(), Value::from(selector.as_str()));
+            }
+            Content::Nbt(fields) => object.extend(fields.clone()),
+        }
+        let style = &self.style;
+        if let Some(color) = style.color {
+            object.insert("color".into(), Value::from(color.name()));
+        }
+        for (value, name) in [(style.bold, "bold"), (style.italic, "italic"), (style.underlined, "underlined"),
+                              (style.strikethrough, "strikethrough"), (style.obfuscated, "obfuscated")] {
+            if let Some(value) = value {
+                object.insert(name.into(), Value::from(value));
+            }
+        }
+        for (value, name) in [(&style.font, "font"), (&style.insertion, "insertion")] {
+            if let Some(value) = value {
+                object.insert(name.into(), Value::from(value.as_str()));
+            }
+        }
+        for (value, name) in [(&style.click_event, "clickEvent"), (&style.hover_event, "hoverEvent")] {
+            if let Some(value) = value {
+                object.insert(name.into(), value.clone());
+            }
+        }
+        if !self.extra.is_empty() {
+            object.insert("extra".into(), Value::Array(self.extra.iter().map(Component::to_value).collect()));
+        }
+        Value::Object(object)
+    }
+
+    pub fn to_json(&self) -> String {
+        self.to_value().to_string()
+    }
+
+    /// The text with legacy `§` codes for its colors and formatting. Colors other than the
+    /// 16 named ones use the `§x§r§r§g§g§b§b` form BungeeCord understands.
+    pub fn to_legacy(&self) -> String {
+        let mut out = String::new();
+        let mut current = Style::default();
+        for span in self.spans() {
+            if span.style.color != current.color || span.style.flags() != current.flags() {
+                match span.style.color {
+                    Some(Color::Named(index)) => out.extend([SECTION, COLORS[index as usize].1]),
+                    Some(Color::Rgb(rgb)) => {
+                        out.extend([SECTION, 'x']);
+                        for digit in format!("{:06x}", rgb).chars() {
+                            out.extend([SECTION, digit]);
+                        }
+                    }
+                    None => out.extend([SECTION, 'r']),
+                }
+                for (set, _, code, _) in span.style.flags() {
+                    if set {
+                        out.extend([SECTION, code]);
+                    }
+                }
+                current = span.style;
+            }
+            out.push_str(&span.text);
+        }
+        out
+    }
+
+    /// The text as the player reads it, without styling.
+    pub fn to_plain(&self) -> String {
+        self.spans().into_iter().map(|span| span.text).collect()
+    }
+
+    /// The text with ANSI escapes for colors and formatting, for terminals. Control
+    /// characters in the text itself are dropped so a server cannot send its own escapes.
+    pub fn to_ansi(&self) -> String {
+        let mut out = String::new();
+        for mut span in self.spans() {
+            span.text.retain(|c| c == '\n' || !c.is_control());
+            let mut codes: Vec<String> = span.style.flags().iter()
+                .filter(|(set, .., ansi)| *set && *ansi != 0)
+                .map(|(.., ansi)| ansi.to_string())
+                .collect();
+            match span.style.color {
+                Some(Color::Named(index)) => codes.push(COLORS[index as usize].3.to_string()),
+                Some(Color::Rgb(rgb)) => codes.push(format!("38;2;{};{};{}", rgb >> 16, (rgb >> 8) & 0xff, rgb & 0xff)),
+                None => (),
+            }
+            if codes.is_empty() {
+                out.push_str(&span.text);
+            } else {
+                let _ = write!(out, "\x1b[{}m{}\x1b[0m", codes.join(";"), span.text);
+            }
+        }
+        out
+    }
+
+    /// The text as HTML, each styled run in a `<span>` with inline CSS.
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        for span in self.spans() {
+            let text = escape_html(&span.text).replace('\n', "<br>");
+            let mut css = Vec::new();
+            if let Some(color) = span.style.color {
+                css.push(format!("color:#{:06x}", color.rgb()));
+            }
+            if span.style.bold == Some(true) {
+                css.push("font-weight:bold".to_string());
+            }
+            if span.style.italic == Some(true) {
+                css.push("font-style:italic".to_string());
+            }
+            let decorations: Vec<&str> = [(span.style.underlined, "underline"), (span.style.strikethrough, "line-through")].iter()
+                .filter(|(set, _)| *set == Some(true))
+                .map(|(_, decoration)| *decoration)
+                .collect();
+            if !decorations.is_empty() {
+                css.push(format!("text-decoration:{}", decorations.join(" ")));
+            }
+            if css.is_empty() {
+                out.push_str(&text);
+            } else {
+                let _ = write!(out, "<span style=\"{}\">{}</span>", css.join(";"), text);
+            }
+        }
+        out
+    }
+
+    /// The component flattened into runs of text, translated and with legacy codes applied.
+    /// Neighbouring runs in the same style are merged.
+    fn spans(&self) -> Vec<Span> {
+        let mut spans: Vec<Span> = Vec::new();
+        self.flatten(&Style::default(), 0, &mut |text, style| {
+            if text.is_empty() {
+                return;
+            }
+            match spans.last_mut() {
+                Some(last) if last.style == *style => last.text.push_str(text),
+                _ => spans.push(Span { text: text.to_string(), style: style.clone() }),
+            }
+        });
+        spans
+    }
+
+    fn flatten(&self, parent: &Style, depth: usize, emit: &mut dyn FnMut(&str, &Style)) {
+        if depth > MAX_DEPTH {
+            return;
+        }
+        let style = self.style.inherit(parent);
+        match &self.content {
+            Content::Text(text) => emit_legacy(text, &style, emit),
+            Content::Translate { key, with, fallback } => {
+                let template = translate(key).or_else(|| fallback.clone()).unwrap_or_else(|| key.clone());
+                let mut next_argument = 0;
+                let mut literal = String::new();
+                let mut chars = template.chars().peekable();
+                while let Some(c) = chars.next() {
+                    if c != '%' {
+                        literal.push(c);
+                        continue;
+                    }
+                    // %s takes the next argument, %2$s a numbered one and %% is a percent sign.
+                    let mut digits = String::new();
+                    while let Some(digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                        digits.push(*digit);
+                        chars.next();
+                    }
+                    let index = match (digits.is_empty(), chars.peek()) {
+                        (true, Some('%')) => {
+                            chars.next();
+                            literal.push('%');
+                            continue;
+                        }
+                        (true, Some('s')) => {
+                            next_argument += 1;
+                            next_argument - 1
+                        }
+                        (false, Some('$')) => {
+                            chars.next();
+                            digits.parse::<usize>().unwrap_or(0).wrapping_sub(1)
+                        }
+                        _ => {
+                            literal.push('%');
+                            literal.push_str(&digits);
+                            continue;
+                        }
+                    };
+                    if chars.next_if_eq(&'s').is_none() && digits.is_empty() {
+                        continue;
+                    }
+                    emit_legacy(&std::mem::take(&mut literal), &style, emit);
+                    if let Some(argument) = with.get(index) {
+                        argument.flatten(&style, depth + 1, emit);
+                    }
+                }
+                emit_legacy(&literal, &style, emit);
+            }
+            Content::Keybind(key) => emit(&translate(key).unwrap_or_else(|| key.clone()), &style),
+            Content::Score { value, .. } => emit(value.as_deref().unwrap_or(""), &style),
+            Content::Selector(selector) => emit(selector, &style),
+            Content::Nbt(_) => (),
+        }
+        for child in &self.extra {
+            child.flatten(&style, depth + 1, emit);
+        }
+    }
+}
+
+/// Emits `text`, switching style at each legacy code. `§r` goes back to `style`.
+fn emit_legacy(text: &str, style: &Style, emit: &mut dyn FnMut(&str, &Style)) {
+    if !text.contains(SECTION) {
+        return emit(text, style);
+    }
+    let mut current = style.clone();
+    for (run, code) in split_legacy(text) {
+        emit(&run, &current);
+        if let Some(code) = code {
+            current.apply_legacy(&code, style);
+        }
+    }
+}
+
+/// A legacy formatting code.
+enum Legacy {
+    /// `§0` to `§f`, or `§x` followed by six `§`-prefixed hex digits.
+    Color(Color),
+    /// `§k` to `§o`.
+    Format(char),
+    /// `§r`.
+    Reset,
+}
+
+/// Splits text at its legacy codes: each run of text, followed by the code that ends it.
+/// A `§` that does not start a code is kept as text.
+fn split_legacy(text: &str) -> Vec<(String, Option<Legacy>)> {
+    let mut parts = Vec::new();
+    let mut run = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        if c != SECTION {
+            run.push(c);
+            continue;
+        }
+        let code = rest.chars().next().map(|code| code.to_ascii_lowercase());
+        let (legacy, length) = match code {
+            Some('x') => match legacy_hex(&rest[1..]) {
+                Some((rgb, length)) => (Legacy::Color(Color::Rgb(rgb)), 1 + length),
+                None => {
+                    run.push(c);
+                    continue;
+                }
+            },
+            Some(code @ ('k'..='o')) => (Legacy::Format(code), 1),
+            Some('r') => (Legacy::Reset, 1),
+            Some(code) => match Color::from_legacy(code) {
+                Some(color) => (Legacy::Color(color), 1),
+                None => {
+                    run.push(c);
+                    continue;
+                }
+            },
+            None => {
+                run.push(c);
+                continue;
+            }
+        };
+        rest = &rest[length..];
+        parts.push((std::mem::take(&mut run), Some(legacy)));
+    }
+    parts.push((run, None));
+    parts
+}
+
+/// The color of the `§r§r§g§g§b§b` that follows `§x`, and its length in bytes.
+fn legacy_hex(text: &str) -> Option<(u32, usize)> {
+    let mut chars = text.chars();
+    let mut rgb = 0;
+    for _ in 0..6 {
+        if chars.next() != Some(SECTION) {
+            return None;
+        }
+        rgb = rgb << 4 | chars.next()?.to_digit(16)?;
+    }
+    Some((rgb, 6 * (SECTION.len_utf8() + 1)))
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::new(ErrorKind::InvalidData, message.into())
+}
+
+fn parse(value: &Value, depth: usize) -> io::Result<Component> {
+    if depth > MAX_DEPTH {
+        return Err(invalid("text component nested too deep"));
+    }
+    let object = match value {
+        Value::String(text) => return Ok(Component::text(text.as_str())),
+        Value::Number(number) => return Ok(Component::text(number.to_string())),
+        Value::Bool(flag) => return Ok(Component::text(flag.to_string())),
+        Value::Null => return Err(invalid("text component is null")),
+        // The first element is the parent of the others.
+        Value::Array(parts) => {
+            let (first, rest) = parts.split_first().ok_or_else(|| invalid("text component is an empty array"))?;
+            let mut component = parse(first, depth + 1)?;
+            for part in rest {
+                component.extra.push(parse(part, depth + 1)?);
+            }
+            return Ok(component);
+        }
+        Value::Object(object) => object,
+    };
+    let string = |name: &str| object.get(name).and_then(Value::as_str).map(str::to_string);
+    let content = if let Some(text) = object.get("text") {
+        Content::Text(text.as_str().map_or_else(|| text.to_string(), str::to_string))
+    } else if let Some(key) = string("translate") {
+        let with = match object.get("with") {
+            Some(Value::Array(arguments)) => arguments.iter().map(|argument| parse(argument, depth + 1)).collect::<io::Result<_>>()?,
+            _ => Vec::new(),
+        };
+        Content::Translate { key, with, fallback: string("fallback") }
+    } else if let Some(key) = string("keybind") {
+        Content::Keybind(key)
+    } else if let Some(Value::Object(score)) = object.get("score") {
+        let field = |name: &str| score.get(name).and_then(Value::as_str).map(str::to_string);
+        Content::Score { name: field("name").unwrap_or_default(), objective: field("objective").unwrap_or_default(), value: field("value") }
+    } else if let Some(selector) = string("selector") {
+        Content::Selector(selector)
+    } else if object.contains_key("nbt") {
+        let fields = ["nbt", "interpret", "separator", "block", "entity", "storage", "source"];
+        Content::Nbt(object.iter().filter(|(name, _)| fields.contains(&name.as_str())).map(|(name, value)| (name.clone(), value.clone())).collect())
+    } else {
+        // Components with only children or style turn up in the wild; vanilla would refuse them.
+        Content::Text(String::new())
+    };
+    let flag = |name: &str| match object.get(name) {
+        Some(Value::Bool(flag)) => Some(*flag),
+        Some(Value::Number(number)) => Some(number.as_f64() != Some(0.0)),
+        _ => None,
+    };
+    let style = Style {
+        color: string("color").and_then(|color| Color::parse(&color)),
+        bold: flag("bold"),
+        italic: flag("italic"),
+        underlined: flag("underlined"),
+        strikethrough: flag("strikethrough"),
+        obfuscated: flag("obfuscated"),
+        font: string("font"),
+        insertion: string("insertion"),
+        click_event: object.get("clickEvent").or_else(|| object.get("click_event")).cloned(),
+        hover_event: object.get("hoverEvent").or_else(|| object.get("hover_event")).cloned(),
+    };
+    let extra = match object.get("extra") {
+        Some(Value::Array(children)) => children.iter().map(|child| parse(child, depth + 1)).collect::<io::Result<_>>()?,
+        _ => Vec::new(),
+    };
+    Ok(Component { content, style, extra })
+}
+
+/// NBT as the JSON it stands for; bytes stay numbers, which `parse` reads as booleans where one is expected.
+fn nbt_to_json(tag: &Tag) -> Value {
+    match tag {
+        Tag::Byte(value) => Value::from(*value),
+        Tag::Short(value) => Value::from(*value),
+        Tag::Int(value) => Value::from(*value),
+        Tag::Long(value) => Value::from(*value),
+        Tag::Float(value) => Value::from(*value),
+        Tag::Double(value) => Value::from(*value),
+        Tag::ByteArray(values) => Value::from(values.clone()),
+        Tag::String(value) => Value::from(value.as_str()),
+        Tag::List(list) => Value::Array(list.items.iter().map(nbt_to_json).collect()),
+        Tag::Compound(compound) => Value::Object(compound.0.iter().map(|(name, tag)| (name.clone(), nbt_to_json(tag))).collect()),
+        Tag::IntArray(values) => Value::from(values.clone()),
+        Tag::LongArray(values) => Value::from(values.clone()),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// How a `minecraft:chat_type` from the Login registry codec lays out Player Chat.
+#[derive(Clone, Debug)]
+pub struct ChatType {
+    pub translation_key: String,
+    /// Which of `sender`, `target` and `content` fill the key's placeholders, in order.
+    pub parameters: Vec<String>,
+    pub style: Style,
+}
+
+impl Default for ChatType {
+    /// Plain chat, `<sender> content`.
+    fn default() -> ChatType {
+        ChatType { translation_key: "chat.type.text".to_string(), parameters: vec!["sender".to_string(), "content".to_string()], style: Style::default() }
+    }
+}
+
+impl ChatType {
+    /// Reads the `chat` decoration of a registry element; `None` for types without one.
+    pub fn from_element(element: &Compound) -> Option<ChatType> {
+        let Some(Tag::Compound(chat)) = element.get("chat") else { return None };
+        let Some(Tag::String(translation_key)) = chat.get("translation_key") else { return None };
+        let parameters = match chat.get("parameters") {
+            Some(Tag::List(list)) => list.items.iter().filter_map(|item| match item {
+                Tag::String(parameter) => Some(parameter.clone()),
+                _ => None,
+            }).collect(),
+            _ => Vec::new(),
+        };
+        let style = chat.get("style")
+            .and_then(|style| Component::from_nbt(style).ok())
+            .map_or_else(Style::default, |component| component.style);
+        Some(ChatType { translation_key: translation_key.clone(), parameters, style })
+    }
+
+    /// The message as the client shows it.
+    pub fn decorate(&self, sender: Component, target: Option<Component>, content: Component) -> Component {
+        let with = self.parameters.iter()
+            .map(|parameter| match parameter.as_str() {
+                "sender" => sender.clone(),
+                "target" => target.clone().unwrap_or_else(|| Component::text("")),
+                _ => content.clone(),
+            })
+            .collect();
+        let mut decorated = Component::translate(self.translation_key.clone(), with);
+        decorated.style = self.style.clone();
+        decorated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_codes_render_in_every_form() {
+        let component = Component::from_legacy("§cRed §lbold§r plain");
+        assert_eq!(component.to_plain(), "Red bold plain");
+        assert_eq!(component.to_legacy(), "§cRed §c§lbold§r plain");
+        assert_eq!(component.to_ansi(), "\x1b[91mRed \x1b[0m\x1b[1;91mbold\x1b[0m plain");
+        assert_eq!(component.to_html(), "<span style=\"color:#ff5555\">Red </span><span style=\"color:#ff5555;font-weight:bold\">bold</span> plain");
+        assert_eq!(Component::from_json(&component.to_json()).unwrap(), component);
+    }
+
+    #[test]
+    fn legacy_colors_end_formatting() {
+        let component = Component::from_legacy("§lA§aB");
+        assert_eq!(component.extra[1].style.bold, None);
+        assert_eq!(component.to_legacy(), "§r§lA§aB");
+
+        let hex = Component::text("§x§1§2§3§4§5§6hi");
+        assert_eq!(hex.to_ansi(), "\x1b[38;2;18;52;86mhi\x1b[0m");
+        assert_eq!(hex.to_legacy(), "§x§1§2§3§4§5§6hi");
+        // A `§` that starts no code stays text; `§x` without six digits is not a color.
+        assert_eq!(Component::from_legacy("100§ §z §x§1!").to_plain(), "100§ §z §x!");
+    }
+
+    #[test]
+    fn rendering_escapes_what_the_server_sends() {
+        assert_eq!(Component::text("a\x1b[31mb\nc").to_ansi(), "a[31mb\nc");
+        assert_eq!(Component::text("<b>&\n").with_color(Color::Rgb(0x123456)).to_html(), "<span style=\"color:#123456\">&lt;b&gt;&amp;<br></span>");
+    }
+
+    #[test]
+    fn translations_fill_their_arguments() {
+        let chat = ChatType::default().decorate(Component::text("Alex"), None, Component::text("hi"));
+        assert_eq!(chat.to_plain(), "<Alex> hi");
+
+        let numbered = Component::from_json(r#"{"translate":"proxy.test.unknown","fallback":"%2$s then %1$s, 100%%","with":["a",{"text":"b","color":"green"}],"color":"red"}"#).unwrap();
+        assert_eq!(numbered.to_plain(), "b then a, 100%");
+        assert_eq!(numbered.to_legacy(), "§ab§c then a, 100%");
+        assert_eq!(Component::translate("proxy.test.unknown", Vec::new()).to_plain(), "proxy.test.unknown");
+        assert_eq!(Component::translate("multiplayer.player.joined", Vec::new()).to_plain(), " joined the game");
+    }
+
+    #[test]
+    fn json_parsing() {
+        assert_eq!(plain(r#"["a",{"text":"b","extra":[1,true]}]"#), "ab1true");
+        assert_eq!(plain("not json"), "not json");
+        let nested = format!("{}\"deep\"{}", "[".repeat(MAX_DEPTH + 2), "]".repeat(MAX_DEPTH + 2));
+        assert!(Component::from_json(&nested).is_err());
+        assert!(Component::from_json("[]").is_err());
+    }
+}
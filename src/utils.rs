@@ -1,8 +1,32 @@
-use std::{usize};
-use byteorder::ReadBytesExt;
-use std::io::{Cursor, ErrorKind,Error};
-#[derive(Copy,Clone, Debug)]
+use byteorder::{BigEndian, ReadBytesExt};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{Cursor, ErrorKind, Error, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Largest frame the protocol allows: the length prefix is at most a 3-byte VarInt.
+pub const MAX_FRAME_LEN: usize = 2_097_151;
+/// Largest uncompressed packet a compressed frame may announce.
+pub const MAX_DATA_LEN: usize = 8_388_608;
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+/// Turns on strict validation: every length and string limit from the protocol spec is
+/// enforced. Without it only input that cannot be read at all (negative lengths, data
+/// cut short) and frames or packets over the size limits are errors.
+pub fn set_strict(strict: bool){
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+pub fn strict() -> bool{
+    STRICT.load(Ordering::Relaxed)
+}
+#[derive(Copy,Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum State{
+    Handshake = 0,
     Status = 1,
     Login = 2,
     Play = 3,
@@ -20,14 +44,119 @@ impl State{
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Direction{
+    Serverbound,
+    Clientbound
+}
+
+impl fmt::Display for Direction{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            Direction::Serverbound => f.write_str("serverbound"),
+            Direction::Clientbound => f.write_str("clientbound")
+        }
+    }
+}
+
+/// Context attached to an error raised while reading one field of a packet:
+/// which field was being read and at what byte offset the read started.
+#[derive(Debug)]
+pub struct FieldError{
+    pub field: &'static str,
+    pub offset: u64,
+    pub source: Error
+}
+
+impl fmt::Display for FieldError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        write!(f, "Failed to parse {} reason: {}", self.field, self.source)
+    }
+}
+
+impl std::error::Error for FieldError{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>{
+        Some(&self.source)
+    }
+}
+
+impl FieldError{
+    /// Returns the `FieldError` carried by `e`, if it was produced by `read_field`.
+    pub fn of(e: &Error) -> Option<&FieldError>{
+        e.get_ref().and_then(|inner| inner.downcast_ref::<FieldError>())
+    }
+}
+
+/// Input the protocol rules out, as opposed to input that is merely cut short.
+#[derive(Debug)]
+pub enum ProtocolError{
+    NegativeLength(i32),
+    /// A string or array over its limit: in bytes, or in UTF-16 code units for decoded strings.
+    TooLong{ len: usize, max: usize },
+    FrameTooLarge(usize),
+    BadCompression(String),
+    /// String data that is not valid (modified) UTF-8, with the offset of the first bad byte.
+    InvalidUtf8(usize),
+    BadNbt(String),
+    BadChunk(String),
+    BadMetadata(String)
+}
+
+impl fmt::Display for ProtocolError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self{
+            ProtocolError::NegativeLength(len) => write!(f, "negative length {}", len),
+            ProtocolError::TooLong{ len, max } => write!(f, "length {} over the limit of {}", len, max),
+            ProtocolError::FrameTooLarge(len) => write!(f, "frame of {} bytes over the limit of {}", len, MAX_FRAME_LEN),
+            ProtocolError::BadCompression(reason) => write!(f, "bad compression: {}", reason),
+            ProtocolError::InvalidUtf8(offset) => write!(f, "invalid UTF-8 at byte {}", offset),
+            ProtocolError::BadNbt(reason) => write!(f, "bad NBT: {}", reason),
+            ProtocolError::BadChunk(reason) => write!(f, "bad chunk: {}", reason),
+            ProtocolError::BadMetadata(reason) => write!(f, "bad entity metadata: {}", reason)
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError{}
+
+impl ProtocolError{
+    pub fn into_error(self) -> Error{
+        Error::new(ErrorKind::InvalidData, self)
+    }
+}
+
+/// Turns a length prefix into a size: negative lengths are always an error, lengths
+/// over `max` only in strict mode.
+pub fn check_length(len: i32, max: usize) -> Result<usize, Error>{
+    if strict(){
+        return check_limit(len, max);
+    }
+    usize::try_from(len).map_err(|_| ProtocolError::NegativeLength(len).into_error())
+}
+
+/// Like `check_length`, but lengths over `max` are an error in every mode. For sizes the
+/// proxy allocates before it has the data, such as buffered frames and inflated packets.
+pub fn check_limit(len: i32, max: usize) -> Result<usize, Error>{
+    let len = usize::try_from(len).map_err(|_| ProtocolError::NegativeLength(len).into_error())?;
+    if len > max{
+        return Err(ProtocolError::TooLong{ len, max }.into_error());
+    }
+    Ok(len)
+}
+
+/// Runs `read` on the cursor, tagging any failure with `field` and the offset the cursor was at.
+pub fn read_field<T>(cr: &mut Cursor<&[u8]>,
+                     field: &'static str,
+                     read: impl FnOnce(&mut Cursor<&[u8]>) -> Result<T, Error>) -> Result<T, Error>{
+    let offset = cr.position();
+    read(cr).map_err(|source| Error::new(source.kind(), FieldError{field, offset, source}))
+}
+
 pub fn read_var_int(buff: &mut impl ReadBytesExt)-> Result<i32, std::io::Error>{
     let mut value: i32 = 0;
     let mut pos: i32 = 0;    
     loop{
-        let current_byte = match buff.read_u8(){
-            Ok(value) => value,
-            Err(e) => return Err(e)
-        };
+        let current_byte = buff.read_u8()?;
 
         value |= i32::from(current_byte & 0b0111_1111) << pos;
 
@@ -42,6 +171,25 @@ pub fn read_var_int(buff: &mut impl ReadBytesExt)-> Result<i32, std::io::Error>{
     Ok(value)
 }
 
+pub fn read_var_long(buff: &mut impl ReadBytesExt)-> Result<i64, std::io::Error>{
+    let mut value: i64 = 0;
+    let mut pos: i32 = 0;
+    loop{
+        let current_byte = buff.read_u8()?;
+
+        value |= i64::from(current_byte & 0b0111_1111) << pos;
+
+        if current_byte & 0b1000_0000 == 0 {
+            break;
+        }
+
+        pos += 7;
+
+        if pos >= 64 {return Err(Error::new(ErrorKind::InvalidData, "Number too big"));}
+    }
+    Ok(value)
+}
+
 pub fn read_var_int_long(buff: &[u8]) -> Option<(i64, &[u8])>{
     let mut value: i64 = 0;
     let mut pos: i32 = 0;
@@ -70,53 +218,404 @@ pub fn read_var_int_long(buff: &[u8]) -> Option<(i64, &[u8])>{
     Some((value, &buff[it..]))
 }
 
+pub fn write_var_int(out: &mut Vec<u8>, value: i32){
+    let mut value = value as u32;
+    loop{
+        if value & !0x7f == 0{
+            out.push(value as u8);
+            return;
+        }
+        out.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+/// A block position, packed into a long on the wire: x and z take 26 bits, y 12.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Position{
+    pub x: i32,
+    pub y: i32,
+    pub z: i32
+}
+
+impl Position{
+    pub fn unpack(value: i64) -> Position{
+        Position{
+            x: (value >> 38) as i32,
+            y: (value << 52 >> 52) as i32,
+            z: (value << 26 >> 38) as i32
+        }
+    }
+
+    pub fn pack(&self) -> i64{
+        ((self.x as i64 & 0x3ff_ffff) << 38) | ((self.z as i64 & 0x3ff_ffff) << 12) | (self.y as i64 & 0xfff)
+    }
+
+    pub fn read(buff: &mut impl ReadBytesExt) -> Result<Position, std::io::Error>{
+        Ok(Position::unpack(buff.read_i64::<BigEndian>()?))
+    }
+}
+
+impl fmt::Display for Position{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        write!(f, "{},{},{}", self.x, self.y, self.z)
+    }
+}
+
+/// Reads an Angle, a rotation in 256ths of a full turn, as degrees.
+pub fn read_angle(buff: &mut impl ReadBytesExt) -> Result<f32, std::io::Error>{
+    Ok(buff.read_u8()? as f32 * 360.0 / 256.0)
+}
+
+/// Writes a String: the length in UTF-8 bytes, then the bytes.
+pub fn write_string(out: &mut Vec<u8>, value: &str){
+    write_var_int(out, value.len() as i32);
+    out.extend_from_slice(value.as_bytes());
+}
+
+/// Reads exactly `len` bytes without trusting `len` for the allocation up front.
+pub fn read_bytes(buff: &mut impl Read, len: usize) -> Result<Vec<u8>, std::io::Error>{
+    let mut out = Vec::new();
+    buff.take(len as u64).read_to_end(&mut out)?;
+    if out.len() < len{
+        return Err(Error::new(ErrorKind::UnexpectedEof, format!("expected {} bytes, got {}", len, out.len())));
+    }
+    Ok(out)
+}
+
+/// Reads a String: a VarInt length in bytes, then UTF-8. `max` is the field's limit in
+/// UTF-16 code units, which is how the spec counts; strict mode enforces it along with the
+/// `max * 3` bytes that many units can take. Invalid UTF-8 is always an error.
+pub fn read_string(buff: &mut impl Read, max: usize) -> Result<String, std::io::Error>{
+    let size = check_length(read_var_int(buff)?, max * 3)?;
+    let value = String::from_utf8(read_bytes(buff, size)?)
+        .map_err(|e| ProtocolError::InvalidUtf8(e.utf8_error().valid_up_to()).into_error())?;
+    // Every code unit takes at least a byte, so only longer strings need counting.
+    if strict() && value.len() > max{
+        let units = value.encode_utf16().count();
+        if units > max{
+            return Err(ProtocolError::TooLong{ len: units, max }.into_error());
+        }
+    }
+    Ok(value)
+}
+
 pub fn read_string_255(buff: &mut impl ReadBytesExt) -> Result<String, std::io::Error>{
-    let size = match read_var_int(buff){
-        Ok(val) => val,
-        Err(e) => return Err(e)
-    };
+    read_string(buff, 255)
+}
+
+/// Decodes Java's modified UTF-8, used for NBT strings: U+0000 takes two bytes and characters
+/// outside the BMP are written as two three-byte surrogates. Malformed sequences are an
+/// error; a surrogate without its pair becomes U+FFFD, as Rust strings cannot hold it.
+pub fn decode_modified_utf8(bytes: &[u8]) -> Result<String, std::io::Error>{
+    let mut units: Vec<u16> = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len(){
+        let first = bytes[pos] as u16;
+        let (unit, len) = match first{
+            0x00..=0x7f => (first, 1),
+            0xc0..=0xdf => (first & 0x1f, 2),
+            0xe0..=0xef => (first & 0x0f, 3),
+            _ => return Err(ProtocolError::InvalidUtf8(pos).into_error())
+        };
+        let continuation = bytes.get(pos + 1..pos + len).ok_or_else(|| ProtocolError::InvalidUtf8(pos).into_error())?;
+        let mut unit = unit;
+        for byte in continuation{
+            if byte & 0xc0 != 0x80{
+                return Err(ProtocolError::InvalidUtf8(pos).into_error());
+            }
+            unit = (unit << 6) | (byte & 0x3f) as u16;
+        }
+        units.push(unit);
+        pos += len;
+    }
+    Ok(String::from_utf16_lossy(&units))
+}
 
-    let mut str_buff:String = String::new();
-    
-    for i in 0..size as usize{
-        match buff.read_u8(){
-            Ok(v) => str_buff.push(v as char),
-            Err(e)=> return Err(e)
+pub fn encode_modified_utf8(value: &str) -> Vec<u8>{
+    let mut out = Vec::with_capacity(value.len());
+    for unit in value.encode_utf16(){
+        match unit{
+            0x01..=0x7f => out.push(unit as u8),
+            0x00 | 0x80..=0x7ff => out.extend_from_slice(&[0xc0 | (unit >> 6) as u8, 0x80 | (unit & 0x3f) as u8]),
+            _ => out.extend_from_slice(&[0xe0 | (unit >> 12) as u8, 0x80 | ((unit >> 6) & 0x3f) as u8, 0x80 | (unit & 0x3f) as u8])
         }
     }
+    out
+}
+
+/// Reads an NBT string: an unsigned short length in bytes, then modified UTF-8.
+pub fn read_nbt_string(buff: &mut impl Read) -> Result<String, std::io::Error>{
+    let len = buff.read_u16::<BigEndian>()?;
+    decode_modified_utf8(&read_bytes(buff, len as usize)?)
+}
 
-    Ok(str_buff)
+/// Writes an NBT string. Strings over 65535 bytes of modified UTF-8 do not fit and are cut
+/// at a character boundary, which keeps the two halves of a surrogate pair together.
+pub fn write_nbt_string(out: &mut Vec<u8>, value: &str){
+    let mut bytes = encode_modified_utf8(value);
+    if bytes.len() > u16::MAX as usize{
+        let mut end = u16::MAX as usize;
+        while bytes[end] & 0xc0 == 0x80{
+            end -= 1;
+        }
+        // A high surrogate (ED A0..AF xx) just before the cut lost its low half.
+        if end >= 3 && bytes[end - 3] == 0xed && bytes[end - 2] & 0xf0 == 0xa0{
+            end -= 3;
+        }
+        bytes.truncate(end);
+    }
+    out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    out.extend_from_slice(&bytes);
 }
 
-pub fn tokenize_to_packets(buff: &[u8]) -> Result<Vec<&[u8]>, std::io::Error>{
+/// Splits `buff` into complete length-prefixed frames, prefix included. Returns the frames
+/// and how many bytes of `buff` they cover; a trailing partial frame is left for the next read.
+/// Frames over `MAX_FRAME_LEN` are an error in every mode, so one is never buffered.
+pub fn tokenize_to_packets(buff: &[u8]) -> Result<(Vec<&[u8]>, usize), std::io::Error>{
     let mut result: Vec<&[u8]> = Vec::new();
     let mut cr = Cursor::new(buff);
-    while (cr.position() as usize) < buff.len(){
-        match read_var_int(&mut cr){
-            Ok(size) =>{
-                let curr:usize = cr.position() as usize;
-                let end:usize = curr + size as usize;
-                if (curr + size as usize) > buff.len(){
-                    result.push(&buff);
-                    break;
+    let mut consumed: usize = 0;
+    while consumed < buff.len(){
+        let size = match read_var_int(&mut cr){
+            Ok(size) => check_limit(size, MAX_FRAME_LEN)
+                .map_err(|_| if size < 0 { ProtocolError::NegativeLength(size) } else { ProtocolError::FrameTooLarge(size as usize) }.into_error())?,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e)
+        };
+        let end = match (cr.position() as usize).checked_add(size){
+            Some(end) if end <= buff.len() => end,
+            _ => break
+        };
+        result.push(&buff[consumed..end]);
+        consumed = end;
+        cr.set_position(end as u64);
+    }
+    Ok((result, consumed))
+}
+
+/// A frame with its length prefix and compression header stripped.
+pub struct RawPacket<'a>{
+    pub id: i32,
+    pub data: Cow<'a, [u8]>,
+    /// Size on the wire, length prefix included.
+    pub wire_len: usize,
+    /// Size of id + data after decompression.
+    pub uncompressed_len: usize
+}
+
+/// Decodes one frame from `tokenize_to_packets`. `compression` is the threshold announced by
+/// Set Compression; once set every frame carries a data length and may be zlib-compressed.
+/// Data lengths over `MAX_DATA_LEN` are always an error, as that much is inflated; strict
+/// mode also checks the data length against the threshold and what decompresses.
+pub fn decode_frame(frame: &[u8], compression: Option<usize>) -> Result<RawPacket<'_>, std::io::Error>{
+    let mut cr = Cursor::new(frame);
+    read_var_int(&mut cr)?;
+    let body: Cow<[u8]> = match compression{
+        None => Cow::Borrowed(&frame[cr.position() as usize..]),
+        Some(threshold) => {
+            let data_len = check_limit(read_var_int(&mut cr)?, MAX_DATA_LEN)?;
+            let rest = &frame[cr.position() as usize..];
+            if data_len == 0{
+                Cow::Borrowed(rest)
+            }else{
+                if strict() && data_len < threshold{
+                    let reason = format!("{} byte packet compressed below the threshold of {}", data_len, threshold);
+                    return Err(ProtocolError::BadCompression(reason).into_error());
                 }
-                if (size as usize) < buff.len() {
-                    result.push(&buff[curr..end]);
-                    cr.set_position(end as u64);
+                let mut out = Vec::new();
+                ZlibDecoder::new(rest).take(data_len as u64).read_to_end(&mut out)?;
+                if strict() && out.len() != data_len{
+                    let reason = format!("announced {} bytes, decompressed to {}", data_len, out.len());
+                    return Err(ProtocolError::BadCompression(reason).into_error());
                 }
-            },
-            Err(e) => return Err(e)
+                Cow::Owned(out)
+            }
+        }
+    };
+    let uncompressed_len = body.len();
+    let mut cr = Cursor::new(&body[..]);
+    let id = read_var_int(&mut cr)?;
+    let start = cr.position() as usize;
+    let data = match body{
+        Cow::Borrowed(body) => Cow::Borrowed(&body[start..]),
+        Cow::Owned(mut body) => {
+            body.drain(..start);
+            Cow::Owned(body)
+        }
+    };
+    Ok(RawPacket{ id, data, wire_len: frame.len(), uncompressed_len })
+}
+
+/// Builds a frame around `body` (packet id + data), the inverse of `decode_frame`.
+/// With compression on, bodies of at least `threshold` bytes are zlib-compressed.
+pub fn encode_frame(body: &[u8], compression: Option<usize>) -> Vec<u8>{
+    let mut payload = Vec::new();
+    match compression{
+        None => payload.extend_from_slice(body),
+        Some(threshold) if body.len() < threshold => {
+            write_var_int(&mut payload, 0);
+            payload.extend_from_slice(body);
+        }
+        Some(_) => {
+            write_var_int(&mut payload, body.len() as i32);
+            let mut encoder = ZlibEncoder::new(payload, Compression::default());
+            encoder.write_all(body).expect("writing to a Vec cannot fail");
+            payload = encoder.finish().expect("writing to a Vec cannot fail");
+        }
+    }
+    let mut frame = Vec::with_capacity(payload.len() + 5);
+    write_var_int(&mut frame, payload.len() as i32);
+    frame.extend_from_slice(&payload);
+    frame
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn var_int(value: i32) -> Vec<u8>{
+        let mut out = Vec::new();
+        write_var_int(&mut out, value);
+        out
+    }
+
+    fn kind_of(e: &Error) -> Option<&ProtocolError>{
+        e.get_ref().and_then(|inner| inner.downcast_ref::<ProtocolError>())
+    }
+
+    /// Runs `check` leniently, then strictly. Strict mode is process-wide, so tests that
+    /// switch it take turns.
+    fn in_both_modes(check: impl Fn(bool)){
+        static MODE: std::sync::Mutex<()> = std::sync::Mutex::new(());
+        let _turn = MODE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for strict in [false, true]{
+            set_strict(strict);
+            check(strict);
+        }
+        set_strict(false);
+    }
+
+    #[test]
+    fn frames_split_at_their_length(){
+        let mut buff = encode_frame(&[0x01, 0xaa], None);
+        buff.extend(encode_frame(&[0x02], None));
+        buff.extend(&var_int(10)[..]);
+        buff.push(0x03);
+        let (frames, consumed) = tokenize_to_packets(&buff).unwrap();
+        assert_eq!(frames, [&[2, 0x01, 0xaa][..], &[1, 0x02][..]]);
+        assert_eq!(consumed, 5);
+
+        // A prefix cut short waits for more; a full frame of the largest size is fine.
+        assert_eq!(tokenize_to_packets(&[0x80]).unwrap().1, 0);
+        assert_eq!(tokenize_to_packets(&var_int(MAX_FRAME_LEN as i32)).unwrap().1, 0);
+    }
+
+    #[test]
+    fn bad_frame_lengths_are_errors(){
+        let negative = tokenize_to_packets(&var_int(-1)).unwrap_err();
+        assert!(matches!(kind_of(&negative), Some(ProtocolError::NegativeLength(-1))));
+        let oversized = tokenize_to_packets(&var_int(MAX_FRAME_LEN as i32 + 1)).unwrap_err();
+        assert!(matches!(kind_of(&oversized), Some(ProtocolError::FrameTooLarge(len)) if *len == MAX_FRAME_LEN + 1));
+        // A VarInt running past five bytes.
+        assert!(tokenize_to_packets(&[0xff; 6]).is_err());
+
+        let mut frame = var_int(MAX_DATA_LEN as i32 + 1);
+        frame.extend([0x78, 0x9c]);
+        let mut framed = var_int(frame.len() as i32);
+        framed.extend(frame);
+        let inflated = decode_frame(&framed, Some(256)).err().unwrap();
+        assert!(matches!(kind_of(&inflated), Some(ProtocolError::TooLong{ max: MAX_DATA_LEN, .. })));
+        let negative = [6, 0xff, 0xff, 0xff, 0xff, 0x0f, 0x00];
+        assert!(matches!(kind_of(&decode_frame(&negative, Some(256)).err().unwrap()), Some(ProtocolError::NegativeLength(-1))));
+    }
+
+    #[test]
+    fn frames_round_trip(){
+        let body: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        for compression in [None, Some(64), Some(1024)]{
+            let frame = encode_frame(&body, compression);
+            let packet = decode_frame(&frame, compression).unwrap();
+            assert_eq!((packet.id, &packet.data[..], packet.wire_len), (0, &body[1..], frame.len()));
+        }
+    }
+
+    #[test]
+    fn strict_mode_enforces_the_spec_limits(){
+        // Compressed below a threshold of 256.
+        let mut payload = var_int(10);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[0; 10]).unwrap();
+        payload.extend(encoder.finish().unwrap());
+        let mut small = var_int(payload.len() as i32);
+        small.extend(payload);
+
+        in_both_modes(|strict| {
+            assert_eq!(check_length(300, 255).is_ok(), !strict);
+            assert!(check_limit(300, 255).is_err());
+            assert!(check_length(-1, 255).is_err() && check_limit(-1, 255).is_err());
+            assert_eq!(check_limit(255, 255).unwrap(), 255);
+            assert_eq!(decode_frame(&small, Some(256)).is_ok(), !strict);
+        });
+    }
+
+    #[test]
+    fn strings_are_limited_in_utf16_units(){
+        let string = |value: &str| {
+            let mut out = Vec::new();
+            write_string(&mut out, value);
+            Cursor::new(out)
         };
-        
+        in_both_modes(|strict| {
+            // Three units, two of them the surrogate pair of the emoji, in five bytes.
+            assert_eq!(read_string(&mut string("a😀"), 2).is_ok(), !strict);
+            assert_eq!(read_string(&mut string("a😀"), 3).unwrap(), "a😀");
+            // Six bytes, but only three units.
+            assert_eq!(read_string(&mut string("ééé"), 3).unwrap(), "ééé");
+            assert_eq!(read_string(&mut string("abcd"), 3).is_ok(), !strict);
+        });
     }
-    let mut i: usize = 0;
-    while i < result.len(){
-        if result[i].is_empty(){
-            result.remove(i);
-            continue;
+
+    #[test]
+    fn strings_must_be_utf8(){
+        let e = read_string(&mut Cursor::new([3, b'a', 0xc3, 0x28]), 16).unwrap_err();
+        assert!(matches!(kind_of(&e), Some(ProtocolError::InvalidUtf8(1))));
+        assert_eq!(read_string(&mut Cursor::new([2, 0xc3, 0xa9]), 16).unwrap(), "é");
+        assert_eq!(read_string(&mut Cursor::new([5, b'a']), 16).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn modified_utf8(){
+        assert_eq!(encode_modified_utf8("a\0"), [b'a', 0xc0, 0x80]);
+        // U+1F600 is the pair D83D DE00, each half three bytes.
+        assert_eq!(encode_modified_utf8("😀"), [0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80]);
+        for value in ["", "plain", "a\0b", "é€😀", "\u{7ff}\u{800}\u{ffff}\u{10000}\u{10ffff}"]{
+            assert_eq!(decode_modified_utf8(&encode_modified_utf8(value)).unwrap(), value);
+        }
+        assert_eq!(decode_modified_utf8(&[0xed, 0xa0, 0xbd]).unwrap(), "\u{fffd}");
+        for bad in [&[0xff][..], &[0x80], &[0xe0, 0x80], &[0xc0, 0x41]]{
+            assert!(decode_modified_utf8(bad).is_err(), "{:?}", bad);
         }
-        i = i+1 ;
     }
-    Ok(result)
-}   
 
+    #[test]
+    fn long_nbt_strings_are_cut_between_characters(){
+        let read = |value: &str| {
+            let mut out = Vec::new();
+            write_nbt_string(&mut out, value);
+            assert!(out.len() <= u16::MAX as usize + 2);
+            read_nbt_string(&mut Cursor::new(out)).unwrap()
+        };
+        assert_eq!(read("é"), "é");
+        // The cut lands in the low half of a pair, after the 3 bytes of the high half.
+        let paired = "a".repeat(65532) + "😀";
+        assert_eq!(read(&paired), "a".repeat(65532));
+        // ... or inside the high half.
+        let paired = "a".repeat(65533) + "😀";
+        assert_eq!(read(&paired), "a".repeat(65533));
+        let wide = "a".repeat(65534) + "€";
+        assert_eq!(read(&wide), "a".repeat(65534));
+        let exact = "a".repeat(65532) + "€";
+        assert_eq!(read(&exact), exact);
+    }
+}
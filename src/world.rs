@@ -0,0 +1,274 @@
+use crate::chunk::{self, BlockEntity, Chunk, ChunkSection, PalettedContainer, BLOCK_STATES, SECTION_SIZE};
+use crate::nbt::Tag;
+use crate::packets::server::{LoginPlayPacket, RespawnPacket, UpdateSectionBlocksPacket};
+use crate::utils::Position;
+use std::collections::HashMap;
+
+/// Vertical extent of the overworld, assumed until Login says otherwise.
+const DEFAULT_MIN_Y: i32 = -64;
+const DEFAULT_HEIGHT: i32 = 384;
+
+/// The part of the world a session's client has loaded, rebuilt from the packets the
+/// server sends it. Block ids are global block state ids.
+pub struct World {
+    pub dimension: String,
+    pub dimension_type: String,
+    pub min_y: i32,
+    pub height: i32,
+    /// Biome names by protocol id, from the Login registry codec.
+    pub biomes: Vec<String>,
+    /// `min_y` and `height` of each dimension type in the Login registry codec.
+    dimension_types: HashMap<String, (i32, i32)>,
+    chunks: HashMap<(i32, i32), Chunk>,
+}
+
+impl World {
+    pub fn new() -> World {
+        World {
+            dimension: String::new(),
+            dimension_type: String::new(),
+            min_y: DEFAULT_MIN_Y,
+            height: DEFAULT_HEIGHT,
+            biomes: Vec::new(),
+            dimension_types: HashMap::new(),
+            chunks: HashMap::new(),
+        }
+    }
+
+    pub fn login(&mut self, login: &LoginPlayPacket) {
+        self.dimension_types = login.registry_entries("minecraft:dimension_type").into_iter()
+            .filter_map(|(name, element)| match (element.get("min_y"), element.get("height")) {
+                (Some(Tag::Int(min_y)), Some(Tag::Int(height))) => Some((name.to_string(), (*min_y, *height))),
+                _ => None,
+            })
+            .collect();
+        // Ids run from 0 without gaps; others are ignored rather than trusted as indices.
+        let biomes = login.registry_ids("minecraft:worldgen/biome");
+        self.biomes = vec![String::new(); biomes.len()];
+        for (name, id) in biomes {
+            if let Some(slot) = usize::try_from(id).ok().and_then(|id| self.biomes.get_mut(id)) {
+                *slot = name.to_string();
+            }
+        }
+        self.enter(&login.dimension_name, &login.dimension_type);
+    }
+
+    /// A Respawn always comes with a fresh set of chunks, even into the same dimension.
+    pub fn respawn(&mut self, respawn: &RespawnPacket) {
+        self.enter(&respawn.dimension_name, &respawn.dimension_type);
+    }
+
+    fn enter(&mut self, dimension: &str, dimension_type: &str) {
+        let (min_y, height) = self.dimension_types.get(dimension_type).copied().unwrap_or((DEFAULT_MIN_Y, DEFAULT_HEIGHT));
+        self.dimension = dimension.to_string();
+        self.dimension_type = dimension_type.to_string();
+        self.min_y = min_y;
+        self.height = height;
+        self.chunks.clear();
+    }
+
+    pub fn load(&mut self, chunk: Chunk) {
+        self.chunks.insert((chunk.x, chunk.z), chunk);
+    }
+
+    pub fn unload(&mut self, x: i32, z: i32) {
+        self.chunks.remove(&(x, z));
+    }
+
+    pub fn chunk(&self, x: i32, z: i32) -> Option<&Chunk> {
+        self.chunks.get(&(x, z))
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks.values()
+    }
+
+    /// Index of the section holding block `y`, counted from the bottom of the world.
+    fn section_index(&self, y: i32) -> Option<usize> {
+        usize::try_from((y - self.min_y).div_euclid(SECTION_SIZE as i32)).ok()
+    }
+
+    pub fn block_at(&self, position: Position) -> Option<i32> {
+        let chunk = self.chunk(position.x >> 4, position.z >> 4)?;
+        chunk.block(position.x as usize & 15, self.section_index(position.y)?, position.y as usize & 15, position.z as usize & 15)
+    }
+
+    pub fn block_entity_at(&self, position: Position) -> Option<&BlockEntity> {
+        self.chunk(position.x >> 4, position.z >> 4)?.block_entities.iter()
+            .find(|entity| entity.x as i32 == position.x & 15 && entity.y as i32 == position.y && entity.z as i32 == position.z & 15)
+    }
+
+    /// Applies a Block Update. Returns false when the chunk is not loaded.
+    pub fn set_block(&mut self, position: Position, id: i32) -> bool {
+        let local = (position.x as u8 & 15, position.y.rem_euclid(16) as u8, position.z as u8 & 15, id);
+        self.update_section(position.x >> 4, position.y.div_euclid(16), position.z >> 4, &[local])
+    }
+
+    /// Applies Update Section Blocks. Returns false when the chunk is not loaded.
+    pub fn set_section_blocks(&mut self, update: &UpdateSectionBlocksPacket) -> bool {
+        let blocks: Vec<(u8, u8, u8, i32)> = update.blocks.iter().map(|&(id, x, y, z)| (x, y, z, id)).collect();
+        self.update_section(update.section_x, update.section_y, update.section_z, &blocks)
+    }
+
+    /// Sets `(x, y, z, id)` blocks within one section. Block entities at changed positions
+    /// are dropped; the server follows up with Block Entity Data when the new block has one.
+    fn update_section(&mut self, x: i32, section_y: i32, z: i32, blocks: &[(u8, u8, u8, i32)]) -> bool {
+        let Some(index) = self.section_index(section_y * SECTION_SIZE as i32) else { return false };
+        let Some(chunk) = self.chunks.get_mut(&(x, z)) else { return false };
+        let sections = (self.height as usize).div_ceil(SECTION_SIZE);
+        if index >= sections {
+            return false;
+        }
+        // A column sent with fewer sections than the world is tall is filled up with air.
+        while chunk.sections.len() <= index {
+            chunk.sections.push(ChunkSection { block_count: 0, block_states: PalettedContainer::single(0), biomes: PalettedContainer::single(0) });
+        }
+        let section = &mut chunk.sections[index];
+        // Ids the container already holds are written in place; the first one it does not
+        // unpacks the section, which is packed again once all blocks are set.
+        let mut states: Option<Vec<i32>> = None;
+        for &(bx, by, bz, id) in blocks {
+            let slot = chunk::block_index(bx as usize, by as usize, bz as usize);
+            let old = match &states {
+                Some(states) => states[slot],
+                None => section.block_states.get(slot).unwrap_or(0),
+            };
+            // Id 0 is air; cave and void air are counted as blocks, unlike on the server.
+            section.block_count += (id != 0) as i16 - (old != 0) as i16;
+            match &mut states {
+                Some(states) => states[slot] = id,
+                None if section.block_states.set(slot, id) => {}
+                None => {
+                    let mut entries = section.block_states.entries(BLOCK_STATES);
+                    entries[slot] = id;
+                    states = Some(entries);
+                }
+            }
+        }
+        if let Some(states) = states {
+            section.block_states = PalettedContainer::from_entries(&states, BLOCK_STATES);
+        }
+        let bottom = section_y * SECTION_SIZE as i32;
+        chunk.block_entities.retain(|entity| {
+            !blocks.iter().any(|&(bx, by, bz, _)| (entity.x, entity.y, entity.z) == (bx, (bottom + by as i32) as i16, bz))
+        });
+        true
+    }
+
+    /// Applies Block Entity Data. Returns false when the chunk is not loaded.
+    pub fn set_block_entity(&mut self, position: Position, kind: i32, data: Option<Tag>) -> bool {
+        let Some(chunk) = self.chunks.get_mut(&(position.x >> 4, position.z >> 4)) else { return false };
+        let entity = BlockEntity { x: (position.x & 15) as u8, z: (position.z & 15) as u8, y: position.y as i16, kind, data };
+        match chunk.block_entities.iter_mut().find(|existing| (existing.x, existing.y, existing.z) == (entity.x, entity.y, entity.z)) {
+            Some(existing) => *existing = entity,
+            None => chunk.block_entities.push(entity),
+        }
+        true
+    }
+
+    /// Biome id at a block position.
+    pub fn biome_at(&self, position: Position) -> Option<i32> {
+        let chunk = self.chunk(position.x >> 4, position.z >> 4)?;
+        let section = chunk.sections.get(self.section_index(position.y)?)?;
+        let cell = |value: i32| value.rem_euclid(SECTION_SIZE as i32) as usize / (SECTION_SIZE / chunk::BIOME_SIZE);
+        section.biomes.get(chunk::biome_index(cell(position.x), cell(position.y), cell(position.z)))
+    }
+
+    pub fn summary(&self) -> String {
+        format!("dimension:{} type:{} min_y:{} height:{} chunks:{}", self.dimension, self.dimension_type, self.min_y, self.height, self.chunks.len())
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{LightData, Palette};
+
+    /// A world with the column at `x`, `z` loaded with `sections` sections of stone, id 1.
+    fn world(x: i32, z: i32, sections: usize) -> World {
+        let mut world = World::new();
+        let stone = ChunkSection { block_count: 4096, block_states: PalettedContainer::single(1), biomes: PalettedContainer::single(0) };
+        world.load(Chunk { x, z, heightmaps: Default::default(), sections: vec![stone; sections], block_entities: Vec::new(), light: LightData::default() });
+        world
+    }
+
+    fn section(world: &World, x: i32, z: i32, index: usize) -> &ChunkSection {
+        &world.chunk(x, z).unwrap().sections[index]
+    }
+
+    #[test]
+    fn block_updates_keep_the_count() {
+        let mut world = world(0, 0, 4);
+        let position = Position { x: 3, y: -60, z: 5 };
+        assert!(world.set_block(position, 0));
+        assert_eq!(world.block_at(position), Some(0));
+        assert_eq!(world.block_at(Position { x: 4, y: -60, z: 5 }), Some(1));
+        assert_eq!(section(&world, 0, 0, 0).block_count, 4095);
+        // Air to air and stone to dirt change nothing and one block.
+        assert!(world.set_block(position, 0));
+        assert!(world.set_block(Position { x: 0, y: -64, z: 0 }, 10));
+        assert_eq!(section(&world, 0, 0, 0).block_count, 4095);
+        assert!(world.set_block(position, 10));
+        assert_eq!(section(&world, 0, 0, 0).block_count, 4096);
+        // Dirt was not in the palette: the section was packed again, in the order of its blocks.
+        assert_eq!(section(&world, 0, 0, 0).block_states.palette_ids(), Some(&[10, 1, 0][..]));
+
+        assert!(!world.set_block(Position { x: 16, y: 0, z: 0 }, 1));
+        assert!(!world.set_block(Position { x: 0, y: 320, z: 0 }, 1));
+        assert!(!world.set_block(Position { x: 0, y: -65, z: 0 }, 1));
+    }
+
+    #[test]
+    fn blocks_in_the_palette_are_set_in_place() {
+        let mut world = world(0, 0, 1);
+        world.set_block(Position { x: 0, y: -64, z: 0 }, 0);
+        let before = section(&world, 0, 0, 0).block_states.clone();
+        assert_eq!(before.palette, Palette::Indirect(vec![0, 1]));
+        world.set_block(Position { x: 0, y: -64, z: 0 }, 1);
+        world.set_block(Position { x: 15, y: -49, z: 15 }, 0);
+        // A packed section would be single again; the palette is only ever added to.
+        let after = &section(&world, 0, 0, 0).block_states;
+        assert_eq!((after.bits, &after.palette), (before.bits, &before.palette));
+        assert_eq!(after.get(0), Some(1));
+        assert_eq!(after.get(4095), Some(0));
+    }
+
+    #[test]
+    fn section_updates_pad_the_column_with_air() {
+        // The column at -1,-2 spans x -16 to -1 and z -32 to -17.
+        let mut world = world(-1, -2, 1);
+        world.set_block_entity(Position { x: -16, y: -32, z: -17 }, 2, None);
+        world.set_block_entity(Position { x: -16, y: -31, z: -17 }, 2, None);
+        // Section y -2 is the third from the bottom: blocks -32 to -17.
+        let update = UpdateSectionBlocksPacket {
+            section_x: -1, section_y: -2, section_z: -2, suppress_light_updates: false,
+            blocks: vec![(5, 0, 0, 15), (6, 15, 15, 0), (0, 1, 1, 1)],
+        };
+        assert!(world.set_section_blocks(&update));
+        let chunk = world.chunk(-1, -2).unwrap();
+        assert_eq!(chunk.sections.len(), 3);
+        assert_eq!((chunk.sections[1].block_count, &chunk.sections[1].block_states), (0, &PalettedContainer::single(0)));
+        assert_eq!(chunk.sections[2].block_count, 2);
+        assert_eq!(world.block_at(Position { x: -16, y: -32, z: -17 }), Some(5));
+        assert_eq!(world.block_at(Position { x: -1, y: -17, z: -32 }), Some(6));
+        assert_eq!(world.block_at(Position { x: -15, y: -31, z: -31 }), Some(0));
+        assert_eq!(world.block_at(Position { x: -15, y: -40, z: -31 }), Some(0));
+        // Only the block entity whose block changed is gone.
+        assert!(world.block_entity_at(Position { x: -16, y: -32, z: -17 }).is_none());
+        assert!(world.block_entity_at(Position { x: -16, y: -31, z: -17 }).is_some());
+
+        // Growing past the palette's 16 ids packs the section with wider entries.
+        let blocks = (0..20).map(|id| (100 + id, id as u8 % 16, 4 + id as u8 / 16, 0)).collect();
+        assert!(world.set_section_blocks(&UpdateSectionBlocksPacket { blocks, ..update }));
+        assert_eq!(section(&world, -1, -2, 2).block_states.bits, 5);
+        assert_eq!(section(&world, -1, -2, 2).block_count, 22);
+        assert_eq!(world.block_at(Position { x: -13, y: -27, z: -32 }), Some(119));
+    }
+}